@@ -0,0 +1,465 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Apache Arrow export of channel and frame data.  Feature: "arrow"
+// A batch has the index columns first (named from the IndexMetadataRecords), then for each channel its
+// value column and a column per value attribute ("<channel>.<attribute>").  Column types come from the
+// ChannelDataKind, and the uom is kept in the field metadata.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{channel_data_frame::Frame, error::Error, schema_gen::*};
+use arrow_array::{builder::*, ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// All the data of one channel, eg. collected from ChannelData messages.
+pub fn channel_batch(
+    metadata: &ChannelMetadataRecord,
+    items: &[DataItem],
+) -> Result<RecordBatch, Error> {
+    let mut indexes = IndexColumns::new(&metadata.indexes);
+    let mut channel = ChannelColumns::new(
+        &metadata.channel_name,
+        &metadata.data_kind,
+        &metadata.uom,
+        &metadata.attribute_metadata,
+    );
+
+    for item in items.iter().filter(|item| item.channel_id == metadata.id) {
+        indexes.append(&item.indexes)?;
+        channel.append(&item.value, &item.value_attributes)?;
+    }
+
+    let mut columns = indexes.finish();
+    columns.extend(channel.finish());
+    record_batch(columns)
+}
+
+// A batch per channel, keyed by channel id.  Items of channels without metadata are an error.
+pub fn channel_batches(
+    metadata: &[ChannelMetadataRecord],
+    items: &[DataItem],
+) -> Result<HashMap<i64, RecordBatch>, Error> {
+    if let Some(item) = items
+        .iter()
+        .find(|item| !metadata.iter().any(|m| m.id == item.channel_id))
+    {
+        return Err(Error::Simple(format!(
+            "No metadata for channel {}",
+            item.channel_id
+        )));
+    }
+
+    metadata
+        .iter()
+        .map(|m| Ok((m.id, channel_batch(m, items)?)))
+        .collect()
+}
+
+// A frame from ChannelDataFrame, one batch row per frame row.
+pub fn frame_batch(frame: &Frame) -> Result<RecordBatch, Error> {
+    let mut indexes = IndexColumns::new(&frame.index_metadata);
+    for row in 0..frame.row_count() {
+        let values: Vec<IndexValue> = frame
+            .indexes
+            .iter()
+            .map(|column| {
+                column
+                    .get(row)
+                    .cloned()
+                    .unwrap_or(IndexValue { item: None })
+            })
+            .collect();
+        indexes.append(&values)?;
+    }
+    let mut columns = indexes.finish();
+
+    for column in &frame.columns {
+        let mut channel = match &column.metadata {
+            Some(metadata) => ChannelColumns::new(
+                &metadata.channel_name,
+                &metadata.data_kind,
+                &metadata.uom,
+                &metadata.attribute_metadata,
+            ),
+            // Without metadata the type is taken from the values.
+            None => ChannelColumns::new(
+                &column.uri,
+                &column
+                    .values
+                    .iter()
+                    .find_map(|value| data_kind(&value.item))
+                    .unwrap_or(ChannelDataKind::TypeDouble),
+                "",
+                &[],
+            ),
+        };
+        for (row, value) in column.values.iter().enumerate() {
+            let attributes = column
+                .value_attributes
+                .get(row)
+                .map(|a| a.as_slice())
+                .unwrap_or_default();
+            channel.append(value, attributes)?;
+        }
+        columns.extend(channel.finish());
+    }
+    record_batch(columns)
+}
+
+// FrameRows as read from GetFrameResponseRows, with the header and channel metadata of the frame.
+pub fn frame_rows_batch(
+    header: GetFrameResponseHeader,
+    metadata: &[FrameChannelMetadataRecord],
+    rows: Vec<FrameRow>,
+) -> Result<RecordBatch, Error> {
+    let mut frame = Frame::new(header, metadata);
+    for row in rows {
+        frame.append_row(row);
+    }
+    frame_batch(&frame)
+}
+
+fn record_batch(columns: Vec<(Field, ArrayRef)>) -> Result<RecordBatch, Error> {
+    let (fields, arrays): (Vec<Field>, Vec<ArrayRef>) = columns.into_iter().unzip();
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+        .map_err(|err| Error::Simple(format!("Arrow error: {}", err)))
+}
+
+fn field(name: &str, data_type: DataType, uom: &str) -> Field {
+    let field = Field::new(name, data_type, true);
+    if uom.is_empty() {
+        field
+    } else {
+        field.with_metadata(HashMap::from([("uom".to_string(), uom.to_string())]))
+    }
+}
+
+fn data_kind(value: &DataValueEnum) -> Option<ChannelDataKind> {
+    match value {
+        DataValueEnum::Boolean(_) => Some(ChannelDataKind::TypeBoolean),
+        DataValueEnum::Int(_) => Some(ChannelDataKind::TypeInt),
+        DataValueEnum::Long(_) => Some(ChannelDataKind::TypeLong),
+        DataValueEnum::Float(_) => Some(ChannelDataKind::TypeFloat),
+        DataValueEnum::Double(_) => Some(ChannelDataKind::TypeDouble),
+        DataValueEnum::String(_) => Some(ChannelDataKind::TypeString),
+        DataValueEnum::Bytes(_) => Some(ChannelDataKind::TypeBytes),
+        _ => None,
+    }
+}
+
+// A column builder for values of one ChannelDataKind.
+enum ValueBuilder {
+    Timestamp(TimestampMicrosecondBuilder),
+    Boolean(BooleanBuilder),
+    Int32(Int32Builder),
+    Int64(Int64Builder),
+    Float32(Float32Builder),
+    Float64(Float64Builder),
+    Utf8(StringBuilder),
+    Binary(BinaryBuilder),
+}
+
+impl ValueBuilder {
+    fn new(kind: &ChannelDataKind) -> ValueBuilder {
+        match kind {
+            ChannelDataKind::DateTime => {
+                ValueBuilder::Timestamp(TimestampMicrosecondBuilder::new().with_timezone("UTC"))
+            }
+            ChannelDataKind::ElapsedTime | ChannelDataKind::TypeLong => {
+                ValueBuilder::Int64(Int64Builder::new())
+            }
+            ChannelDataKind::MeasuredDepth
+            | ChannelDataKind::PassIndexedDepth
+            | ChannelDataKind::TrueVerticalDepth
+            | ChannelDataKind::TypeDouble => ValueBuilder::Float64(Float64Builder::new()),
+            ChannelDataKind::TypeBoolean => ValueBuilder::Boolean(BooleanBuilder::new()),
+            ChannelDataKind::TypeInt => ValueBuilder::Int32(Int32Builder::new()),
+            ChannelDataKind::TypeFloat => ValueBuilder::Float32(Float32Builder::new()),
+            ChannelDataKind::TypeString => ValueBuilder::Utf8(StringBuilder::new()),
+            ChannelDataKind::TypeBytes => ValueBuilder::Binary(BinaryBuilder::new()),
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match self {
+            ValueBuilder::Timestamp(_) => {
+                DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+            }
+            ValueBuilder::Boolean(_) => DataType::Boolean,
+            ValueBuilder::Int32(_) => DataType::Int32,
+            ValueBuilder::Int64(_) => DataType::Int64,
+            ValueBuilder::Float32(_) => DataType::Float32,
+            ValueBuilder::Float64(_) => DataType::Float64,
+            ValueBuilder::Utf8(_) => DataType::Utf8,
+            ValueBuilder::Binary(_) => DataType::Binary,
+        }
+    }
+
+    // Values are widened to the column type where that is lossless (see DataValue conversions).
+    fn append(&mut self, value: &DataValue) -> Result<(), Error> {
+        match self {
+            ValueBuilder::Timestamp(b) => b.append_option(value.get::<i64>()?),
+            ValueBuilder::Boolean(b) => b.append_option(value.get::<bool>()?),
+            ValueBuilder::Int32(b) => b.append_option(value.get::<i32>()?),
+            ValueBuilder::Int64(b) => b.append_option(value.get::<i64>()?),
+            ValueBuilder::Float32(b) => b.append_option(value.get::<f32>()?),
+            ValueBuilder::Float64(b) => b.append_option(value.get::<f64>()?),
+            ValueBuilder::Utf8(b) => b.append_option(value.get::<String>()?),
+            ValueBuilder::Binary(b) => b.append_option(value.get::<Vec<u8>>()?),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            ValueBuilder::Timestamp(b) => Arc::new(b.finish()),
+            ValueBuilder::Boolean(b) => Arc::new(b.finish()),
+            ValueBuilder::Int32(b) => Arc::new(b.finish()),
+            ValueBuilder::Int64(b) => Arc::new(b.finish()),
+            ValueBuilder::Float32(b) => Arc::new(b.finish()),
+            ValueBuilder::Float64(b) => Arc::new(b.finish()),
+            ValueBuilder::Utf8(b) => Arc::new(b.finish()),
+            ValueBuilder::Binary(b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+// The value column of a channel, and a column per attribute.
+struct ChannelColumns {
+    value: (String, String, ValueBuilder), // Name, uom, builder
+    attributes: Vec<(i32, String, String, ValueBuilder)>,
+}
+
+impl ChannelColumns {
+    fn new(
+        name: &str,
+        kind: &ChannelDataKind,
+        uom: &str,
+        attributes: &[AttributeMetadataRecord],
+    ) -> ChannelColumns {
+        ChannelColumns {
+            value: (name.to_string(), uom.to_string(), ValueBuilder::new(kind)),
+            attributes: attributes
+                .iter()
+                .map(|a| {
+                    (
+                        a.attribute_id,
+                        format!("{}.{}", name, a.attribute_name),
+                        a.uom.clone(),
+                        ValueBuilder::new(&a.data_kind),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn append(&mut self, value: &DataValue, attributes: &[DataAttribute]) -> Result<(), Error> {
+        let null = DataValue::null();
+        self.value
+            .2
+            .append(value)
+            .map_err(|err| column_error(&self.value.0, err))?;
+        for (id, name, _, builder) in self.attributes.iter_mut() {
+            let value = attributes
+                .iter()
+                .find(|a| a.attribute_id == *id)
+                .map(|a| &a.attribute_value)
+                .unwrap_or(&null);
+            builder
+                .append(value)
+                .map_err(|err| column_error(name, err))?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<(Field, ArrayRef)> {
+        let (name, uom, builder) = &mut self.value;
+        let mut columns = vec![(field(name, builder.data_type(), uom), builder.finish())];
+        for (_, name, uom, builder) in self.attributes.iter_mut() {
+            columns.push((field(name, builder.data_type(), uom), builder.finish()));
+        }
+        columns
+    }
+}
+
+// The index columns.  A pass indexed depth has its pass number in an extra "<name>.pass" column.
+struct IndexColumns {
+    columns: Vec<(String, String, ValueBuilder, Option<Int64Builder>)>, // Name, uom, builder, pass
+}
+
+impl IndexColumns {
+    fn new(indexes: &[IndexMetadataRecord]) -> IndexColumns {
+        IndexColumns {
+            columns: indexes
+                .iter()
+                .enumerate()
+                .map(|(n, index)| {
+                    let name = if index.name.is_empty() {
+                        format!("index{}", n)
+                    } else {
+                        index.name.clone()
+                    };
+                    let (kind, pass) = match index.index_kind {
+                        ChannelIndexKind::DateTime => (ChannelDataKind::DateTime, None),
+                        ChannelIndexKind::ElapsedTime => (ChannelDataKind::ElapsedTime, None),
+                        ChannelIndexKind::PassIndexedDepth => {
+                            (ChannelDataKind::PassIndexedDepth, Some(Int64Builder::new()))
+                        }
+                        _ => (ChannelDataKind::TypeDouble, None),
+                    };
+                    (name, index.uom.clone(), ValueBuilder::new(&kind), pass)
+                })
+                .collect(),
+        }
+    }
+
+    fn append(&mut self, values: &[IndexValue]) -> Result<(), Error> {
+        for (n, (name, _, builder, pass)) in self.columns.iter_mut().enumerate() {
+            let (value, pass_number) = match values.get(n).and_then(|v| v.item.as_ref()) {
+                None => (DataValue::null(), None),
+                Some(UnionLongDoublePassIndexedDepth::Long(v)) => match builder {
+                    ValueBuilder::Float64(_) => (DataValue::from(*v as f64), None),
+                    _ => (DataValue::from(*v), None),
+                },
+                Some(UnionLongDoublePassIndexedDepth::Double(v)) => (DataValue::from(*v), None),
+                Some(UnionLongDoublePassIndexedDepth::PassIndexedDepth(v)) => {
+                    (DataValue::from(v.depth), Some(v.pass))
+                }
+            };
+            builder
+                .append(&value)
+                .map_err(|err| column_error(name, err))?;
+            if let Some(pass) = pass {
+                pass.append_option(pass_number);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<(Field, ArrayRef)> {
+        let mut columns = Vec::new();
+        for (name, uom, builder, pass) in self.columns.iter_mut() {
+            columns.push((field(name, builder.data_type(), uom), builder.finish()));
+            if let Some(pass) = pass {
+                columns.push((
+                    field(&format!("{}.pass", name), DataType::Int64, ""),
+                    Arc::new(pass.finish()),
+                ));
+            }
+        }
+        columns
+    }
+}
+
+fn column_error(column: &str, err: Error) -> Error {
+    Error::Simple(format!("Column {}: {}", column, err))
+}
+
+#[test]
+fn test_channel_batch() {
+    use arrow_array::{cast::AsArray, types::*, Array};
+
+    let metadata = ChannelMetadataRecord {
+        uri: "eml:///witsml20.Channel(f8fd0d43-8a3b-4c5c-8e0f-4e7a7c0f8a31)".to_string(),
+        id: 7,
+        indexes: vec![IndexMetadataRecord {
+            index_kind: ChannelIndexKind::DateTime,
+            interval: IndexInterval {
+                start_index: IndexValue { item: None },
+                end_index: IndexValue { item: None },
+                uom: "".to_string(),
+                depth_datum: "".to_string(),
+            },
+            direction: IndexDirection::Increasing,
+            name: "TIME".to_string(),
+            uom: "".to_string(),
+            depth_datum: "".to_string(),
+            index_property_kind_uri: "".to_string(),
+            filterable: true,
+        }],
+        channel_name: "ROP".to_string(),
+        data_kind: ChannelDataKind::TypeDouble,
+        uom: "m/h".to_string(),
+        depth_datum: "".to_string(),
+        channel_class_uri: "".to_string(),
+        status: ActiveStatusKind::Active,
+        source: "".to_string(),
+        axis_vector_lengths: vec![],
+        attribute_metadata: vec![AttributeMetadataRecord {
+            attribute_id: 1,
+            attribute_name: "quality".to_string(),
+            data_kind: ChannelDataKind::TypeInt,
+            uom: "".to_string(),
+            depth_datum: "".to_string(),
+            attribute_property_kind_uri: "".to_string(),
+            axis_vector_lengths: vec![],
+        }],
+        custom_data: HashMap::new(),
+    };
+    let item = |time: i64, value: DataValue, quality: Option<i32>| DataItem {
+        channel_id: 7,
+        indexes: vec![IndexValue {
+            item: Some(UnionLongDoublePassIndexedDepth::Long(time)),
+        }],
+        value,
+        value_attributes: quality
+            .map(|q| DataAttribute {
+                attribute_id: 1,
+                attribute_value: DataValue::from(q),
+            })
+            .into_iter()
+            .collect(),
+    };
+
+    let items = vec![
+        item(1_000_000, DataValue::from(12.5), Some(3)),
+        item(2_000_000, DataValue::null(), None),
+        item(3_000_000, DataValue::from(7), None), // Int widened to double
+    ];
+    let batch = channel_batch(&metadata, &items).unwrap();
+
+    let schema = batch.schema();
+    let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+    assert_eq!(names, vec!["TIME", "ROP", "ROP.quality"]);
+    assert_eq!(
+        schema.field(1).metadata().get("uom"),
+        Some(&"m/h".to_string())
+    );
+
+    let time = batch.column(0).as_primitive::<TimestampMicrosecondType>();
+    assert_eq!(time.value(2), 3_000_000);
+    let rop = batch.column(1).as_primitive::<Float64Type>();
+    assert_eq!(rop.value(0), 12.5);
+    assert!(rop.is_null(1));
+    assert_eq!(rop.value(2), 7.0);
+    let quality = batch.column(2).as_primitive::<Int32Type>();
+    assert_eq!(quality.value(0), 3);
+    assert!(quality.is_null(2));
+
+    // A string cannot go in a double column
+    let bad = vec![item(4_000_000, DataValue::from("x"), None)];
+    assert!(channel_batch(&metadata, &bad).is_err());
+    assert!(channel_batches(&[], &items).is_err());
+}
@@ -0,0 +1,428 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Avro JSON encoding  (Avro 1.11 Specification, JSON Encoding), used by the ETP JSON encoding.
+//   null, boolean, numbers, strings, arrays and maps      as themselves; records as objects
+//   bytes / fixed                                          strings, one character per byte (U+0000 - U+00FF)
+//   enums                                                  the symbol
+//   unions                                                 null, or {"<branch type>": value}, eg. {"long": 5}
+// Values are matched against the schema, so unions and enums decode to the same Values as the binary encoding.
+// render is the same, for reading rather than decoding: Uuids are hyphenated, and bytes in base64 or hex.
+// ------------------------------------------------------------------------------------------------------------
+
+use apache_avro::{types::Value, AvroResult, Error, Schema};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{Map, Number, Value as JsonValue};
+use std::collections::HashMap;
+
+// Named types (records, enums, fixed) by full name, to resolve references.
+pub type SchemaNames = HashMap<String, Schema>;
+
+pub fn collect_names(schema: &Schema, names: &mut SchemaNames) {
+    match schema {
+        Schema::Record { name, fields, .. } => {
+            names.insert(name.fullname(None), schema.clone());
+            for field in fields {
+                collect_names(&field.schema, names);
+            }
+        }
+        Schema::Enum { name, .. } | Schema::Fixed { name, .. } => {
+            names.insert(name.fullname(None), schema.clone());
+        }
+        Schema::Array(items) | Schema::Map(items) => collect_names(items, names),
+        Schema::Union(union) => {
+            for variant in union.variants() {
+                collect_names(variant, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+// The name of a union branch: the primitive type, "array" / "map", or the full name of a named type.
+pub fn type_name(schema: &Schema) -> String {
+    match schema {
+        Schema::Null => "null".to_string(),
+        Schema::Boolean => "boolean".to_string(),
+        Schema::Int => "int".to_string(),
+        Schema::Long => "long".to_string(),
+        Schema::Float => "float".to_string(),
+        Schema::Double => "double".to_string(),
+        Schema::Bytes => "bytes".to_string(),
+        Schema::String => "string".to_string(),
+        Schema::Array(_) => "array".to_string(),
+        Schema::Map(_) => "map".to_string(),
+        Schema::Record { name, .. }
+        | Schema::Enum { name, .. }
+        | Schema::Fixed { name, .. }
+        | Schema::Ref { name } => name.fullname(None),
+        _ => "unknown".to_string(),
+    }
+}
+
+// How render shows bytes (other than Uuids).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BytesFormat {
+    Base64,
+    Hex(usize), // At most this many bytes, then the length
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RenderOptions {
+    pub bytes: BytesFormat,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            bytes: BytesFormat::Base64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Style {
+    Avro,
+    Render(RenderOptions),
+}
+
+pub fn resolve<'a>(schema: &'a Schema, names: &'a SchemaNames) -> AvroResult<&'a Schema> {
+    match schema {
+        Schema::Ref { name } => names
+            .get(&name.fullname(None))
+            .ok_or_else(|| invalid(format!("Unknown type {}", name.fullname(None)))),
+        _ => Ok(schema),
+    }
+}
+
+pub fn to_json(value: &Value, schema: &Schema, names: &SchemaNames) -> AvroResult<JsonValue> {
+    encode(value, schema, names, Style::Avro)
+}
+
+// Readable JSON for logs.  Like to_json, NaN and infinities are shown as strings, so any value matching
+// the schema renders.
+pub fn render(
+    value: &Value,
+    schema: &Schema,
+    names: &SchemaNames,
+    options: RenderOptions,
+) -> AvroResult<JsonValue> {
+    encode(value, schema, names, Style::Render(options))
+}
+
+fn encode(
+    value: &Value,
+    schema: &Schema,
+    names: &SchemaNames,
+    style: Style,
+) -> AvroResult<JsonValue> {
+    let schema = resolve(schema, names)?;
+    Ok(match (schema, value) {
+        (Schema::Null, Value::Null) => JsonValue::Null,
+        (Schema::Boolean, Value::Boolean(b)) => JsonValue::Bool(*b),
+        (Schema::Int, Value::Int(i)) => JsonValue::from(*i),
+        (Schema::Long, Value::Long(i)) => JsonValue::from(*i),
+        (Schema::Long, Value::Int(i)) => JsonValue::from(*i as i64),
+        (Schema::Float, Value::Float(f)) => float(*f as f64),
+        (Schema::Double, Value::Double(f)) => float(*f),
+        (Schema::Double, Value::Float(f)) => float(*f as f64),
+        (Schema::Bytes | Schema::Fixed { .. }, Value::Bytes(bytes) | Value::Fixed(_, bytes)) => {
+            match style {
+                Style::Avro => JsonValue::String(bytes.iter().map(|b| *b as char).collect()),
+                Style::Render(options) => JsonValue::String(render_bytes(schema, bytes, options)),
+            }
+        }
+        (Schema::String, Value::String(s)) => JsonValue::String(s.clone()),
+        (Schema::Enum { .. }, Value::Enum(_, symbol) | Value::String(symbol)) => {
+            JsonValue::String(symbol.clone())
+        }
+        (Schema::Array(items), Value::Array(values)) => JsonValue::Array(
+            values
+                .iter()
+                .map(|v| encode(v, items, names, style))
+                .collect::<AvroResult<_>>()?,
+        ),
+        (Schema::Map(items), Value::Map(values)) => JsonValue::Object(
+            values
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), encode(v, items, names, style)?)))
+                .collect::<AvroResult<Map<_, _>>>()?,
+        ),
+        (Schema::Record { fields, .. }, Value::Record(values)) => {
+            let mut object = Map::new();
+            for field in fields {
+                let value = values
+                    .iter()
+                    .find(|(name, _)| *name == field.name)
+                    .map(|(_, v)| v)
+                    .ok_or_else(|| invalid(format!("Missing field {}", field.name)))?;
+                object.insert(
+                    field.name.clone(),
+                    encode(value, &field.schema, names, style)?,
+                );
+            }
+            JsonValue::Object(object)
+        }
+        (Schema::Union(union), Value::Union(index, value)) => {
+            let branch = union
+                .variants()
+                .get(*index as usize)
+                .ok_or_else(|| invalid(format!("No union branch {}", index)))?;
+            match resolve(branch, names)? {
+                Schema::Null => JsonValue::Null,
+                _ => {
+                    let mut object = Map::new();
+                    object.insert(type_name(branch), encode(value, branch, names, style)?);
+                    JsonValue::Object(object)
+                }
+            }
+        }
+        _ => {
+            return Err(invalid(format!(
+                "{:?} does not match {}",
+                value,
+                type_name(schema)
+            )))
+        }
+    })
+}
+
+pub fn from_json(json: &JsonValue, schema: &Schema, names: &SchemaNames) -> AvroResult<Value> {
+    let schema = resolve(schema, names)?;
+    let mismatch = || invalid(format!("{} does not match {}", json, type_name(schema)));
+    Ok(match (schema, json) {
+        (Schema::Null, JsonValue::Null) => Value::Null,
+        (Schema::Boolean, JsonValue::Bool(b)) => Value::Boolean(*b),
+        (Schema::Int, JsonValue::Number(n)) => Value::Int(
+            n.as_i64()
+                .and_then(|i| i32::try_from(i).ok())
+                .ok_or_else(mismatch)?,
+        ),
+        (Schema::Long, JsonValue::Number(n)) => Value::Long(n.as_i64().ok_or_else(mismatch)?),
+        (Schema::Float, JsonValue::Number(n)) => {
+            Value::Float(n.as_f64().ok_or_else(mismatch)? as f32)
+        }
+        (Schema::Double, JsonValue::Number(n)) => Value::Double(n.as_f64().ok_or_else(mismatch)?),
+        (Schema::Float, JsonValue::String(s)) => {
+            Value::Float(non_finite(s).ok_or_else(mismatch)? as f32)
+        }
+        (Schema::Double, JsonValue::String(s)) => {
+            Value::Double(non_finite(s).ok_or_else(mismatch)?)
+        }
+        (Schema::Bytes, JsonValue::String(s)) => Value::Bytes(bytes(s).ok_or_else(mismatch)?),
+        (Schema::Fixed { size, .. }, JsonValue::String(s)) => match bytes(s) {
+            Some(bytes) if bytes.len() == *size => Value::Fixed(*size, bytes),
+            _ => return Err(mismatch()),
+        },
+        (Schema::String, JsonValue::String(s)) => Value::String(s.clone()),
+        (Schema::Enum { symbols, .. }, JsonValue::String(s)) => {
+            let index = symbols
+                .iter()
+                .position(|sym| sym == s)
+                .ok_or_else(mismatch)?;
+            Value::Enum(index as u32, s.clone())
+        }
+        (Schema::Array(items), JsonValue::Array(values)) => Value::Array(
+            values
+                .iter()
+                .map(|v| from_json(v, items, names))
+                .collect::<AvroResult<_>>()?,
+        ),
+        (Schema::Map(items), JsonValue::Object(values)) => Value::Map(
+            values
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), from_json(v, items, names)?)))
+                .collect::<AvroResult<_>>()?,
+        ),
+        (Schema::Record { fields, .. }, JsonValue::Object(object)) => Value::Record(
+            fields
+                .iter()
+                .map(|field| {
+                    let value = match (object.get(&field.name), &field.default) {
+                        (Some(value), _) => from_json(value, &field.schema, names)?,
+                        (None, Some(default)) => from_default(default, &field.schema, names)?,
+                        (None, None) => {
+                            return Err(invalid(format!("Missing field {}", field.name)))
+                        }
+                    };
+                    Ok((field.name.clone(), value))
+                })
+                .collect::<AvroResult<_>>()?,
+        ),
+        (Schema::Union(union), JsonValue::Null) => {
+            let index = union
+                .variants()
+                .iter()
+                .position(|v| matches!(v, Schema::Null))
+                .ok_or_else(mismatch)?;
+            Value::Union(index as u32, Box::new(Value::Null))
+        }
+        (Schema::Union(union), JsonValue::Object(object)) if object.len() == 1 => {
+            let (name, value) = object.iter().next().ok_or_else(mismatch)?;
+            // Named types may be given by their short name.
+            let index = union
+                .variants()
+                .iter()
+                .position(|v| {
+                    let full = type_name(v);
+                    full == *name || full.rsplit('.').next() == Some(name.as_str())
+                })
+                .ok_or_else(mismatch)?;
+            Value::Union(
+                index as u32,
+                Box::new(from_json(value, &union.variants()[index], names)?),
+            )
+        }
+        _ => return Err(mismatch()),
+    })
+}
+
+// Field defaults of a union are for the first branch, and are not wrapped.
+fn from_default(default: &JsonValue, schema: &Schema, names: &SchemaNames) -> AvroResult<Value> {
+    match resolve(schema, names)? {
+        Schema::Union(union) => {
+            let first = union
+                .variants()
+                .first()
+                .ok_or_else(|| invalid("Empty union".to_string()))?;
+            Ok(Value::Union(0, Box::new(from_json(default, first, names)?)))
+        }
+        schema => from_json(default, schema, names),
+    }
+}
+
+fn render_bytes(schema: &Schema, bytes: &[u8], options: RenderOptions) -> String {
+    if let Schema::Fixed { name, size: 16, .. } = schema {
+        if name.name == "Uuid" {
+            if let Ok(uuid) = uuid::Uuid::from_slice(bytes) {
+                return uuid.hyphenated().to_string();
+            }
+        }
+    }
+    match options.bytes {
+        BytesFormat::Base64 => STANDARD.encode(bytes),
+        BytesFormat::Hex(max) => {
+            let hex: String = bytes
+                .iter()
+                .take(max)
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            if bytes.len() > max {
+                format!("{}... ({} bytes)", hex, bytes.len())
+            } else {
+                hex
+            }
+        }
+    }
+}
+
+// JSON has no NaN or infinity, so those are written as the strings Java and Jackson use.
+fn float(f: f64) -> JsonValue {
+    match Number::from_f64(f) {
+        Some(n) => JsonValue::Number(n),
+        None if f.is_nan() => JsonValue::String("NaN".to_string()),
+        None if f > 0.0 => JsonValue::String("Infinity".to_string()),
+        None => JsonValue::String("-Infinity".to_string()),
+    }
+}
+
+fn non_finite(s: &str) -> Option<f64> {
+    match s {
+        "NaN" => Some(f64::NAN),
+        "Infinity" => Some(f64::INFINITY),
+        "-Infinity" => Some(f64::NEG_INFINITY),
+        _ => None,
+    }
+}
+
+fn bytes(s: &str) -> Option<Vec<u8>> {
+    s.chars().map(|c| u8::try_from(c as u32).ok()).collect()
+}
+
+fn invalid(reason: String) -> Error {
+    Error::ValidationWithReason(reason)
+}
+
+#[test]
+fn test_json_bytes() {
+    let names = SchemaNames::new();
+    let schema = Schema::Array(Box::new(Schema::Bytes));
+    let value = Value::Array(vec![Value::Bytes(vec![0x00, 0x7f, 0xff])]);
+
+    let json = to_json(&value, &schema, &names).unwrap();
+    assert_eq!(json, serde_json::json!(["\u{0}\u{7f}\u{ff}"]));
+    assert_eq!(from_json(&json, &schema, &names).unwrap(), value);
+
+    let options = |bytes| RenderOptions { bytes };
+    assert_eq!(
+        render(&value, &schema, &names, options(BytesFormat::Base64)).unwrap(),
+        serde_json::json!(["AH//"])
+    );
+    assert_eq!(
+        render(&value, &schema, &names, options(BytesFormat::Hex(2))).unwrap(),
+        serde_json::json!(["007f... (3 bytes)"])
+    );
+}
+
+#[test]
+fn test_json_non_finite() {
+    let names = SchemaNames::new();
+    let schema = Schema::Array(Box::new(Schema::Double));
+    let value = Value::Array(vec![
+        Value::Double(1.5),
+        Value::Double(f64::INFINITY),
+        Value::Double(f64::NEG_INFINITY),
+    ]);
+
+    let json = to_json(&value, &schema, &names).unwrap();
+    assert_eq!(json, serde_json::json!([1.5, "Infinity", "-Infinity"]));
+    assert_eq!(from_json(&json, &schema, &names).unwrap(), value);
+
+    let json = to_json(&Value::Float(f32::NAN), &Schema::Float, &names).unwrap();
+    assert_eq!(json, serde_json::json!("NaN"));
+    assert!(matches!(
+        from_json(&json, &Schema::Float, &names).unwrap(),
+        Value::Float(f) if f.is_nan()
+    ));
+    assert!(from_json(&serde_json::json!("nan"), &Schema::Double, &names).is_err());
+}
+
+#[test]
+fn test_render_non_finite() {
+    let names = SchemaNames::new();
+    let schema = Schema::Map(Box::new(Schema::Float));
+    let value = Value::Map(
+        [
+            ("nan".to_string(), Value::Float(f32::NAN)),
+            ("inf".to_string(), Value::Float(f32::INFINITY)),
+            ("zero".to_string(), Value::Float(0.0)),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    assert_eq!(
+        render(&value, &schema, &names, RenderOptions::default()).unwrap(),
+        serde_json::json!({"nan": "NaN", "inf": "Infinity", "zero": 0.0})
+    );
+}
@@ -0,0 +1,103 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// etp-render: prints ETP messages as readable JSON (header and body).
+//   etp-render [--hex[=N]] [FILE...]
+// Each file holds one WebSocket message as sent: Avro binary (possibly compressed), or the JSON encoding
+// ([header, body]).  With no files, one message is read from stdin.  Bytes are shown in base64, or with
+// --hex as hex truncated to N bytes (default 32).
+// ------------------------------------------------------------------------------------------------------------
+
+use etp_rs::avro_json::{BytesFormat, RenderOptions};
+use etp_rs::schema::MsgSchema;
+use std::io::Read;
+use std::process::ExitCode;
+
+const DEFAULT_HEX_BYTES: usize = 32;
+
+fn main() -> ExitCode {
+    let mut options = RenderOptions::default();
+    let mut files = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--hex" => options.bytes = BytesFormat::Hex(DEFAULT_HEX_BYTES),
+            "-h" | "--help" => {
+                println!("Usage: etp-render [--hex[=N]] [FILE...]");
+                return ExitCode::SUCCESS;
+            }
+            _ => match arg.strip_prefix("--hex=") {
+                Some(count) => match count.parse() {
+                    Ok(count) => options.bytes = BytesFormat::Hex(count),
+                    Err(_) => {
+                        eprintln!("Invalid byte count {}", count);
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => files.push(arg),
+            },
+        }
+    }
+
+    let schema = MsgSchema::new();
+    let mut status = ExitCode::SUCCESS;
+    if files.is_empty() {
+        let mut msg = vec![];
+        if let Err(err) = std::io::stdin().read_to_end(&mut msg) {
+            eprintln!("stdin: {}", err);
+            return ExitCode::FAILURE;
+        }
+        if let Err(err) = print_message(&schema, &msg, options) {
+            eprintln!("stdin: {}", err);
+            status = ExitCode::FAILURE;
+        }
+    }
+    for file in &files {
+        if let Err(err) = std::fs::read(file)
+            .map_err(|err| err.to_string())
+            .and_then(|msg| print_message(&schema, &msg, options))
+        {
+            eprintln!("{}: {}", file, err);
+            status = ExitCode::FAILURE;
+        }
+    }
+    status
+}
+
+fn print_message(schema: &MsgSchema, msg: &[u8], options: RenderOptions) -> Result<(), String> {
+    // JSON messages are an array; a binary header starts with the protocol number, never '['.
+    let text = std::str::from_utf8(msg).ok().map(|text| text.trim_start());
+    let (header, body) = match text {
+        Some(text) if text.starts_with('[') => schema.decode_message_json(text),
+        _ => schema.decode_message(msg),
+    }
+    .map_err(|err| err.to_string())?;
+
+    let rendered = schema
+        .render_message(&header, &body, options)
+        .map_err(|err| err.to_string())?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&rendered).map_err(|err| err.to_string())?
+    );
+    Ok(())
+}
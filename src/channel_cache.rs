@@ -0,0 +1,452 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Local channel cache.
+// Keeps the data of subscribed channels, by primary index, applying ChannelSubscribe events in the order
+// they arrive: new data, replaced ranges and truncations.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    channel_index::{ChannelIndex, ChannelIndexInterval},
+    channel_subscribe::{ChannelEvent, ChannelSample},
+    error::Error,
+    schema_gen::*,
+};
+#[allow(unused_imports)]
+use log::{info, trace, warn};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone)]
+struct CachedChannel {
+    metadata: ChannelMetadataRecord,
+    samples: BTreeMap<ChannelIndex, ChannelSample>, // By primary index, in the channel direction
+}
+
+impl CachedChannel {
+    // The interval in the direction of the channel, start <= end, whichever way round it was given.
+    fn in_direction(&self, interval: &ChannelIndexInterval) -> ChannelIndexInterval {
+        let Some(index) = self.metadata.indexes.first() else {
+            return ChannelIndexInterval::new(interval.start.clone(), interval.end.clone());
+        };
+        let in_direction = |value: &ChannelIndex| ChannelIndex {
+            direction: index.direction.clone(),
+            ..value.clone()
+        };
+        ChannelIndexInterval::new(in_direction(&interval.start), in_direction(&interval.end))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ChannelCache {
+    channels: HashMap<i64, CachedChannel>,
+}
+
+impl ChannelCache {
+    pub fn new() -> ChannelCache {
+        ChannelCache::default()
+    }
+
+    // Data is only kept for channels that have been added.  Adding a channel again keeps its data.
+    pub fn add_channel(&mut self, metadata: ChannelMetadataRecord) {
+        match self.channels.get_mut(&metadata.id) {
+            Some(channel) => channel.metadata = metadata,
+            None => {
+                self.channels.insert(
+                    metadata.id,
+                    CachedChannel {
+                        metadata,
+                        samples: BTreeMap::new(),
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn remove_channel(&mut self, channel_id: i64) -> Option<ChannelMetadataRecord> {
+        self.channels
+            .remove(&channel_id)
+            .map(|channel| channel.metadata)
+    }
+
+    pub fn channel(&self, channel_id: i64) -> Option<&ChannelMetadataRecord> {
+        self.channels
+            .get(&channel_id)
+            .map(|channel| &channel.metadata)
+    }
+
+    pub fn channel_id(&self, uri: &str) -> Option<i64> {
+        self.channels
+            .values()
+            .find(|channel| channel.metadata.uri == uri)
+            .map(|channel| channel.metadata.id)
+    }
+
+    // Applies an event from ChannelSubscribeConsumer::handle_message.  Events that do not change
+    // channel data are ignored.  An event with an invalid index value is rejected whole: the cache is unchanged.
+    pub fn apply(&mut self, event: &ChannelEvent) -> Result<(), Error> {
+        match event {
+            ChannelEvent::Data(samples) => self.insert(samples),
+            ChannelEvent::RangeReplaced {
+                channel_ids,
+                changed_interval,
+                data,
+                ..
+            } => self.replace_range(channel_ids, changed_interval, data),
+            ChannelEvent::Truncated(truncated) => self.truncate(&truncated.channels),
+            _ => Ok(()),
+        }
+    }
+
+    // Adds samples, replacing any at the same index.  Samples for channels not in the cache are skipped.
+    pub fn insert(&mut self, samples: &[ChannelSample]) -> Result<(), Error> {
+        let indexed = self.index_samples(samples)?;
+        self.insert_indexed(indexed);
+        Ok(())
+    }
+
+    // The data of the channels within the interval is replaced by the samples (which may be none).
+    pub fn replace_range(
+        &mut self,
+        channel_ids: &[i64],
+        changed_interval: &IndexInterval,
+        samples: &[ChannelSample],
+    ) -> Result<(), Error> {
+        let mut intervals = vec![];
+        for channel_id in channel_ids {
+            let Some(channel) = self.channels.get(channel_id) else {
+                continue;
+            };
+            let Some(metadata) = channel.metadata.indexes.first() else {
+                continue;
+            };
+            if let Some(interval) = ChannelIndexInterval::from_interval(changed_interval, metadata)?
+            {
+                intervals.push((*channel_id, interval));
+            }
+        }
+        let indexed = self.index_samples(samples)?;
+
+        for (channel_id, interval) in intervals {
+            if let Some(channel) = self.channels.get_mut(&channel_id) {
+                channel.samples.retain(|index, _| !interval.contains(index));
+            }
+        }
+        self.insert_indexed(indexed);
+        Ok(())
+    }
+
+    // Removes data after the new end index of each channel.
+    pub fn truncate(&mut self, truncated: &[TruncateInfo]) -> Result<(), Error> {
+        let mut ends = vec![];
+        for info in truncated {
+            let Some(channel) = self.channels.get(&info.channel_id) else {
+                continue;
+            };
+            let Some(metadata) = channel.metadata.indexes.first() else {
+                continue;
+            };
+            if let Some(end) = ChannelIndex::from_value(&info.new_end_index, metadata)? {
+                ends.push((info.channel_id, end));
+            }
+        }
+
+        for (channel_id, end) in ends {
+            if let Some(channel) = self.channels.get_mut(&channel_id) {
+                channel.samples.retain(|index, _| *index <= end);
+            }
+        }
+        Ok(())
+    }
+
+    // The primary index of each sample for a cached channel, checked before any are inserted.
+    fn index_samples<'a>(
+        &self,
+        samples: &'a [ChannelSample],
+    ) -> Result<Vec<(ChannelIndex, &'a ChannelSample)>, Error> {
+        let mut indexed = Vec::with_capacity(samples.len());
+        for sample in samples {
+            let Some(channel) = self.channels.get(&sample.channel_id) else {
+                trace!("Data for unknown channel {} skipped", sample.channel_id);
+                continue;
+            };
+            indexed.push((
+                primary_index(&channel.metadata, sample.indexes.first())?,
+                sample,
+            ));
+        }
+        Ok(indexed)
+    }
+
+    fn insert_indexed(&mut self, indexed: Vec<(ChannelIndex, &ChannelSample)>) {
+        for (index, sample) in indexed {
+            if let Some(channel) = self.channels.get_mut(&sample.channel_id) {
+                channel.samples.insert(index, sample.clone());
+            }
+        }
+    }
+
+    pub fn clear(&mut self, channel_id: i64) {
+        if let Some(channel) = self.channels.get_mut(&channel_id) {
+            channel.samples.clear();
+        }
+    }
+
+    // All the samples of the channel, in index order.
+    pub fn samples(&self, channel_id: i64) -> impl Iterator<Item = &ChannelSample> {
+        self.channels
+            .get(&channel_id)
+            .into_iter()
+            .flat_map(|channel| channel.samples.values())
+    }
+
+    // The samples of the channel within the interval (inclusive), in index order.
+    pub fn range(
+        &self,
+        channel_id: i64,
+        interval: &ChannelIndexInterval,
+    ) -> impl Iterator<Item = &ChannelSample> {
+        let channel = self.channels.get(&channel_id);
+        let interval = channel.map(|channel| channel.in_direction(interval));
+        channel
+            .into_iter()
+            .zip(interval)
+            .flat_map(|(channel, interval)| {
+                channel
+                    .samples
+                    .range(interval.start..=interval.end)
+                    .map(|(_, s)| s)
+            })
+    }
+
+    pub fn latest(&self, channel_id: i64) -> Option<&ChannelSample> {
+        self.channels
+            .get(&channel_id)
+            .and_then(|channel| channel.samples.values().next_back())
+    }
+
+    // First and last index held for the channel.
+    pub fn extent(&self, channel_id: i64) -> Option<ChannelIndexInterval> {
+        let samples = &self.channels.get(&channel_id)?.samples;
+        let (start, _) = samples.first_key_value()?;
+        let (end, _) = samples.last_key_value()?;
+        Some(ChannelIndexInterval {
+            start: start.clone(),
+            end: end.clone(),
+        })
+    }
+
+    // Parts of the interval with no data: spans longer than max_spacing (see ChannelIndex::offset for
+    // units) between consecutive samples, or between the ends of the interval and the nearest sample.
+    // Indexes that cannot be measured apart (eg. different passes) are not reported.
+    pub fn gaps(
+        &self,
+        channel_id: i64,
+        interval: &ChannelIndexInterval,
+        max_spacing: f64,
+    ) -> Vec<ChannelIndexInterval> {
+        let channel = self.channels.get(&channel_id);
+        let interval = match channel {
+            Some(channel) => channel.in_direction(interval),
+            None => ChannelIndexInterval::new(interval.start.clone(), interval.end.clone()),
+        };
+
+        let mut points = vec![&interval.start];
+        if let Some(channel) = channel {
+            points.extend(
+                channel
+                    .samples
+                    .range(interval.start.clone()..=interval.end.clone())
+                    .map(|(index, _)| index),
+            );
+        }
+        points.push(&interval.end);
+
+        points
+            .windows(2)
+            .filter(|pair| matches!(pair[0].distance_to(pair[1]), Some(d) if d > max_spacing))
+            .map(|pair| ChannelIndexInterval {
+                start: pair[0].clone(),
+                end: pair[1].clone(),
+            })
+            .collect()
+    }
+}
+
+fn primary_index(
+    metadata: &ChannelMetadataRecord,
+    value: Option<&IndexValue>,
+) -> Result<ChannelIndex, Error> {
+    let index_metadata = metadata
+        .indexes
+        .first()
+        .ok_or_else(|| Error::Simple(format!("Channel {} has no index", metadata.uri)))?;
+    value
+        .map(|value| ChannelIndex::from_value(value, index_metadata))
+        .transpose()?
+        .flatten()
+        .ok_or_else(|| Error::Simple(format!("Data for {} has no index value", metadata.uri)))
+}
+
+#[test]
+fn test_channel_cache() {
+    let depth = |v: f64| IndexValue {
+        item: Some(UnionLongDoublePassIndexedDepth::Double(v)),
+    };
+    let index_metadata = IndexMetadataRecord {
+        index_kind: ChannelIndexKind::MeasuredDepth,
+        interval: IndexInterval {
+            start_index: depth(0.0),
+            end_index: depth(100.0),
+            uom: "m".to_string(),
+            depth_datum: "".to_string(),
+        },
+        direction: IndexDirection::Increasing,
+        name: "MD".to_string(),
+        uom: "m".to_string(),
+        depth_datum: "".to_string(),
+        index_property_kind_uri: "".to_string(),
+        filterable: true,
+    };
+    let sample = |v: f64| ChannelSample {
+        channel_id: 7,
+        uri: "eml:///witsml20.Channel(f8fd0d43-8a3b-4c5c-8e0f-4e7a7c0f8a31)".to_string(),
+        channel_name: "ROP".to_string(),
+        indexes: vec![depth(v)],
+        value: DataValue {
+            item: DataValueEnum::Double(v * 10.0),
+        },
+        value_attributes: Vec::new(),
+    };
+    let interval = |start: f64, end: f64| {
+        ChannelIndexInterval::from_interval(
+            &IndexInterval {
+                start_index: depth(start),
+                end_index: depth(end),
+                uom: "m".to_string(),
+                depth_datum: "".to_string(),
+            },
+            &index_metadata,
+        )
+        .unwrap()
+        .unwrap()
+    };
+    let values = |cache: &ChannelCache| -> Vec<f64> {
+        cache
+            .samples(7)
+            .map(|s| f64::try_from(&s.value).unwrap() / 10.0)
+            .collect()
+    };
+
+    let mut cache = ChannelCache::new();
+    cache.add_channel(ChannelMetadataRecord {
+        uri: "eml:///witsml20.Channel(f8fd0d43-8a3b-4c5c-8e0f-4e7a7c0f8a31)".to_string(),
+        id: 7,
+        indexes: vec![index_metadata.clone()],
+        channel_name: "ROP".to_string(),
+        data_kind: ChannelDataKind::TypeDouble,
+        uom: "m/h".to_string(),
+        depth_datum: "".to_string(),
+        channel_class_uri: "".to_string(),
+        status: ActiveStatusKind::Active,
+        source: "".to_string(),
+        axis_vector_lengths: vec![],
+        attribute_metadata: vec![],
+        custom_data: HashMap::new(),
+    });
+
+    // Out of order data is kept in index order
+    cache
+        .apply(&ChannelEvent::Data(vec![
+            sample(3.0),
+            sample(1.0),
+            sample(2.0),
+            sample(6.0),
+        ]))
+        .unwrap();
+    assert_eq!(values(&cache), vec![1.0, 2.0, 3.0, 6.0]);
+    assert_eq!(
+        cache
+            .range(7, &interval(1.5, 3.0))
+            .map(|s| s.indexes[0].clone())
+            .collect::<Vec<_>>(),
+        vec![depth(2.0), depth(3.0)]
+    );
+
+    // 3 to 6 is a gap, and 6 to 8 does not reach the end
+    let gaps = cache.gaps(7, &interval(1.0, 8.0), 1.0);
+    assert_eq!(gaps, vec![interval(3.0, 6.0), interval(6.0, 8.0)]);
+
+    // An inverted interval, or one in the other direction, is taken in the channel direction
+    let inverted = ChannelIndexInterval {
+        start: interval(1.0, 8.0).end,
+        end: interval(1.0, 8.0).start,
+    };
+    assert_eq!(cache.range(7, &inverted).count(), 4);
+    assert_eq!(cache.gaps(7, &inverted, 1.0), gaps);
+    let mut decreasing = interval(1.5, 3.0);
+    decreasing.start.direction = IndexDirection::Decreasing;
+    decreasing.end.direction = IndexDirection::Decreasing;
+    assert_eq!(cache.range(7, &decreasing).count(), 2);
+
+    cache
+        .apply(&ChannelEvent::RangeReplaced {
+            change_time: 0,
+            channel_ids: vec![7],
+            changed_interval: interval(2.0, 6.0).to_interval(),
+            data: vec![sample(2.5), sample(4.0)],
+        })
+        .unwrap();
+    assert_eq!(values(&cache), vec![1.0, 2.5, 4.0]);
+
+    cache
+        .apply(&ChannelEvent::Truncated(ChannelsTruncated {
+            channels: vec![TruncateInfo {
+                channel_id: 7,
+                new_end_index: depth(3.0),
+            }],
+            change_time: 0,
+        }))
+        .unwrap();
+    assert_eq!(values(&cache), vec![1.0, 2.5]);
+    assert_eq!(cache.extent(7), Some(interval(1.0, 2.5)));
+    assert_eq!(cache.latest(7), Some(&sample(2.5)));
+
+    // A batch with a sample missing its index is rejected whole
+    let unindexed = ChannelSample {
+        indexes: vec![],
+        ..sample(5.0)
+    };
+    assert!(cache
+        .apply(&ChannelEvent::Data(vec![sample(4.0), unindexed.clone()]))
+        .is_err());
+    assert_eq!(values(&cache), vec![1.0, 2.5]);
+    assert!(cache
+        .apply(&ChannelEvent::RangeReplaced {
+            change_time: 0,
+            channel_ids: vec![7],
+            changed_interval: interval(0.0, 10.0).to_interval(),
+            data: vec![unindexed],
+        })
+        .is_err());
+    assert_eq!(values(&cache), vec![1.0, 2.5]);
+}
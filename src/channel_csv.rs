@@ -0,0 +1,563 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// CSV / TSV export and import of channel data.  Feature: "csv"
+// One row per primary index value: the index columns (named as in the IndexMetadataRecords), then a column
+// per channel (its channel name) and, optionally, per value attribute ("<channel>.<attribute>").
+// DateTime values are written as UTC RFC 3339 times; empty fields are nulls.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    channel_index::ChannelIndex, channel_subscribe::ChannelSample, error::Error, schema_gen::*,
+};
+#[allow(unused_imports)]
+use log::{info, trace, warn};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub attributes: bool, // Include value attribute columns
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: b',',
+            attributes: true,
+        }
+    }
+}
+
+impl CsvOptions {
+    pub fn tsv() -> CsvOptions {
+        CsvOptions {
+            delimiter: b'\t',
+            ..CsvOptions::default()
+        }
+    }
+}
+
+// A column of the file: an index, a channel value, or a channel value attribute.
+#[derive(Debug, Clone)]
+enum Column {
+    Index(usize, ChannelIndexKind),
+    Value(usize, ChannelDataKind),          // Channel position
+    Attribute(usize, i32, ChannelDataKind), // Channel position, attribute id
+}
+
+fn columns(channels: &[ChannelMetadataRecord], options: &CsvOptions) -> Vec<(String, Column)> {
+    let mut columns: Vec<(String, Column)> = channels
+        .first()
+        .map(|channel| {
+            channel
+                .indexes
+                .iter()
+                .enumerate()
+                .map(|(n, index)| {
+                    (
+                        index.name.clone(),
+                        Column::Index(n, index.index_kind.clone()),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    for (pos, channel) in channels.iter().enumerate() {
+        columns.push((
+            channel.channel_name.clone(),
+            Column::Value(pos, channel.data_kind.clone()),
+        ));
+        if options.attributes {
+            for attribute in &channel.attribute_metadata {
+                columns.push((
+                    format!("{}.{}", channel.channel_name, attribute.attribute_name),
+                    Column::Attribute(pos, attribute.attribute_id, attribute.data_kind.clone()),
+                ));
+            }
+        }
+    }
+    columns
+}
+
+fn csv_error(err: csv::Error) -> Error {
+    Error::Simple(format!("CSV error: {}", err))
+}
+
+// Writes channel samples (eg. from ChannelSubscribe or a ChannelCache), joined on the primary index of
+// the first channel.  Samples of channels not listed are ignored.
+pub fn write_channels<W: Write>(
+    out: W,
+    channels: &[ChannelMetadataRecord],
+    samples: &[ChannelSample],
+    options: &CsvOptions,
+) -> Result<(), Error> {
+    let index = channels
+        .first()
+        .and_then(|channel| channel.indexes.first())
+        .ok_or_else(|| Error::Simple("No channel index to write".to_string()))?;
+
+    // Each row has the index values, and the sample of each channel at that index.
+    let mut rows: BTreeMap<ChannelIndex, (Vec<IndexValue>, Vec<Option<&ChannelSample>>)> =
+        BTreeMap::new();
+    for sample in samples {
+        let Some(pos) = channels.iter().position(|c| c.id == sample.channel_id) else {
+            continue;
+        };
+        let key = sample
+            .indexes
+            .first()
+            .map(|value| ChannelIndex::from_value(value, index))
+            .transpose()?
+            .flatten()
+            .ok_or_else(|| Error::Simple("Data without an index value".to_string()))?;
+        let row = rows
+            .entry(key)
+            .or_insert_with(|| (sample.indexes.clone(), vec![None; channels.len()]));
+        row.1[pos] = Some(sample);
+    }
+
+    let columns = columns(channels, options);
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .from_writer(out);
+    writer
+        .write_record(columns.iter().map(|(name, _)| name))
+        .map_err(csv_error)?;
+
+    for (indexes, samples) in rows.values() {
+        let mut record = Vec::with_capacity(columns.len());
+        for (name, column) in &columns {
+            let field = match column {
+                Column::Index(n, kind) => format_index(indexes.get(*n), kind),
+                Column::Value(pos, kind) => match samples[*pos] {
+                    Some(sample) => format_value(&sample.value, kind),
+                    None => Ok(String::new()),
+                },
+                Column::Attribute(pos, id, kind) => match samples[*pos].and_then(|sample| {
+                    sample
+                        .value_attributes
+                        .iter()
+                        .find(|a| a.attribute_id == *id)
+                }) {
+                    Some(attribute) => format_value(&attribute.attribute_value, kind),
+                    None => Ok(String::new()),
+                },
+            }
+            .map_err(|err| Error::Simple(format!("Column {}: {}", name, err)))?;
+            record.push(field);
+        }
+        writer.write_record(&record).map_err(csv_error)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Reads a file written by write_channels (or a spreadsheet with the same headers) into DataItems, in
+// file order, using the channel ids of the metadata (eg. from ChannelDataLoadProducer::channel).
+// Columns are matched by header; unknown columns are skipped.  Empty channel fields give no DataItem.
+pub fn read_channels<R: Read>(
+    input: R,
+    channels: &[ChannelMetadataRecord],
+    options: &CsvOptions,
+) -> Result<Vec<DataItem>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .from_reader(input);
+    let known = columns(channels, options);
+
+    let headers = reader.headers().map_err(csv_error)?.clone();
+    let columns: Vec<Option<&(String, Column)>> = headers
+        .iter()
+        .map(|header| {
+            let column = known.iter().find(|(name, _)| name == header.trim());
+            if column.is_none() {
+                warn!("CSV column {} does not match a channel, skipped", header);
+            }
+            column
+        })
+        .collect();
+
+    let index_count = channels.first().map(|c| c.indexes.len()).unwrap_or(0);
+    for n in 0..index_count {
+        if !columns
+            .iter()
+            .any(|c| matches!(c, Some((_, Column::Index(i, _))) if *i == n))
+        {
+            return Err(Error::Simple(format!(
+                "Index column {} missing",
+                channels[0].indexes[n].name
+            )));
+        }
+    }
+
+    let mut items = Vec::new();
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(csv_error)?;
+        let line_error =
+            |name: &str, err: Error| Error::Simple(format!("Row {}, {}: {}", line + 1, name, err));
+
+        let mut indexes = vec![IndexValue { item: None }; index_count];
+        let mut values: Vec<Option<DataValue>> = vec![None; channels.len()];
+        let mut attributes: Vec<Vec<DataAttribute>> = vec![vec![]; channels.len()];
+
+        for (field, column) in record.iter().zip(&columns) {
+            let Some((name, column)) = column else {
+                continue;
+            };
+            let field = field.trim();
+            match column {
+                Column::Index(n, kind) => {
+                    indexes[*n] = parse_index(field, kind).map_err(|err| line_error(name, err))?
+                }
+                _ if field.is_empty() => {}
+                Column::Value(pos, kind) => {
+                    values[*pos] =
+                        Some(parse_value(field, kind).map_err(|err| line_error(name, err))?)
+                }
+                Column::Attribute(pos, id, kind) => attributes[*pos].push(DataAttribute {
+                    attribute_id: *id,
+                    attribute_value: parse_value(field, kind)
+                        .map_err(|err| line_error(name, err))?,
+                }),
+            }
+        }
+
+        for ((channel, value), value_attributes) in channels.iter().zip(values).zip(attributes) {
+            if let Some(value) = value {
+                items.push(DataItem {
+                    channel_id: channel.id,
+                    indexes: indexes.clone(),
+                    value,
+                    value_attributes,
+                });
+            }
+        }
+    }
+    Ok(items)
+}
+
+fn format_index(value: Option<&IndexValue>, kind: &ChannelIndexKind) -> Result<String, Error> {
+    Ok(match value.and_then(|v| v.item.as_ref()) {
+        None => String::new(),
+        Some(UnionLongDoublePassIndexedDepth::Long(v)) if *kind == ChannelIndexKind::DateTime => {
+            format_time(*v)
+        }
+        Some(UnionLongDoublePassIndexedDepth::Long(v)) => v.to_string(),
+        Some(UnionLongDoublePassIndexedDepth::Double(v)) => v.to_string(),
+        Some(UnionLongDoublePassIndexedDepth::PassIndexedDepth(_)) => {
+            return Err(Error::Simple(
+                "Pass indexed depths are not supported in CSV".to_string(),
+            ))
+        }
+    })
+}
+
+fn parse_index(field: &str, kind: &ChannelIndexKind) -> Result<IndexValue, Error> {
+    if field.is_empty() {
+        return Ok(IndexValue { item: None });
+    }
+    let item = match kind {
+        ChannelIndexKind::DateTime => UnionLongDoublePassIndexedDepth::Long(parse_time(field)?),
+        ChannelIndexKind::ElapsedTime => UnionLongDoublePassIndexedDepth::Long(parse(field)?),
+        ChannelIndexKind::PassIndexedDepth => {
+            return Err(Error::Simple(
+                "Pass indexed depths are not supported in CSV".to_string(),
+            ))
+        }
+        _ => UnionLongDoublePassIndexedDepth::Double(parse(field)?),
+    };
+    Ok(IndexValue { item: Some(item) })
+}
+
+fn format_value(value: &DataValue, kind: &ChannelDataKind) -> Result<String, Error> {
+    Ok(match &value.item {
+        DataValueEnum::Null => String::new(),
+        DataValueEnum::Long(v) if *kind == ChannelDataKind::DateTime => format_time(*v),
+        DataValueEnum::Boolean(_)
+        | DataValueEnum::Int(_)
+        | DataValueEnum::Long(_)
+        | DataValueEnum::Float(_)
+        | DataValueEnum::Double(_)
+        | DataValueEnum::String(_) => value.to_string(),
+        _ => {
+            return Err(Error::Simple(format!(
+                "{} values are not supported in CSV",
+                value.type_name()
+            )))
+        }
+    })
+}
+
+fn parse_value(field: &str, kind: &ChannelDataKind) -> Result<DataValue, Error> {
+    Ok(match kind {
+        ChannelDataKind::DateTime => DataValue::from(parse_time(field)?),
+        ChannelDataKind::ElapsedTime | ChannelDataKind::TypeLong => {
+            DataValue::from(parse::<i64>(field)?)
+        }
+        ChannelDataKind::TypeBoolean => DataValue::from(match field {
+            "1" => true,
+            "0" => false,
+            _ => parse::<bool>(&field.to_lowercase())?,
+        }),
+        ChannelDataKind::TypeInt => DataValue::from(parse::<i32>(field)?),
+        ChannelDataKind::TypeFloat => DataValue::from(parse::<f32>(field)?),
+        ChannelDataKind::TypeString => DataValue::from(field),
+        ChannelDataKind::TypeBytes => {
+            return Err(Error::Simple(
+                "Bytes values are not supported in CSV".to_string(),
+            ))
+        }
+        _ => DataValue::from(parse::<f64>(field)?),
+    })
+}
+
+fn parse<T: std::str::FromStr>(field: &str) -> Result<T, Error> {
+    field
+        .parse::<T>()
+        .map_err(|_| Error::Simple(format!("Invalid value '{}'", field)))
+}
+
+// Microseconds since the Unix epoch as "1970-01-01T00:00:00.000000Z".
+fn format_time(micros: i64) -> String {
+    let seconds = micros.div_euclid(1_000_000);
+    let (days, secs) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        micros.rem_euclid(1_000_000)
+    )
+}
+
+// An RFC 3339 time ("Z" or "+hh:mm" offset, optional fraction), or a number of microseconds.
+fn parse_time(field: &str) -> Result<i64, Error> {
+    if let Ok(micros) = field.parse::<i64>() {
+        return Ok(micros);
+    }
+    let invalid = || Error::Simple(format!("Invalid time '{}'", field));
+    let number = |s: &str, range: std::ops::RangeInclusive<i64>| match s.parse::<i64>() {
+        Ok(n) if range.contains(&n) => Ok(n),
+        _ => Err(invalid()),
+    };
+
+    let (date, time) = field.split_once(['T', ' ']).ok_or_else(invalid)?;
+    let date: Vec<&str> = date.split('-').collect();
+    let (clock, rest) = match (time.get(..8), time.get(8..)) {
+        (Some(clock), Some(rest)) if date.len() == 3 => (clock, rest),
+        _ => return Err(invalid()),
+    };
+    let clock: Vec<&str> = clock.split(':').collect();
+    if clock.len() != 3 {
+        return Err(invalid());
+    }
+
+    let (fraction, offset) = match rest.find(['Z', 'z', '+', '-']) {
+        Some(pos) => rest.split_at(pos),
+        None => return Err(invalid()),
+    };
+    let micros = match fraction.strip_prefix('.') {
+        Some(digits) if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) => {
+            let digits: String = digits
+                .chars()
+                .chain(std::iter::repeat('0'))
+                .take(6)
+                .collect();
+            number(&digits, 0..=999_999)?
+        }
+        Some(_) => return Err(invalid()),
+        None if fraction.is_empty() => 0,
+        None => return Err(invalid()),
+    };
+    let offset_seconds = match offset {
+        "Z" | "z" => 0,
+        _ => {
+            let (hours, minutes) = offset[1..].split_once(':').ok_or_else(invalid)?;
+            let seconds = number(hours, 0..=23)? * 3600 + number(minutes, 0..=59)? * 60;
+            if offset.starts_with('-') {
+                -seconds
+            } else {
+                seconds
+            }
+        }
+    };
+
+    let days = days_from_civil(
+        number(date[0], i64::MIN..=i64::MAX)?,
+        number(date[1], 1..=12)?,
+        number(date[2], 1..=31)?,
+    )
+    .ok_or_else(invalid)?;
+    let seconds_of_day = number(clock[0], 0..=23)? * 3600
+        + number(clock[1], 0..=59)? * 60
+        + number(clock[2], 0..=60)?
+        - offset_seconds;
+    days.checked_mul(86400)
+        .and_then(|seconds| seconds.checked_add(seconds_of_day))
+        .and_then(|seconds| seconds.checked_mul(1_000_000))
+        .and_then(|seconds| seconds.checked_add(micros))
+        .ok_or_else(invalid)
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date (None if out of range), and back.
+fn days_from_civil(year: i64, month: i64, day: i64) -> Option<i64> {
+    let year = if month <= 2 {
+        year.checked_sub(1)?
+    } else {
+        year
+    };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era.checked_mul(146097)?.checked_add(day_of_era - 719468)
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[test]
+fn test_csv_roundtrip() {
+    let index = IndexMetadataRecord {
+        index_kind: ChannelIndexKind::DateTime,
+        interval: IndexInterval {
+            start_index: IndexValue { item: None },
+            end_index: IndexValue { item: None },
+            uom: "".to_string(),
+            depth_datum: "".to_string(),
+        },
+        direction: IndexDirection::Increasing,
+        name: "TIME".to_string(),
+        uom: "".to_string(),
+        depth_datum: "".to_string(),
+        index_property_kind_uri: "".to_string(),
+        filterable: true,
+    };
+    let channel = |id: i64, name: &str, data_kind: ChannelDataKind| ChannelMetadataRecord {
+        uri: format!("eml:///witsml20.Channel({})", name),
+        id,
+        indexes: vec![index.clone()],
+        channel_name: name.to_string(),
+        data_kind,
+        uom: "".to_string(),
+        depth_datum: "".to_string(),
+        channel_class_uri: "".to_string(),
+        status: ActiveStatusKind::Active,
+        source: "".to_string(),
+        axis_vector_lengths: vec![],
+        attribute_metadata: vec![AttributeMetadataRecord {
+            attribute_id: 1,
+            attribute_name: "quality".to_string(),
+            data_kind: ChannelDataKind::TypeInt,
+            uom: "".to_string(),
+            depth_datum: "".to_string(),
+            attribute_property_kind_uri: "".to_string(),
+            axis_vector_lengths: vec![],
+        }],
+        custom_data: std::collections::HashMap::new(),
+    };
+    let channels = vec![
+        channel(1, "ROP", ChannelDataKind::TypeDouble),
+        channel(2, "Activity", ChannelDataKind::TypeString),
+    ];
+    let sample = |id: i64, time: i64, value: DataValue, quality: Option<i32>| ChannelSample {
+        channel_id: id,
+        uri: "".to_string(),
+        channel_name: "".to_string(),
+        indexes: vec![IndexValue {
+            item: Some(UnionLongDoublePassIndexedDepth::Long(time)),
+        }],
+        value,
+        value_attributes: quality
+            .map(|q| DataAttribute {
+                attribute_id: 1,
+                attribute_value: DataValue::from(q),
+            })
+            .into_iter()
+            .collect(),
+    };
+
+    // 2023-03-01T12:00:00Z, and half a second later
+    let t0 = 1_677_672_000_000_000;
+    let samples = vec![
+        sample(1, t0, DataValue::from(12.5), Some(3)),
+        sample(2, t0, DataValue::from("Drilling, rotary"), None),
+        sample(1, t0 + 500_000, DataValue::from(13.0), None),
+    ];
+
+    let mut out = Vec::new();
+    write_channels(&mut out, &channels, &samples, &CsvOptions::default()).unwrap();
+    let text = String::from_utf8(out.clone()).unwrap();
+    assert_eq!(
+        text.lines().collect::<Vec<_>>(),
+        vec![
+            "TIME,ROP,ROP.quality,Activity,Activity.quality",
+            "2023-03-01T12:00:00.000000Z,12.5,3,\"Drilling, rotary\",",
+            "2023-03-01T12:00:00.500000Z,13,,,",
+        ]
+    );
+
+    let items = read_channels(out.as_slice(), &channels, &CsvOptions::default()).unwrap();
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].channel_id, 1);
+    assert_eq!(items[0].indexes, samples[0].indexes);
+    assert_eq!(items[0].value_attributes, samples[0].value_attributes);
+    assert_eq!(items[1].value, DataValue::from("Drilling, rotary"));
+    assert_eq!(items[2].value, DataValue::from(13.0));
+
+    assert_eq!(
+        parse_time("2023-03-01T13:30:00.5+01:30").unwrap(),
+        t0 + 500_000
+    );
+    assert_eq!(format_time(-1), "1969-12-31T23:59:59.999999Z");
+    for bad in [
+        "2023-03-01T13:30:0\u{e9}Z",
+        "2023-03-01T13:3\u{e9}0:00Z",
+        "99999999999999-03-01T13:30:00Z",
+        "-9223372036854775808-01-01T00:00:00Z",
+        "2023-03-01T13:30:00+9999999999999999:00",
+        "2023-13-01T13:30:00Z",
+        "2023-03-01T13:30:00.+5Z",
+    ] {
+        assert!(parse_time(bad).is_err(), "{}", bad);
+    }
+    assert!(read_channels("ROP\n1.0\n".as_bytes(), &channels, &CsvOptions::default()).is_err());
+}
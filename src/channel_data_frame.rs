@@ -0,0 +1,399 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Channel Data Frame (Protocol 2) - Customer side.
+// Reads a frame (rows of channel values over an index range) and pivots it into columns.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error,
+    headerflags::*,
+    schema::*,
+    schema_gen::*,
+    session::{protocol_exception, Session},
+};
+use apache_avro::from_value;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct FrameColumn {
+    pub uri: String,
+    pub metadata: Option<FrameChannelMetadataRecord>, // If the metadata was supplied for this channel
+    pub values: Vec<DataValue>,
+    pub value_attributes: Vec<Vec<DataAttribute>>,
+}
+
+// A frame in column order.  Every column (index or channel) has one entry per row.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Frame {
+    pub index_metadata: Vec<IndexMetadataRecord>,
+    pub indexes: Vec<Vec<IndexValue>>, // One column per entry in index_metadata
+    pub columns: Vec<FrameColumn>, // One column per channel, in the order of the response header
+}
+
+impl Frame {
+    pub fn new(header: GetFrameResponseHeader, metadata: &[FrameChannelMetadataRecord]) -> Frame {
+        let columns = header
+            .channel_uris
+            .into_iter()
+            .map(|uri| FrameColumn {
+                metadata: metadata.iter().find(|m| m.uri == uri).cloned(),
+                uri,
+                values: vec![],
+                value_attributes: vec![],
+            })
+            .collect();
+
+        Frame {
+            indexes: vec![vec![]; header.indexes.len()],
+            index_metadata: header.indexes,
+            columns,
+        }
+    }
+
+    pub fn row_count(&self) -> usize {
+        match self.columns.first() {
+            Some(column) => column.values.len(),
+            None => self.indexes.first().map(|index| index.len()).unwrap_or(0),
+        }
+    }
+
+    pub fn column(&self, uri: &str) -> Option<&FrameColumn> {
+        self.columns.iter().find(|column| column.uri == uri)
+    }
+
+    // Adds a row.  Missing indexes or points are filled with nulls so all columns stay the same length.
+    pub fn append_row(&mut self, row: FrameRow) {
+        let mut indexes = row.indexes.into_iter();
+        for index_column in self.indexes.iter_mut() {
+            index_column.push(indexes.next().unwrap_or(IndexValue { item: None }));
+        }
+
+        let mut points = row.points.into_iter();
+        for column in self.columns.iter_mut() {
+            match points.next() {
+                Some(point) => {
+                    column.values.push(point.value);
+                    column.value_attributes.push(point.value_attributes);
+                }
+                None => {
+                    column.values.push(DataValue {
+                        item: DataValueEnum::Null,
+                    });
+                    column.value_attributes.push(vec![]);
+                }
+            }
+        }
+    }
+}
+
+// An in-progress GetFrame request.  Rows are read from the session on demand.
+pub struct FrameReader<'a> {
+    session: &'a mut Session,
+    request_id: i64,
+    request_uuid: Uuid,
+    frame: Frame,
+    finished: bool,
+}
+
+impl<'a> FrameReader<'a> {
+    pub fn request_uuid(&self) -> Uuid {
+        self.request_uuid
+    }
+
+    // The rows read so far.
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    // Reads the next response message into the frame.  Returns the number of rows added, or None when complete.
+    pub fn read_rows(&mut self) -> Result<Option<usize>, Error> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let (msg_hdr, msg_body) = match self.session.read_correlated(self.request_id) {
+            Ok(msg) => msg,
+            Err(err) => {
+                // Nothing more can be read
+                self.finished = true;
+                return Err(err);
+            }
+        };
+        self.finished = msg_hdr.get_flags().finalmsg;
+
+        match msg_hdr.msgtype() {
+            CHANNELDATAFRAME_GETFRAMERESPONSEROWS => {}
+            CORE_PROTOCOLEXCEPTION => return Err(protocol_exception(&msg_hdr, &msg_body)),
+            _ => {
+                return Err(Error::UnexpectedMessage(
+                    msg_hdr.protocol,
+                    msg_hdr.message_type,
+                ))
+            }
+        }
+
+        let rows = from_value::<GetFrameResponseRows>(&msg_body)?;
+        let count = rows.frame.len();
+        for row in rows.frame {
+            self.frame.append_row(row);
+        }
+        Ok(Some(count))
+    }
+
+    // Reads all remaining rows.
+    pub fn finish(mut self) -> Result<Frame, Error> {
+        while self.read_rows()?.is_some() {}
+        Ok(std::mem::take(&mut self.frame))
+    }
+
+    // Asks the store to stop sending rows, and returns the rows received up to that point.
+    pub fn cancel(mut self) -> Result<Frame, Error> {
+        if !self.finished {
+            self.session.send_message(
+                CancelGetFrame {
+                    request_uuid: self.request_uuid,
+                },
+                CHANNELDATAFRAME_CANCELGETFRAME,
+                0,
+                MessageHeaderFlags::default(),
+                None,
+            )?;
+
+            // Rows may already be in flight.  The store ends the request with a final message,
+            // which may be a ProtocolException for the cancelled request.
+            loop {
+                match self.read_rows() {
+                    Ok(Some(_)) => {}
+                    Ok(None) => break,
+                    Err(Error::ProtocolException(_, _)) => break,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(std::mem::take(&mut self.frame))
+    }
+}
+
+// Stopping early leaves the rest of the response to be discarded, rather than returned by later reads.
+impl<'a> Drop for FrameReader<'a> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.session.discard_response(self.request_id);
+        }
+    }
+}
+
+impl Session {
+    pub fn get_frame_metadata(
+        &mut self,
+        uri: &str,
+        include_all_channel_secondary_indexes: bool,
+    ) -> Result<GetFrameMetadataResponse, Error> {
+        let msg_id = self.send_message(
+            GetFrameMetadata {
+                uri: uri.to_string(),
+                include_all_channel_secondary_indexes,
+            },
+            CHANNELDATAFRAME_GETFRAMEMETADATA,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let (msg_hdr, msg_body) = self.read_response(msg_id)?;
+        match msg_hdr.msgtype() {
+            CHANNELDATAFRAME_GETFRAMEMETADATARESPONSE => {
+                Ok(from_value::<GetFrameMetadataResponse>(&msg_body)?)
+            }
+            _ => Err(Error::UnexpectedMessage(
+                msg_hdr.protocol,
+                msg_hdr.message_type,
+            )),
+        }
+    }
+
+    // Requests the frame for an index interval, and reads the response header.
+    // 'metadata' (from get_frame_metadata) is attached to the matching columns, and may be empty.
+    pub fn get_frame(
+        &mut self,
+        uri: &str,
+        requested_interval: IndexInterval,
+        include_all_channel_secondary_indexes: bool,
+        metadata: &[FrameChannelMetadataRecord],
+    ) -> Result<FrameReader<'_>, Error> {
+        let request_uuid = *uuid::Uuid::new_v4().as_bytes();
+        let request_id = self.send_message(
+            GetFrame {
+                uri: uri.to_string(),
+                include_all_channel_secondary_indexes,
+                requested_interval,
+                request_uuid,
+                requested_secondary_intervals: vec![],
+            },
+            CHANNELDATAFRAME_GETFRAME,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let (msg_hdr, msg_body) = self.read_response(request_id)?;
+        if msg_hdr.msgtype() != CHANNELDATAFRAME_GETFRAMERESPONSEHEADER {
+            return Err(Error::UnexpectedMessage(
+                msg_hdr.protocol,
+                msg_hdr.message_type,
+            ));
+        }
+        let header = from_value::<GetFrameResponseHeader>(&msg_body)?;
+
+        Ok(FrameReader {
+            session: self,
+            request_id,
+            request_uuid,
+            frame: Frame::new(header, metadata),
+            finished: msg_hdr.get_flags().finalmsg,
+        })
+    }
+}
+
+#[test]
+fn test_frame_columns() {
+    let index = IndexMetadataRecord {
+        index_kind: ChannelIndexKind::MeasuredDepth,
+        interval: IndexInterval {
+            start_index: IndexValue {
+                item: Some(UnionLongDoublePassIndexedDepth::Double(0.0)),
+            },
+            end_index: IndexValue {
+                item: Some(UnionLongDoublePassIndexedDepth::Double(10.0)),
+            },
+            uom: "m".to_string(),
+            depth_datum: "".to_string(),
+        },
+        direction: IndexDirection::Increasing,
+        name: "MD".to_string(),
+        uom: "m".to_string(),
+        depth_datum: "".to_string(),
+        index_property_kind_uri: "".to_string(),
+        filterable: true,
+    };
+    let header = GetFrameResponseHeader {
+        channel_uris: vec!["eml:///c1".to_string(), "eml:///c2".to_string()],
+        indexes: vec![index],
+    };
+    let mut frame = Frame::new(header, &[]);
+
+    let point = |v: f64| FramePoint {
+        value: DataValue {
+            item: DataValueEnum::Double(v),
+        },
+        value_attributes: vec![],
+    };
+    frame.append_row(FrameRow {
+        indexes: vec![IndexValue {
+            item: Some(UnionLongDoublePassIndexedDepth::Double(1.0)),
+        }],
+        points: vec![point(1.5), point(2.5)],
+    });
+    // Short row, second channel has no value
+    frame.append_row(FrameRow {
+        indexes: vec![IndexValue {
+            item: Some(UnionLongDoublePassIndexedDepth::Double(2.0)),
+        }],
+        points: vec![point(3.5)],
+    });
+
+    assert_eq!(frame.row_count(), 2);
+    assert_eq!(frame.indexes[0].len(), 2);
+    let c2 = frame.column("eml:///c2").unwrap();
+    assert_eq!(c2.values[0].item, DataValueEnum::Double(2.5));
+    assert_eq!(c2.values[1].item, DataValueEnum::Null);
+    assert!(c2.metadata.is_none());
+}
+
+#[test]
+fn test_frame_reader_drop() {
+    use crate::session::TestPeer;
+
+    let (mut session, mut peer) = TestPeer::connect();
+    let depth = |v: f64| IndexValue {
+        item: Some(UnionLongDoublePassIndexedDepth::Double(v)),
+    };
+    let rows = |v: f64| GetFrameResponseRows {
+        frame: vec![FrameRow {
+            indexes: vec![depth(v)],
+            points: vec![],
+        }],
+    };
+    let interval = IndexInterval {
+        start_index: depth(0.0),
+        end_index: depth(10.0),
+        uom: "m".to_string(),
+        depth_datum: "".to_string(),
+    };
+
+    // The client's first request is MessageID 2
+    peer.send(
+        GetFrameResponseHeader {
+            channel_uris: vec![],
+            indexes: vec![],
+        },
+        CHANNELDATAFRAME_GETFRAMERESPONSEHEADER,
+        2,
+        false,
+    );
+    peer.send(
+        Pong {
+            current_date_time: 1,
+        },
+        CORE_PONG,
+        0,
+        true,
+    );
+    peer.send(rows(1.0), CHANNELDATAFRAME_GETFRAMERESPONSEROWS, 2, false);
+    peer.send(rows(2.0), CHANNELDATAFRAME_GETFRAMERESPONSEROWS, 2, false);
+    peer.send(rows(3.0), CHANNELDATAFRAME_GETFRAMERESPONSEROWS, 2, true);
+    peer.send(
+        Pong {
+            current_date_time: 2,
+        },
+        CORE_PONG,
+        0,
+        true,
+    );
+
+    let mut reader = session
+        .get_frame("eml:///witsml20.Log(a)", interval, false, &[])
+        .unwrap();
+    assert_eq!(reader.read_rows().unwrap(), Some(1));
+    drop(reader);
+
+    // Only the unrelated messages are left, whether already queued or still to arrive
+    for time in [1, 2] {
+        let (msg_hdr, msg_body) = session.read_message().unwrap();
+        assert_eq!(msg_hdr.msgtype(), CORE_PONG);
+        assert_eq!(
+            from_value::<Pong>(&msg_body).unwrap().current_date_time,
+            time
+        );
+    }
+}
@@ -0,0 +1,575 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Channel Data Load (Protocol 22) - Customer (producer) side.
+// Opens channels in a store, and pushes real-time data into them in batches.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error,
+    headerflags::*,
+    schema::*,
+    schema_gen::*,
+    session::{Encoding, MapResponse, Session},
+};
+use apache_avro::{from_value, to_value, types::Value};
+#[allow(unused_imports)]
+use log::{info, trace, warn};
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub struct ChannelDataLoadProducer {
+    channels: HashMap<String, OpenChannelInfo>, // By Channel URI
+    ids: HashMap<String, i64>,                  // Channel URI to store assigned Channel ID
+    buffer: Vec<DataItem>,
+    buffer_size: usize,      // Encoded size of the buffered items
+    max_message_size: usize, // Upper limit on the encoded size of a ChannelData message, in the session encoding
+    max_range_items: usize,  // Upper limit on DataItems in one ReplaceRange message part
+}
+
+impl ChannelDataLoadProducer {
+    // Limits are taken from what the store advertised in OpenSession.
+    pub fn new(session: &Session) -> ChannelDataLoadProducer {
+        let max_range_items = session
+            .open_session_msg
+            .protocol_capability(
+                Protocol::ChannelDataLoad,
+                ProtocolCapabilityKind::MaxRangeDataItemCount,
+            )
+            .and_then(|count| usize::try_from(count).ok())
+            .unwrap_or(usize::MAX);
+
+        ChannelDataLoadProducer::with_limits(session.max_encoded_size(), max_range_items)
+    }
+
+    fn with_limits(max_message_size: usize, max_range_items: usize) -> ChannelDataLoadProducer {
+        ChannelDataLoadProducer {
+            channels: HashMap::new(),
+            ids: HashMap::new(),
+            buffer: vec![],
+            buffer_size: 0,
+            max_message_size,
+            max_range_items: max_range_items.max(1),
+        }
+    }
+
+    pub fn channel_id(&self, uri: &str) -> Option<i64> {
+        self.ids.get(uri).copied()
+    }
+
+    pub fn channel(&self, uri: &str) -> Option<&OpenChannelInfo> {
+        self.channels.get(uri)
+    }
+
+    // Opens the channels for loading.  The store assigns the channel ids used in all later messages.
+    pub fn open_channels(
+        &mut self,
+        session: &mut Session,
+        uris: &[&str],
+    ) -> Result<MapResponse<OpenChannelInfo>, Error> {
+        let request = OpenChannels {
+            uris: uris
+                .iter()
+                .map(|uri| (uri.to_string(), uri.to_string()))
+                .collect(),
+        };
+        let msg_id = session.send_message(
+            request,
+            CHANNELDATALOAD_OPENCHANNELS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let response =
+            session.read_map_response(msg_id, CHANNELDATALOAD_OPENCHANNELSRESPONSE, |body| {
+                Ok(from_value::<OpenChannelsResponse>(body)?.channels)
+            })?;
+
+        for (uri, info) in &response.success {
+            self.ids.insert(uri.clone(), info.metadata.id);
+            self.channels.insert(uri.clone(), info.clone());
+        }
+        Ok(response)
+    }
+
+    // Queues a value for an open channel.  Sends a ChannelData message once a full batch is buffered.
+    pub fn push(
+        &mut self,
+        session: &mut Session,
+        uri: &str,
+        indexes: Vec<IndexValue>,
+        value: DataValue,
+    ) -> Result<(), Error> {
+        let channel_id = match self.ids.get(uri) {
+            Some(id) => *id,
+            None => return Err(Error::Simple(format!("Channel not open: {}", uri))),
+        };
+        self.push_item(
+            session,
+            DataItem {
+                channel_id,
+                indexes,
+                value,
+                value_attributes: vec![],
+            },
+        )
+    }
+
+    // Queues a DataItem that already carries the store assigned channel id.
+    pub fn push_item(&mut self, session: &mut Session, item: DataItem) -> Result<(), Error> {
+        let item_size = encoded_size(session.schema(), session.encoding(), &item)?;
+        if let Some(data) = self.queue(item, item_size)? {
+            send_data(session, data)?;
+        }
+        Ok(())
+    }
+
+    // Buffers an item of the given encoded size.  Returns the batch to send first, if the item does not
+    // fit in the same message.
+    fn queue(&mut self, item: DataItem, item_size: usize) -> Result<Option<Vec<DataItem>>, Error> {
+        if item_size > self.max_message_size {
+            return Err(Error::Simple(format!(
+                "DataItem of {} bytes exceeds the maximum message size of {} bytes",
+                item_size, self.max_message_size
+            )));
+        }
+
+        let full = if self.buffer_size + item_size > self.max_message_size {
+            self.take_buffer()
+        } else {
+            None
+        };
+        self.buffer.push(item);
+        self.buffer_size += item_size;
+        Ok(full)
+    }
+
+    fn take_buffer(&mut self) -> Option<Vec<DataItem>> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        self.buffer_size = 0;
+        Some(std::mem::take(&mut self.buffer))
+    }
+
+    // Sends any buffered DataItems.
+    pub fn flush(&mut self, session: &mut Session) -> Result<(), Error> {
+        match self.take_buffer() {
+            Some(data) => send_data(session, data),
+            None => Ok(()),
+        }
+    }
+
+    // Replaces all data in the interval for the channels with 'data'.
+    // Sent as a multipart message if there are more DataItems than the store accepts in one message.
+    pub fn replace_range(
+        &mut self,
+        session: &mut Session,
+        changed_interval: IndexInterval,
+        uris: &[&str],
+        data: Vec<DataItem>,
+    ) -> Result<ReplaceRangeResponse, Error> {
+        let parts = self.replace_range_parts(changed_interval, uris, data)?;
+
+        // Send buffered data first, so it is not written after (and over) the replacement.
+        self.flush(session)?;
+
+        let request_id = send_multipart(parts, |part, correlation_id, flags| {
+            session.send_message(
+                part,
+                CHANNELDATALOAD_REPLACERANGE,
+                correlation_id,
+                flags,
+                None,
+            )
+        })?;
+
+        let (msg_hdr, msg_body) = session.read_response(request_id)?;
+        match msg_hdr.msgtype() {
+            CHANNELDATALOAD_REPLACERANGERESPONSE => {
+                Ok(from_value::<ReplaceRangeResponse>(&msg_body)?)
+            }
+            _ => Err(Error::UnexpectedMessage(
+                msg_hdr.protocol,
+                msg_hdr.message_type,
+            )),
+        }
+    }
+
+    // The parts of a ReplaceRange, each within the store's limit on DataItems.  There is always one part,
+    // even with no data.
+    fn replace_range_parts(
+        &self,
+        changed_interval: IndexInterval,
+        uris: &[&str],
+        data: Vec<DataItem>,
+    ) -> Result<Vec<ReplaceRange>, Error> {
+        let mut channel_ids = vec![];
+        for uri in uris {
+            match self.ids.get(*uri) {
+                Some(id) => channel_ids.push(*id),
+                None => return Err(Error::Simple(format!("Channel not open: {}", uri))),
+            }
+        }
+
+        let mut chunks: Vec<Vec<DataItem>> = data
+            .chunks(self.max_range_items)
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        if chunks.is_empty() {
+            chunks.push(vec![]);
+        }
+
+        Ok(chunks
+            .into_iter()
+            .map(|chunk| ReplaceRange {
+                changed_interval: changed_interval.clone(),
+                channel_ids: channel_ids.clone(),
+                data: chunk,
+            })
+            .collect())
+    }
+
+    // Truncates each channel at a new end index.  Returns the truncation time per channel URI.
+    pub fn truncate_channels(
+        &mut self,
+        session: &mut Session,
+        new_end_indexes: Vec<(&str, IndexValue)>,
+    ) -> Result<MapResponse<i64>, Error> {
+        let request = self.truncate_request(new_end_indexes)?;
+
+        self.flush(session)?;
+
+        let msg_id = session.send_message(
+            request,
+            CHANNELDATALOAD_TRUNCATECHANNELS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        session.read_map_response(msg_id, CHANNELDATALOAD_TRUNCATECHANNELSRESPONSE, |body| {
+            Ok(from_value::<TruncateChannelsResponse>(body)?.channels_truncated_time)
+        })
+    }
+
+    fn truncate_request(
+        &self,
+        new_end_indexes: Vec<(&str, IndexValue)>,
+    ) -> Result<TruncateChannels, Error> {
+        let mut channels: HashMap<String, TruncateInfo> = HashMap::new();
+        for (uri, new_end_index) in new_end_indexes {
+            let channel_id = match self.ids.get(uri) {
+                Some(id) => *id,
+                None => return Err(Error::Simple(format!("Channel not open: {}", uri))),
+            };
+            channels.insert(
+                uri.to_string(),
+                TruncateInfo {
+                    channel_id,
+                    new_end_index,
+                },
+            );
+        }
+        Ok(TruncateChannels { channels })
+    }
+
+    // Flushes remaining data, then closes the channels.  Returns the closed channel id per URI.
+    pub fn close_channels(
+        &mut self,
+        session: &mut Session,
+        uris: &[&str],
+    ) -> Result<MapResponse<i64>, Error> {
+        self.flush(session)?;
+
+        let mut ids: HashMap<String, i64> = HashMap::new();
+        for uri in uris {
+            match self.ids.get(*uri) {
+                Some(id) => {
+                    ids.insert(uri.to_string(), *id);
+                }
+                None => return Err(Error::Simple(format!("Channel not open: {}", uri))),
+            }
+        }
+
+        let msg_id = session.send_message(
+            CloseChannels { id: ids },
+            CHANNELDATALOAD_CLOSECHANNELS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let response =
+            session.read_map_response(msg_id, CHANNELDATALOAD_CHANNELSCLOSED, |body| {
+                Ok(from_value::<ChannelsClosed>(body)?.id)
+            })?;
+
+        for uri in response.success.keys() {
+            self.ids.remove(uri);
+            self.channels.remove(uri);
+        }
+        Ok(response)
+    }
+
+    // Handles a ChannelsClosed sent by the store without a request (eg. the channel was deleted).
+    // Returns None for any other message.
+    pub fn handle_message(
+        &mut self,
+        msg_hdr: &MessageHeader,
+        msg_body: &Value,
+    ) -> Result<Option<ChannelsClosed>, Error> {
+        if msg_hdr.msgtype() != CHANNELDATALOAD_CHANNELSCLOSED {
+            return Ok(None);
+        }
+
+        let closed = from_value::<ChannelsClosed>(msg_body)?;
+        let closed_ids: Vec<i64> = closed.id.values().copied().collect();
+        self.ids.retain(|_, id| !closed_ids.contains(id));
+        self.channels
+            .retain(|_, info| !closed_ids.contains(&info.metadata.id));
+        self.buffer
+            .retain(|item| !closed_ids.contains(&item.channel_id));
+        Ok(Some(closed))
+    }
+}
+
+fn send_data(session: &mut Session, data: Vec<DataItem>) -> Result<(), Error> {
+    session.send_message(
+        ChannelData { data },
+        CHANNELDATALOAD_CHANNELDATA,
+        0,
+        MessageHeaderFlags::default(),
+        None,
+    )?;
+    Ok(())
+}
+
+// Sends the parts of a multipart request: all but the last are flagged not final, and the parts after the
+// first are correlated to it.  Returns the MessageID of the first part, which the response correlates to.
+fn send_multipart<T, F>(parts: Vec<T>, mut send: F) -> Result<i64, Error>
+where
+    F: FnMut(T, i64, MessageHeaderFlags) -> Result<i64, Error>,
+{
+    let last = parts.len().saturating_sub(1);
+    let mut request_id = 0;
+    for (pos, part) in parts.into_iter().enumerate() {
+        let flags = if pos == last {
+            MessageHeaderFlags::default()
+        } else {
+            MessageHeaderFlags::not_final()
+        };
+        let msg_id = send(part, request_id, flags)?;
+        if pos == 0 {
+            request_id = msg_id;
+        }
+    }
+    Ok(request_id)
+}
+
+// Size of a DataItem once encoded in a ChannelData message, in the session encoding
+fn encoded_size(schema: &MsgSchema, encoding: Encoding, item: &DataItem) -> Result<usize, Error> {
+    let value = to_value(ChannelData {
+        data: vec![item.clone()],
+    })?;
+    Ok(match encoding {
+        Encoding::Binary => schema
+            .serialize_message(CHANNELDATALOAD_CHANNELDATA, value)?
+            .len(),
+        Encoding::Json => schema
+            .serialize_message_json(CHANNELDATALOAD_CHANNELDATA, value)?
+            .to_string()
+            .len(),
+    })
+}
+
+#[test]
+fn test_encoded_size() {
+    let es = MsgSchema::new();
+
+    let item = DataItem {
+        channel_id: 1,
+        indexes: Vec::new(),
+        value: DataValue {
+            item: DataValueEnum::Double(34.1),
+        },
+        value_attributes: Vec::new(),
+    };
+
+    // Same item as test_roundtrip_channeldata in schema.rs
+    assert_eq!(encoded_size(&es, Encoding::Binary, &item).unwrap(), 14);
+
+    // Field names and all, as sent in a JSON session
+    let json = r#"{"data":[{"channelId":1,"indexes":[],"value":{"item":{"double":34.1}},"valueAttributes":[]}]}"#;
+    assert_eq!(
+        encoded_size(&es, Encoding::Json, &item).unwrap(),
+        json.len()
+    );
+}
+
+#[cfg(test)]
+fn test_item(channel_id: i64, depth: f64) -> DataItem {
+    DataItem {
+        channel_id,
+        indexes: vec![IndexValue {
+            item: Some(UnionLongDoublePassIndexedDepth::Double(depth)),
+        }],
+        value: DataValue {
+            item: DataValueEnum::Double(depth * 10.0),
+        },
+        value_attributes: Vec::new(),
+    }
+}
+
+#[test]
+fn test_push_batching() {
+    // Room for three items of 14 bytes per message
+    let mut producer = ChannelDataLoadProducer::with_limits(42, 10);
+
+    let mut sent = vec![];
+    for n in 0..7 {
+        if let Some(batch) = producer.queue(test_item(1, n as f64), 14).unwrap() {
+            sent.push(batch);
+        }
+    }
+    assert_eq!(
+        sent.iter().map(|batch| batch.len()).collect::<Vec<_>>(),
+        vec![3, 3]
+    );
+    assert_eq!(sent[1][0], test_item(1, 3.0));
+
+    // The last item waits for flush
+    assert_eq!(producer.take_buffer(), Some(vec![test_item(1, 6.0)]));
+    assert_eq!(producer.take_buffer(), None);
+
+    assert!(producer.queue(test_item(1, 0.0), 43).is_err());
+}
+
+#[test]
+fn test_replace_range_framing() {
+    let mut producer = ChannelDataLoadProducer::with_limits(1000, 2);
+    producer
+        .ids
+        .insert("eml:///witsml20.Channel(a)".to_string(), 5);
+
+    let interval = IndexInterval {
+        start_index: test_item(5, 0.0).indexes[0].clone(),
+        end_index: test_item(5, 10.0).indexes[0].clone(),
+        uom: "m".to_string(),
+        depth_datum: "".to_string(),
+    };
+    let data: Vec<DataItem> = (0..5).map(|n| test_item(5, n as f64)).collect();
+    let parts = producer
+        .replace_range_parts(interval.clone(), &["eml:///witsml20.Channel(a)"], data)
+        .unwrap();
+    assert_eq!(
+        parts.iter().map(|p| p.data.len()).collect::<Vec<_>>(),
+        vec![2, 2, 1]
+    );
+    assert!(parts.iter().all(|p| p.channel_ids == vec![5]));
+
+    // The first part starts the request, the others are correlated to it; only the last is final
+    let mut messages = vec![];
+    let mut msg_id = 100;
+    let request_id = send_multipart(parts, |part, correlation_id, flags| {
+        msg_id += 2;
+        messages.push((part.data.len(), correlation_id, flags.finalmsg));
+        Ok(msg_id)
+    })
+    .unwrap();
+    assert_eq!(request_id, 102);
+    assert_eq!(
+        messages,
+        vec![(2, 0, false), (2, 102, false), (1, 102, true)]
+    );
+
+    // An empty replacement is still one (final) message
+    let parts = producer
+        .replace_range_parts(interval, &["eml:///witsml20.Channel(a)"], vec![])
+        .unwrap();
+    assert_eq!(parts.len(), 1);
+    assert!(producer
+        .replace_range_parts(
+            parts[0].changed_interval.clone(),
+            &["eml:///unknown"],
+            vec![]
+        )
+        .is_err());
+}
+
+#[test]
+fn test_truncate_request() {
+    let mut producer = ChannelDataLoadProducer::with_limits(1000, 10);
+    producer
+        .ids
+        .insert("eml:///witsml20.Channel(a)".to_string(), 5);
+    let end = test_item(5, 42.0).indexes[0].clone();
+
+    let request = producer
+        .truncate_request(vec![("eml:///witsml20.Channel(a)", end.clone())])
+        .unwrap();
+    assert_eq!(
+        request.channels.get("eml:///witsml20.Channel(a)"),
+        Some(&TruncateInfo {
+            channel_id: 5,
+            new_end_index: end.clone(),
+        })
+    );
+    assert!(producer
+        .truncate_request(vec![("eml:///unknown", end)])
+        .is_err());
+}
+
+#[test]
+fn test_negative_limits() {
+    let (mut session, _peer) = crate::session::TestPeer::connect();
+    let capability = |name: &str| {
+        HashMap::from([(
+            name.to_string(),
+            DataValue {
+                item: DataValueEnum::Long(-1),
+            },
+        )])
+    };
+    session.open_session_msg.endpoint_capabilities = capability("MaxWebSocketMessagePayloadSize");
+    session
+        .open_session_msg
+        .supported_protocols
+        .push(SupportedProtocol {
+            protocol: Protocol::ChannelDataLoad as i32,
+            protocol_version: Version {
+                major: 1,
+                minor: 2,
+                revision: 0,
+                patch: 0,
+            },
+            role: "store".to_string(),
+            protocol_capabilities: capability("MaxRangeDataItemCount"),
+        });
+
+    // Invalid limits are ignored, rather than wrapping round to huge ones
+    let producer = ChannelDataLoadProducer::new(&session);
+    assert_eq!(producer.max_range_items, usize::MAX);
+    assert_eq!(producer.max_message_size, 16777216 - 1024);
+}
@@ -0,0 +1,436 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Typed channel indexes.
+// An IndexValue is a bare long, double or PassIndexedDepth; the IndexMetadataRecord of the channel says
+// what it means.  ChannelIndex carries that meaning, so indexes can be compared and ranges tested.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{error::Error, schema_gen::*};
+use std::cmp::Ordering;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Index {
+    DateTime(i64),    // Microseconds since the Unix epoch, UTC
+    ElapsedTime(i64), // In the uom of the index
+    Depth(f64),       // Measured or true vertical depth
+    PassIndexedDepth(PassIndexedDepth),
+    Scalar(f64), // Pressure, Temperature or Scalar
+}
+
+// An index value of a channel.  Ordered in the direction of the channel, so for a decreasing index
+// the "greater" value is the smaller number.  The uom is not converted (or compared).
+#[derive(Debug, Clone)]
+pub struct ChannelIndex {
+    pub index: Index,
+    pub uom: String,
+    pub direction: IndexDirection,
+}
+
+// A range of index values of a channel, in the channel direction (start <= end).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ChannelIndexInterval {
+    pub start: ChannelIndex,
+    pub end: ChannelIndex,
+}
+
+impl ChannelIndex {
+    pub fn new(
+        value: &UnionLongDoublePassIndexedDepth,
+        kind: &ChannelIndexKind,
+        direction: IndexDirection,
+        uom: &str,
+    ) -> Result<ChannelIndex, Error> {
+        use UnionLongDoublePassIndexedDepth::*;
+        let index = match (kind, value) {
+            (ChannelIndexKind::DateTime, Long(v)) => Index::DateTime(*v),
+            (ChannelIndexKind::ElapsedTime, Long(v)) => Index::ElapsedTime(*v),
+            (ChannelIndexKind::MeasuredDepth | ChannelIndexKind::TrueVerticalDepth, Double(v)) => {
+                Index::Depth(*v)
+            }
+            (ChannelIndexKind::MeasuredDepth | ChannelIndexKind::TrueVerticalDepth, Long(v)) => {
+                Index::Depth(*v as f64)
+            }
+            (ChannelIndexKind::PassIndexedDepth, PassIndexedDepth(v)) => {
+                Index::PassIndexedDepth(v.clone())
+            }
+            (
+                ChannelIndexKind::Pressure
+                | ChannelIndexKind::Temperature
+                | ChannelIndexKind::Scalar,
+                Double(v),
+            ) => Index::Scalar(*v),
+            (
+                ChannelIndexKind::Pressure
+                | ChannelIndexKind::Temperature
+                | ChannelIndexKind::Scalar,
+                Long(v),
+            ) => Index::Scalar(*v as f64),
+            _ => {
+                return Err(Error::Simple(format!(
+                    "{:?} is not a {:?} index",
+                    value, kind
+                )))
+            }
+        };
+        Ok(ChannelIndex {
+            index,
+            uom: uom.to_string(),
+            direction,
+        })
+    }
+
+    // Reads an index value of a channel with this index.  None if the value is null.
+    pub fn from_value(
+        value: &IndexValue,
+        metadata: &IndexMetadataRecord,
+    ) -> Result<Option<ChannelIndex>, Error> {
+        value
+            .item
+            .as_ref()
+            .map(|item| {
+                ChannelIndex::new(
+                    item,
+                    &metadata.index_kind,
+                    metadata.direction.clone(),
+                    &metadata.uom,
+                )
+            })
+            .transpose()
+    }
+
+    pub fn to_value(&self) -> IndexValue {
+        let item = match &self.index {
+            Index::DateTime(v) | Index::ElapsedTime(v) => UnionLongDoublePassIndexedDepth::Long(*v),
+            Index::Depth(v) | Index::Scalar(v) => UnionLongDoublePassIndexedDepth::Double(*v),
+            Index::PassIndexedDepth(v) => {
+                UnionLongDoublePassIndexedDepth::PassIndexedDepth(v.clone())
+            }
+        };
+        IndexValue { item: Some(item) }
+    }
+
+    pub fn from_system_time(time: SystemTime, direction: IndexDirection) -> ChannelIndex {
+        let micros = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => after.as_micros() as i64,
+            Err(before) => -(before.duration().as_micros() as i64),
+        };
+        ChannelIndex {
+            index: Index::DateTime(micros),
+            uom: String::new(),
+            direction,
+        }
+    }
+
+    // None unless this is a DateTime index.
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        match self.index {
+            Index::DateTime(v) if v >= 0 => UNIX_EPOCH.checked_add(Duration::from_micros(v as u64)),
+            Index::DateTime(v) => UNIX_EPOCH.checked_sub(Duration::from_micros(v.unsigned_abs())),
+            _ => None,
+        }
+    }
+
+    // Moves the index by delta, in microseconds for DateTime, otherwise in the uom of the index.
+    // Positive deltas move forward in the channel direction.
+    pub fn offset(&self, delta: f64) -> ChannelIndex {
+        let delta = match self.direction {
+            IndexDirection::Decreasing => -delta,
+            _ => delta,
+        };
+        let index = match &self.index {
+            Index::DateTime(v) => Index::DateTime(v + delta.round() as i64),
+            Index::ElapsedTime(v) => Index::ElapsedTime(v + delta.round() as i64),
+            Index::Depth(v) => Index::Depth(v + delta),
+            Index::Scalar(v) => Index::Scalar(v + delta),
+            Index::PassIndexedDepth(v) => {
+                let delta = match v.direction {
+                    PassDirection::Up => -delta,
+                    _ => delta,
+                };
+                Index::PassIndexedDepth(PassIndexedDepth {
+                    depth: v.depth + delta,
+                    ..v.clone()
+                })
+            }
+        };
+        ChannelIndex {
+            index,
+            uom: self.uom.clone(),
+            direction: self.direction.clone(),
+        }
+    }
+
+    // How far other is ahead of this index, in the units of offset.  None if they cannot be
+    // compared by distance (different kinds, or different passes).
+    pub fn distance_to(&self, other: &ChannelIndex) -> Option<f64> {
+        let distance = match (&self.index, &other.index) {
+            (Index::DateTime(a), Index::DateTime(b)) => (b - a) as f64,
+            (Index::ElapsedTime(a), Index::ElapsedTime(b)) => (b - a) as f64,
+            (Index::Depth(a), Index::Depth(b)) => b - a,
+            (Index::Scalar(a), Index::Scalar(b)) => b - a,
+            (Index::PassIndexedDepth(a), Index::PassIndexedDepth(b))
+                if a.pass == b.pass && a.direction == b.direction =>
+            {
+                match a.direction {
+                    PassDirection::Up => a.depth - b.depth,
+                    _ => b.depth - a.depth,
+                }
+            }
+            _ => return None,
+        };
+        Some(match self.direction {
+            IndexDirection::Decreasing => -distance,
+            _ => distance,
+        })
+    }
+
+    // Order of the values as numbers, before the channel direction is applied.
+    fn cmp_values(&self, other: &ChannelIndex) -> Ordering {
+        match (&self.index, &other.index) {
+            (Index::DateTime(a), Index::DateTime(b)) => a.cmp(b),
+            (Index::ElapsedTime(a), Index::ElapsedTime(b)) => a.cmp(b),
+            (Index::Depth(a), Index::Depth(b)) => a.total_cmp(b),
+            (Index::Scalar(a), Index::Scalar(b)) => a.total_cmp(b),
+            // By pass, then direction, then depth in the direction of travel: going up, the
+            // next value is shallower.
+            (Index::PassIndexedDepth(a), Index::PassIndexedDepth(b)) => a
+                .pass
+                .cmp(&b.pass)
+                .then_with(|| a.direction.cmp(&b.direction))
+                .then_with(|| match a.direction {
+                    PassDirection::Up => b.depth.total_cmp(&a.depth),
+                    _ => a.depth.total_cmp(&b.depth),
+                }),
+            // Different kinds of index have no common order; keep it total by kind.
+            (a, b) => index_rank(a).cmp(&index_rank(b)),
+        }
+    }
+}
+
+fn index_rank(index: &Index) -> u8 {
+    match index {
+        Index::DateTime(_) => 0,
+        Index::ElapsedTime(_) => 1,
+        Index::Depth(_) => 2,
+        Index::PassIndexedDepth(_) => 3,
+        Index::Scalar(_) => 4,
+    }
+}
+
+impl Ord for ChannelIndex {
+    // Unordered channels are taken as increasing.
+    fn cmp(&self, other: &ChannelIndex) -> Ordering {
+        let ordering = self.cmp_values(other);
+        match self.direction {
+            IndexDirection::Decreasing => ordering.reverse(),
+            _ => ordering,
+        }
+    }
+}
+
+impl PartialOrd for ChannelIndex {
+    fn partial_cmp(&self, other: &ChannelIndex) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ChannelIndex {
+    fn eq(&self, other: &ChannelIndex) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ChannelIndex {}
+
+impl ChannelIndexInterval {
+    // Puts start and end in the channel direction, whichever way round they are given.
+    pub fn new(start: ChannelIndex, end: ChannelIndex) -> ChannelIndexInterval {
+        if start <= end {
+            ChannelIndexInterval { start, end }
+        } else {
+            ChannelIndexInterval {
+                start: end,
+                end: start,
+            }
+        }
+    }
+
+    // Reads the interval of a channel with this index.  The interval uom is used if it has one.
+    pub fn from_interval(
+        interval: &IndexInterval,
+        metadata: &IndexMetadataRecord,
+    ) -> Result<Option<ChannelIndexInterval>, Error> {
+        let uom = if interval.uom.is_empty() {
+            &metadata.uom
+        } else {
+            &interval.uom
+        };
+        let read = |value: &IndexValue| {
+            value
+                .item
+                .as_ref()
+                .map(|item| {
+                    ChannelIndex::new(item, &metadata.index_kind, metadata.direction.clone(), uom)
+                })
+                .transpose()
+        };
+        match (read(&interval.start_index)?, read(&interval.end_index)?) {
+            (Some(start), Some(end)) => Ok(Some(ChannelIndexInterval::new(start, end))),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn to_interval(&self) -> IndexInterval {
+        IndexInterval {
+            start_index: self.start.to_value(),
+            end_index: self.end.to_value(),
+            uom: self.start.uom.clone(),
+            depth_datum: String::new(),
+        }
+    }
+
+    // Inclusive at both ends.
+    pub fn contains(&self, index: &ChannelIndex) -> bool {
+        self.start <= *index && *index <= self.end
+    }
+
+    pub fn overlaps(&self, other: &ChannelIndexInterval) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    pub fn intersection(&self, other: &ChannelIndexInterval) -> Option<ChannelIndexInterval> {
+        if !self.overlaps(other) {
+            return None;
+        }
+        Some(ChannelIndexInterval {
+            start: self.start.clone().max(other.start.clone()),
+            end: self.end.clone().min(other.end.clone()),
+        })
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl ChannelIndex {
+    pub fn from_datetime(
+        time: chrono::DateTime<chrono::Utc>,
+        direction: IndexDirection,
+    ) -> ChannelIndex {
+        ChannelIndex {
+            index: Index::DateTime(time.timestamp_micros()),
+            uom: String::new(),
+            direction,
+        }
+    }
+
+    // None unless this is a DateTime index.
+    pub fn to_datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        use chrono::TimeZone;
+        match self.index {
+            Index::DateTime(v) => chrono::Utc
+                .timestamp_opt(
+                    v.div_euclid(1_000_000),
+                    (v.rem_euclid(1_000_000) * 1000) as u32,
+                )
+                .single(),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_channel_index_ordering() {
+    let depth = |v: f64, direction: IndexDirection| ChannelIndex {
+        index: Index::Depth(v),
+        uom: "m".to_string(),
+        direction,
+    };
+
+    assert!(depth(100.0, IndexDirection::Increasing) < depth(200.0, IndexDirection::Increasing));
+    assert!(depth(100.0, IndexDirection::Decreasing) > depth(200.0, IndexDirection::Decreasing));
+
+    // Decreasing interval given either way round
+    let interval = ChannelIndexInterval::new(
+        depth(100.0, IndexDirection::Decreasing),
+        depth(200.0, IndexDirection::Decreasing),
+    );
+    assert_eq!(interval.start.index, Index::Depth(200.0));
+    assert!(interval.contains(&depth(150.0, IndexDirection::Decreasing)));
+    assert!(!interval.contains(&depth(250.0, IndexDirection::Decreasing)));
+    assert_eq!(
+        depth(100.0, IndexDirection::Decreasing).offset(10.0).index,
+        Index::Depth(90.0)
+    );
+
+    let other = ChannelIndexInterval::new(
+        depth(150.0, IndexDirection::Decreasing),
+        depth(50.0, IndexDirection::Decreasing),
+    );
+    assert!(interval.overlaps(&other));
+    let both = interval.intersection(&other).unwrap();
+    assert_eq!(both.start.index, Index::Depth(150.0));
+    assert_eq!(both.end.index, Index::Depth(100.0));
+
+    // Passes in order, and depth decreasing in an upward pass
+    let pass = |pass: i64, direction: PassDirection, depth: f64| {
+        let value = UnionLongDoublePassIndexedDepth::PassIndexedDepth(PassIndexedDepth {
+            pass,
+            direction,
+            depth,
+        });
+        ChannelIndex::new(
+            &value,
+            &ChannelIndexKind::PassIndexedDepth,
+            IndexDirection::Increasing,
+            "m",
+        )
+        .unwrap()
+    };
+    assert!(pass(1, PassDirection::Down, 900.0) < pass(2, PassDirection::Up, 1000.0));
+    assert!(pass(2, PassDirection::Up, 1000.0) < pass(2, PassDirection::Up, 900.0));
+    assert_eq!(
+        pass(2, PassDirection::Up, 1000.0).distance_to(&pass(2, PassDirection::Up, 900.0)),
+        Some(100.0)
+    );
+
+    // Time index and the wrong kind of value
+    let time = ChannelIndex::new(
+        &UnionLongDoublePassIndexedDepth::Long(1_500_000),
+        &ChannelIndexKind::DateTime,
+        IndexDirection::Increasing,
+        "",
+    )
+    .unwrap();
+    assert_eq!(
+        time.to_system_time(),
+        Some(UNIX_EPOCH + Duration::from_millis(1500))
+    );
+    assert!(ChannelIndex::new(
+        &UnionLongDoublePassIndexedDepth::Double(1.5),
+        &ChannelIndexKind::DateTime,
+        IndexDirection::Increasing,
+        "",
+    )
+    .is_err());
+}
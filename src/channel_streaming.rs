@@ -0,0 +1,364 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Channel Streaming (Protocol 1) - Simple Streamer.
+// The producer announces its channels with ChannelMetadata, then pushes ChannelData.  There is no subscription
+// management; the consumer only starts and stops the stream.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    channel_subscribe::ChannelSample, error::Error, headerflags::*, schema::*, schema_gen::*,
+    session::Session,
+};
+use apache_avro::{from_value, types::Value};
+#[allow(unused_imports)]
+use log::{info, trace, warn};
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StreamEvent {
+    Metadata(Vec<ChannelMetadataRecord>),
+    Data(Vec<ChannelSample>),
+    Truncated(Vec<TruncateInfo>),
+    // Any message that is not a Channel Streaming message, for the caller to handle.
+    Other(MessageHeader, Value),
+}
+
+// ------------------------------------------------------
+// Consumer
+// ------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct ChannelStreamingConsumer {
+    channels: HashMap<i64, ChannelMetadataRecord>,
+}
+
+impl ChannelStreamingConsumer {
+    pub fn new() -> ChannelStreamingConsumer {
+        ChannelStreamingConsumer::default()
+    }
+
+    pub fn channel(&self, channel_id: i64) -> Option<&ChannelMetadataRecord> {
+        self.channels.get(&channel_id)
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = &ChannelMetadataRecord> {
+        self.channels.values()
+    }
+
+    // Asks the producer to start.  It replies with ChannelMetadata, followed by ChannelData.
+    pub fn start(&mut self, session: &mut Session) -> Result<(), Error> {
+        session.send_message(
+            StartStreaming {},
+            CHANNELSTREAMING_STARTSTREAMING,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+        Ok(())
+    }
+
+    pub fn stop(&mut self, session: &mut Session) -> Result<(), Error> {
+        session.send_message(
+            StopStreaming {},
+            CHANNELSTREAMING_STOPSTREAMING,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+        Ok(())
+    }
+
+    pub fn handle_message(
+        &mut self,
+        msg_hdr: MessageHeader,
+        msg_body: Value,
+    ) -> Result<StreamEvent, Error> {
+        match msg_hdr.msgtype() {
+            CHANNELSTREAMING_CHANNELMETADATA => {
+                let metadata = from_value::<ChannelMetadata>(&msg_body)?;
+                for channel in &metadata.channels {
+                    self.channels.insert(channel.id, channel.clone());
+                }
+                Ok(StreamEvent::Metadata(metadata.channels))
+            }
+            CHANNELSTREAMING_CHANNELDATA => {
+                let data = from_value::<ChannelData>(&msg_body)?;
+                Ok(StreamEvent::Data(self.resolve(data.data)))
+            }
+            CHANNELSTREAMING_TRUNCATECHANNELS_CS => {
+                let truncate = from_value::<TruncateChannelsCs>(&msg_body)?;
+                Ok(StreamEvent::Truncated(truncate.channels))
+            }
+            _ => Ok(StreamEvent::Other(msg_hdr, msg_body)),
+        }
+    }
+
+    // Blocking iterator over incoming events.
+    pub fn events<'a>(&'a mut self, session: &'a mut Session) -> StreamEvents<'a> {
+        StreamEvents {
+            consumer: self,
+            session,
+        }
+    }
+
+    fn resolve(&self, data: Vec<DataItem>) -> Vec<ChannelSample> {
+        data.into_iter()
+            .map(|item| {
+                let (uri, channel_name) = match self.channels.get(&item.channel_id) {
+                    Some(channel) => (channel.uri.clone(), channel.channel_name.clone()),
+                    None => {
+                        warn!("Data received for unannounced channel {}", item.channel_id);
+                        (String::new(), String::new())
+                    }
+                };
+                ChannelSample {
+                    channel_id: item.channel_id,
+                    uri,
+                    channel_name,
+                    indexes: item.indexes,
+                    value: item.value,
+                    value_attributes: item.value_attributes,
+                }
+            })
+            .collect()
+    }
+}
+
+pub struct StreamEvents<'a> {
+    consumer: &'a mut ChannelStreamingConsumer,
+    session: &'a mut Session,
+}
+
+impl<'a> Iterator for StreamEvents<'a> {
+    type Item = Result<StreamEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (msg_hdr, msg_body) = match self.session.read_message() {
+            Ok(msg) => msg,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(self.consumer.handle_message(msg_hdr, msg_body))
+    }
+}
+
+// ------------------------------------------------------
+// Producer
+// ------------------------------------------------------
+#[derive(Debug, Default)]
+pub struct ChannelStreamingProducer {
+    channels: Vec<ChannelMetadataRecord>,
+    streaming: bool, // Between the consumer's StartStreaming and StopStreaming
+}
+
+impl ChannelStreamingProducer {
+    // The channels this producer streams.  Their ids are the ones used in the DataItems.
+    pub fn new(channels: Vec<ChannelMetadataRecord>) -> ChannelStreamingProducer {
+        ChannelStreamingProducer {
+            channels,
+            streaming: false,
+        }
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
+    // Adds channels.  If already streaming they are announced to the consumer straight away.
+    pub fn add_channels(
+        &mut self,
+        session: &mut Session,
+        channels: Vec<ChannelMetadataRecord>,
+    ) -> Result<(), Error> {
+        if self.streaming {
+            session.send_message(
+                ChannelMetadata {
+                    channels: channels.clone(),
+                },
+                CHANNELSTREAMING_CHANNELMETADATA,
+                0,
+                MessageHeaderFlags::default(),
+                None,
+            )?;
+        }
+        self.channels.extend(channels);
+        Ok(())
+    }
+
+    // Handles StartStreaming / StopStreaming from the consumer.  On start, the channels are announced.
+    // Returns false for messages that are not for the producer.
+    pub fn handle_message(
+        &mut self,
+        session: &mut Session,
+        msg_hdr: &MessageHeader,
+    ) -> Result<bool, Error> {
+        match msg_hdr.msgtype() {
+            CHANNELSTREAMING_STARTSTREAMING => {
+                session.send_message(
+                    ChannelMetadata {
+                        channels: self.channels.clone(),
+                    },
+                    CHANNELSTREAMING_CHANNELMETADATA,
+                    msg_hdr.message_id,
+                    MessageHeaderFlags::default(),
+                    None,
+                )?;
+                self.streaming = true;
+                Ok(true)
+            }
+            CHANNELSTREAMING_STOPSTREAMING => {
+                self.streaming = false;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    // Sends data for announced channels.  Fails while the consumer has not started the stream, so the
+    // caller can hold on to the data until it has.
+    pub fn send_data(&mut self, session: &mut Session, data: Vec<DataItem>) -> Result<(), Error> {
+        self.check_data(&data)?;
+
+        session.send_message(
+            ChannelData { data },
+            CHANNELSTREAMING_CHANNELDATA,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+        Ok(())
+    }
+
+    fn check_streaming(&self, what: &str) -> Result<(), Error> {
+        if !self.streaming {
+            return Err(Error::Simple(format!(
+                "Consumer has not started streaming, {} not sent",
+                what
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_data(&self, data: &[DataItem]) -> Result<(), Error> {
+        self.check_streaming(&format!("{} DataItems", data.len()))?;
+
+        for item in data {
+            if !self.channels.iter().any(|c| c.id == item.channel_id) {
+                return Err(Error::Simple(format!(
+                    "Channel {} has not been announced",
+                    item.channel_id
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Tells the consumer that channels have been truncated at a new end index.  Like send_data, fails while
+    // the consumer has not started the stream.
+    pub fn truncate_channels(
+        &mut self,
+        session: &mut Session,
+        channels: Vec<TruncateInfo>,
+    ) -> Result<(), Error> {
+        self.check_streaming(&format!("{} channel truncations", channels.len()))?;
+
+        session.send_message(
+            TruncateChannelsCs { channels },
+            CHANNELSTREAMING_TRUNCATECHANNELS_CS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_start_stop_roundtrip() {
+    let es = MsgSchema::new();
+
+    let encoded = es
+        .serialize_message(
+            CHANNELSTREAMING_STARTSTREAMING,
+            apache_avro::to_value(StartStreaming {}).unwrap(),
+        )
+        .unwrap();
+    assert_eq!(encoded.len(), 0);
+
+    let body = es
+        .deserialize_message(CHANNELSTREAMING_STOPSTREAMING, &mut encoded.as_slice())
+        .unwrap();
+    let _stop = from_value::<StopStreaming>(&body).unwrap();
+
+    assert_eq!(
+        es.msg_name(CHANNELSTREAMING_STARTSTREAMING).unwrap(),
+        "ChannelStreaming.StartStreaming"
+    );
+}
+
+#[test]
+fn test_send_before_start() {
+    let mut producer = ChannelStreamingProducer::new(vec![ChannelMetadataRecord {
+        uri: "eml:///witsml20.Channel(a)".to_string(),
+        id: 3,
+        indexes: vec![],
+        channel_name: "ROP".to_string(),
+        data_kind: ChannelDataKind::TypeDouble,
+        uom: "m/h".to_string(),
+        depth_datum: "".to_string(),
+        channel_class_uri: "".to_string(),
+        status: ActiveStatusKind::Active,
+        source: "".to_string(),
+        axis_vector_lengths: vec![],
+        attribute_metadata: vec![],
+        custom_data: HashMap::new(),
+    }]);
+    let item = |channel_id: i64| DataItem {
+        channel_id,
+        indexes: vec![],
+        value: DataValue {
+            item: DataValueEnum::Double(1.0),
+        },
+        value_attributes: vec![],
+    };
+
+    let truncate = || {
+        vec![TruncateInfo {
+            channel_id: 3,
+            new_end_index: IndexValue {
+                item: Some(UnionLongDoublePassIndexedDepth::Double(10.0)),
+            },
+        }]
+    };
+    let (mut session, _peer) = crate::session::TestPeer::connect();
+
+    // Nothing is dropped silently before the consumer starts the stream
+    assert!(producer.check_data(&[item(3)]).is_err());
+    assert!(producer
+        .truncate_channels(&mut session, truncate())
+        .is_err());
+
+    producer.streaming = true;
+    assert!(producer.check_data(&[item(3)]).is_ok());
+    assert!(producer.check_data(&[item(4)]).is_err());
+    assert!(producer.truncate_channels(&mut session, truncate()).is_ok());
+}
@@ -0,0 +1,473 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Channel Subscribe (Protocol 21) - Customer (consumer) side.
+// Fetches channel metadata, subscribes to channels, and maps streamed DataItems back to their channels.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error,
+    headerflags::*,
+    schema::*,
+    schema_gen::*,
+    session::{MapResponse, Session},
+};
+use apache_avro::{from_value, types::Value};
+#[allow(unused_imports)]
+use log::{info, trace, warn};
+use std::collections::{HashMap, HashSet};
+
+// A single DataItem, resolved against the metadata of its channel.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ChannelSample {
+    pub channel_id: i64,
+    pub uri: String,
+    pub channel_name: String,
+    pub indexes: Vec<IndexValue>,
+    pub value: DataValue,
+    pub value_attributes: Vec<DataAttribute>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ChannelEvent {
+    Data(Vec<ChannelSample>),
+    RangeReplaced {
+        change_time: i64,
+        channel_ids: Vec<i64>,
+        changed_interval: IndexInterval,
+        data: Vec<ChannelSample>,
+    },
+    Truncated(ChannelsTruncated),
+    SubscriptionsStopped {
+        reason: String,
+        channel_ids: Vec<i64>,
+    },
+    // Any message that is not a Channel Subscribe notification, for the caller to handle.
+    Other(MessageHeader, Value),
+}
+
+// Where a subscription starts streaming from.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SubscribeStart {
+    Now,               // Only new data
+    Index(IndexValue), // Data from this index onwards
+    LatestCount(i32),  // The last n index values, then new data
+}
+
+#[derive(Debug, Default)]
+pub struct ChannelSubscribeConsumer {
+    channels: HashMap<i64, ChannelMetadataRecord>,
+    subscribed: HashSet<i64>,
+}
+
+impl ChannelSubscribeConsumer {
+    pub fn new() -> ChannelSubscribeConsumer {
+        ChannelSubscribeConsumer::default()
+    }
+
+    // Registers channel metadata obtained elsewhere (eg. from a previous session).
+    pub fn add_channel(&mut self, metadata: ChannelMetadataRecord) {
+        self.channels.insert(metadata.id, metadata);
+    }
+
+    pub fn channel(&self, channel_id: i64) -> Option<&ChannelMetadataRecord> {
+        self.channels.get(&channel_id)
+    }
+
+    pub fn channel_by_uri(&self, uri: &str) -> Option<&ChannelMetadataRecord> {
+        self.channels.values().find(|channel| channel.uri == uri)
+    }
+
+    pub fn subscribed(&self) -> impl Iterator<Item = &i64> {
+        self.subscribed.iter()
+    }
+
+    // Fetches the metadata for the channel URIs, and keeps it for resolving channel ids.
+    pub fn get_channel_metadata(
+        &mut self,
+        session: &mut Session,
+        uris: &[&str],
+    ) -> Result<MapResponse<ChannelMetadataRecord>, Error> {
+        let request = GetChannelMetadata {
+            uris: uris
+                .iter()
+                .map(|uri| (uri.to_string(), uri.to_string()))
+                .collect(),
+        };
+        let msg_id = session.send_message(
+            request,
+            CHANNELSUBSCRIBE_GETCHANNELMETADATA,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let response = session.read_map_response(
+            msg_id,
+            CHANNELSUBSCRIBE_GETCHANNELMETADATARESPONSE,
+            |body| Ok(from_value::<GetChannelMetadataResponse>(body)?.metadata),
+        )?;
+
+        for metadata in response.success.values() {
+            self.channels.insert(metadata.id, metadata.clone());
+        }
+        Ok(response)
+    }
+
+    // Subscribes to channels whose metadata is known.  Keys of the result are the channel ids.
+    pub fn subscribe(
+        &mut self,
+        session: &mut Session,
+        channel_ids: &[i64],
+        start: SubscribeStart,
+        data_changes: bool,
+    ) -> Result<MapResponse<String>, Error> {
+        let mut channels: HashMap<String, ChannelSubscribeInfo> = HashMap::new();
+        for channel_id in channel_ids {
+            if !self.channels.contains_key(channel_id) {
+                return Err(Error::Simple(format!(
+                    "No metadata for channel {}, call get_channel_metadata first",
+                    channel_id
+                )));
+            }
+
+            let (start_index, request_latest_index_count) = match &start {
+                SubscribeStart::Now => (IndexValue { item: None }, None),
+                SubscribeStart::Index(index) => (index.clone(), None),
+                SubscribeStart::LatestCount(count) => (IndexValue { item: None }, Some(*count)),
+            };
+            channels.insert(
+                channel_id.to_string(),
+                ChannelSubscribeInfo {
+                    channel_id: *channel_id,
+                    start_index,
+                    data_changes,
+                    request_latest_index_count,
+                },
+            );
+        }
+
+        let msg_id = session.send_message(
+            SubscribeChannels { channels },
+            CHANNELSUBSCRIBE_SUBSCRIBECHANNELS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let response = session.read_map_response(
+            msg_id,
+            CHANNELSUBSCRIBE_SUBSCRIBECHANNELSRESPONSE,
+            |body| Ok(from_value::<SubscribeChannelsResponse>(body)?.success),
+        )?;
+
+        for key in response.success.keys() {
+            if let Ok(channel_id) = key.parse::<i64>() {
+                self.subscribed.insert(channel_id);
+            }
+        }
+        Ok(response)
+    }
+
+    // Stops streaming for the channels.  The store confirms with SubscriptionsStopped.  Results are keyed as
+    // in the request (the channel id as a string); channels in 'errors' are still subscribed.
+    pub fn unsubscribe(
+        &mut self,
+        session: &mut Session,
+        channel_ids: &[i64],
+    ) -> Result<MapResponse<i64>, Error> {
+        let request = UnsubscribeChannels {
+            channel_ids: channel_ids.iter().map(|id| (id.to_string(), *id)).collect(),
+        };
+        let msg_id = session.send_message(
+            request,
+            CHANNELSUBSCRIBE_UNSUBSCRIBECHANNELS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let response =
+            session.read_map_response(msg_id, CHANNELSUBSCRIBE_SUBSCRIPTIONSSTOPPED, |body| {
+                Ok(from_value::<SubscriptionsStopped>(body)?.channel_ids)
+            })?;
+
+        for channel_id in response.success.values() {
+            self.subscribed.remove(channel_id);
+        }
+        Ok(response)
+    }
+
+    // Reads historical data for index ranges of subscribed or unsubscribed channels.
+    pub fn get_ranges(
+        &mut self,
+        session: &mut Session,
+        channel_ranges: Vec<ChannelRangeInfo>,
+    ) -> Result<Vec<ChannelSample>, Error> {
+        let request = GetRanges {
+            request_uuid: *uuid::Uuid::new_v4().as_bytes(),
+            channel_ranges,
+        };
+        let msg_id = session.send_message(
+            request,
+            CHANNELSUBSCRIBE_GETRANGES,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let mut samples = vec![];
+        loop {
+            let (msg_hdr, msg_body) = session.read_response(msg_id)?;
+            if msg_hdr.msgtype() != CHANNELSUBSCRIBE_GETRANGESRESPONSE {
+                return Err(Error::UnexpectedMessage(
+                    msg_hdr.protocol,
+                    msg_hdr.message_type,
+                ));
+            }
+            let response = from_value::<GetRangesResponse>(&msg_body)?;
+            samples.extend(self.resolve(response.data));
+            if msg_hdr.get_flags().finalmsg {
+                return Ok(samples);
+            }
+        }
+    }
+
+    // Change annotations per channel, keyed by the request keys.
+    pub fn get_change_annotations(
+        &mut self,
+        session: &mut Session,
+        channels: HashMap<String, ChannelChangeRequestInfo>,
+        latest_only: bool,
+    ) -> Result<MapResponse<ChangeResponseInfo>, Error> {
+        let msg_id = session.send_message(
+            GetChangeAnnotations {
+                channels,
+                latest_only,
+            },
+            CHANNELSUBSCRIBE_GETCHANGEANNOTATIONS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        session.read_map_response(
+            msg_id,
+            CHANNELSUBSCRIBE_GETCHANGEANNOTATIONSRESPONSE,
+            |body| Ok(from_value::<GetChangeAnnotationsResponse>(body)?.changes),
+        )
+    }
+
+    // Converts a message read from the session into a ChannelEvent.
+    pub fn handle_message(
+        &mut self,
+        msg_hdr: MessageHeader,
+        msg_body: Value,
+    ) -> Result<ChannelEvent, Error> {
+        match msg_hdr.msgtype() {
+            CHANNELSUBSCRIBE_CHANNELDATA => {
+                let data = from_value::<ChannelData>(&msg_body)?;
+                Ok(ChannelEvent::Data(self.resolve(data.data)))
+            }
+            CHANNELSUBSCRIBE_RANGEREPLACED => {
+                let replaced = from_value::<RangeReplaced>(&msg_body)?;
+                Ok(ChannelEvent::RangeReplaced {
+                    change_time: replaced.change_time,
+                    channel_ids: replaced.channel_ids,
+                    changed_interval: replaced.changed_interval,
+                    data: self.resolve(replaced.data),
+                })
+            }
+            CHANNELSUBSCRIBE_CHANNELSTRUNCATED => {
+                Ok(ChannelEvent::Truncated(from_value::<ChannelsTruncated>(
+                    &msg_body,
+                )?))
+            }
+            CHANNELSUBSCRIBE_SUBSCRIPTIONSSTOPPED => {
+                let stopped = from_value::<SubscriptionsStopped>(&msg_body)?;
+                let channel_ids: Vec<i64> = stopped.channel_ids.values().copied().collect();
+                for channel_id in &channel_ids {
+                    self.subscribed.remove(channel_id);
+                }
+                Ok(ChannelEvent::SubscriptionsStopped {
+                    reason: stopped.reason,
+                    channel_ids,
+                })
+            }
+            _ => Ok(ChannelEvent::Other(msg_hdr, msg_body)),
+        }
+    }
+
+    // Blocking iterator over incoming events.
+    pub fn events<'a>(&'a mut self, session: &'a mut Session) -> ChannelEvents<'a> {
+        ChannelEvents {
+            consumer: self,
+            session,
+        }
+    }
+
+    fn resolve(&self, data: Vec<DataItem>) -> Vec<ChannelSample> {
+        data.into_iter()
+            .map(|item| {
+                let (uri, channel_name) = match self.channels.get(&item.channel_id) {
+                    Some(channel) => (channel.uri.clone(), channel.channel_name.clone()),
+                    None => {
+                        warn!("Data received for unknown channel {}", item.channel_id);
+                        (String::new(), String::new())
+                    }
+                };
+                ChannelSample {
+                    channel_id: item.channel_id,
+                    uri,
+                    channel_name,
+                    indexes: item.indexes,
+                    value: item.value,
+                    value_attributes: item.value_attributes,
+                }
+            })
+            .collect()
+    }
+}
+
+pub struct ChannelEvents<'a> {
+    consumer: &'a mut ChannelSubscribeConsumer,
+    session: &'a mut Session,
+}
+
+impl<'a> Iterator for ChannelEvents<'a> {
+    type Item = Result<ChannelEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (msg_hdr, msg_body) = match self.session.read_message() {
+            Ok(msg) => msg,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(self.consumer.handle_message(msg_hdr, msg_body))
+    }
+}
+
+#[test]
+fn test_channel_data_resolve() {
+    let mut consumer = ChannelSubscribeConsumer::new();
+    consumer.add_channel(ChannelMetadataRecord {
+        uri: "eml:///witsml20.Channel(f8fd0d43-8a3b-4c5c-8e0f-4e7a7c0f8a31)".to_string(),
+        id: 7,
+        indexes: vec![IndexMetadataRecord {
+            index_kind: ChannelIndexKind::MeasuredDepth,
+            interval: IndexInterval {
+                start_index: IndexValue {
+                    item: Some(UnionLongDoublePassIndexedDepth::Double(0.0)),
+                },
+                end_index: IndexValue {
+                    item: Some(UnionLongDoublePassIndexedDepth::Double(100.0)),
+                },
+                uom: "m".to_string(),
+                depth_datum: "".to_string(),
+            },
+            direction: IndexDirection::Increasing,
+            name: "MD".to_string(),
+            uom: "m".to_string(),
+            depth_datum: "".to_string(),
+            index_property_kind_uri: "".to_string(),
+            filterable: true,
+        }],
+        channel_name: "ROP".to_string(),
+        data_kind: ChannelDataKind::TypeDouble,
+        uom: "m/h".to_string(),
+        depth_datum: "".to_string(),
+        channel_class_uri: "".to_string(),
+        status: ActiveStatusKind::Active,
+        source: "".to_string(),
+        axis_vector_lengths: vec![],
+        attribute_metadata: vec![],
+        custom_data: HashMap::new(),
+    });
+
+    let cd = ChannelData {
+        data: vec![DataItem {
+            channel_id: 7,
+            indexes: vec![IndexValue {
+                item: Some(UnionLongDoublePassIndexedDepth::Double(34.1)),
+            }],
+            value: DataValue {
+                item: DataValueEnum::Double(12.5),
+            },
+            value_attributes: Vec::new(),
+        }],
+    };
+    let body = roundtrip(CHANNELSUBSCRIBE_CHANNELDATA, cd);
+    let hdr = MessageHeader {
+        protocol: CHANNELSUBSCRIBE_CHANNELDATA.0 as i32,
+        message_type: CHANNELSUBSCRIBE_CHANNELDATA.1 as i32,
+        correlation_id: 0,
+        message_id: 1,
+        message_flags: MSG_FLAG_FINAL,
+    };
+
+    match consumer.handle_message(hdr, body).unwrap() {
+        ChannelEvent::Data(samples) => {
+            assert_eq!(samples.len(), 1);
+            assert_eq!(samples[0].channel_name, "ROP");
+            assert_eq!(samples[0].value.item, DataValueEnum::Double(12.5));
+        }
+        other => panic!("Unexpected event {:?}", other),
+    }
+}
+
+#[test]
+fn test_unsubscribe_errors() {
+    let (mut session, mut peer) = crate::session::TestPeer::connect();
+    let mut consumer = ChannelSubscribeConsumer::new();
+    consumer.subscribed.extend([1, 2]);
+
+    // The client's first request is MessageID 2.  Channel 2 fails.
+    peer.send(
+        SubscriptionsStopped {
+            reason: "Unsubscribed".to_string(),
+            channel_ids: HashMap::from([("1".to_string(), 1)]),
+        },
+        CHANNELSUBSCRIBE_SUBSCRIPTIONSSTOPPED,
+        2,
+        false,
+    );
+    peer.send(
+        ProtocolException {
+            error: None,
+            errors: HashMap::from([(
+                "2".to_string(),
+                ErrorInfo {
+                    message: "Not subscribed".to_string(),
+                    code: 11,
+                },
+            )]),
+        },
+        CORE_PROTOCOLEXCEPTION,
+        2,
+        true,
+    );
+
+    let response = consumer.unsubscribe(&mut session, &[1, 2]).unwrap();
+    assert_eq!(response.success.get("1"), Some(&1));
+    assert_eq!(response.errors.get("2").map(|e| e.code), Some(11));
+    assert_eq!(consumer.subscribed().collect::<Vec<_>>(), vec![&2]);
+}
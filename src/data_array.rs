@@ -0,0 +1,670 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Data Array (Protocol 9) - Customer side.
+// Arrays larger than the store accepts in one message are split into tiles, transferred as subarrays,
+// and reassembled into a single row-major array.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error,
+    headerflags::*,
+    schema::*,
+    schema_gen::*,
+    session::{MapResponse, Session},
+};
+use apache_avro::{from_value, types::Value};
+use std::collections::HashMap;
+
+// Assumed encoded size of one string element, as strings have no fixed size.
+const STRING_ELEMENT_SIZE: usize = 64;
+
+#[derive(Debug)]
+pub struct DataArrayClient {
+    max_array_size: usize, // Upper limit on the encoded size of the array data in one message
+}
+
+impl DataArrayClient {
+    // Limits are taken from what the store advertised in OpenSession.
+    pub fn new(session: &Session) -> DataArrayClient {
+        let mut max_array_size = session.max_message_size();
+        if let Some(size) = session
+            .open_session_msg
+            .protocol_capability(
+                Protocol::DataArray,
+                ProtocolCapabilityKind::MaxDataArraySize,
+            )
+            .and_then(|size| usize::try_from(size).ok())
+        {
+            max_array_size = max_array_size.min(size);
+        }
+
+        DataArrayClient {
+            max_array_size: max_array_size.max(1),
+        }
+    }
+
+    pub fn max_array_size(&self) -> usize {
+        self.max_array_size
+    }
+
+    pub fn get_metadata(
+        &self,
+        session: &mut Session,
+        data_arrays: HashMap<String, DataArrayIdentifier>,
+    ) -> Result<MapResponse<DataArrayMetadata>, Error> {
+        let msg_id = session.send_message(
+            GetDataArrayMetadata { data_arrays },
+            DATAARRAY_GETDATAARRAYMETADATA,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        session.read_map_response(msg_id, DATAARRAY_GETDATAARRAYMETADATARESPONSE, |body| {
+            Ok(from_value::<GetDataArrayMetadataResponse>(body)?.array_metadata)
+        })
+    }
+
+    // Reads a whole array.  If it is too large for one message it is read in tiles, using the store's
+    // preferred subarray dimensions where it has them.
+    pub fn get_array(
+        &self,
+        session: &mut Session,
+        uid: &DataArrayIdentifier,
+    ) -> Result<DataArray, Error> {
+        let metadata = single(self.get_metadata(session, single_request(uid.clone()))?)?;
+        let dimensions = to_usize(&metadata.dimensions)?;
+        let element_size = element_size(&metadata.transport_array_type);
+
+        if dimensions.iter().product::<usize>() * element_size <= self.max_array_size {
+            let msg_id = session.send_message(
+                GetDataArrays {
+                    data_arrays: single_request(uid.clone()),
+                },
+                DATAARRAY_GETDATAARRAYS,
+                0,
+                MessageHeaderFlags::default(),
+                None,
+            )?;
+            let response =
+                session.read_map_response(msg_id, DATAARRAY_GETDATAARRAYSRESPONSE, |body| {
+                    data_arrays_from_value(body, "dataArrays")
+                })?;
+            return single(response);
+        }
+
+        let tile = tile_dimensions(
+            &dimensions,
+            &metadata.preferred_subarray_dimensions,
+            element_size,
+            self.max_array_size,
+        );
+        let tiles = tiles(&dimensions, &tile);
+        let mut data = empty_array(&metadata.transport_array_type, dimensions.iter().product());
+
+        for batch in self.batches(&tiles, element_size) {
+            let request = batch
+                .iter()
+                .map(|pos| {
+                    let (starts, counts) = &tiles[*pos];
+                    (
+                        pos.to_string(),
+                        GetDataSubarraysType {
+                            uid: uid.clone(),
+                            starts: starts.iter().map(|s| *s as i64).collect(),
+                            counts: counts.iter().map(|c| *c as i64).collect(),
+                        },
+                    )
+                })
+                .collect();
+            let msg_id = session.send_message(
+                GetDataSubarrays {
+                    data_subarrays: request,
+                },
+                DATAARRAY_GETDATASUBARRAYS,
+                0,
+                MessageHeaderFlags::default(),
+                None,
+            )?;
+            let response =
+                session.read_map_response(msg_id, DATAARRAY_GETDATASUBARRAYSRESPONSE, |body| {
+                    data_arrays_from_value(body, "dataSubarrays")
+                })?;
+
+            for (key, subarray) in check_errors(response)? {
+                let (starts, counts) = key
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|pos| tiles.get(pos))
+                    .ok_or_else(|| {
+                        Error::Simple(format!("Unknown subarray in response: {}", key))
+                    })?;
+                scatter(&mut data, &dimensions, starts, counts, subarray.data.item)?;
+            }
+        }
+
+        Ok(DataArray {
+            dimensions: metadata.dimensions,
+            data: AnyArray { item: data },
+        })
+    }
+
+    // Writes a whole array.  If it is too large for one message, the array is created uninitialized
+    // and then written in tiles.
+    pub fn put_array(
+        &self,
+        session: &mut Session,
+        uid: &DataArrayIdentifier,
+        array: DataArray,
+    ) -> Result<(), Error> {
+        let dimensions = to_usize(&array.dimensions)?;
+        let length = array_len(&array.data.item);
+        if dimensions.iter().product::<usize>() != length {
+            return Err(Error::Simple(format!(
+                "Array of {} elements does not match dimensions {:?}",
+                length, array.dimensions
+            )));
+        }
+
+        let transport_array_type = transport_type(&array.data.item);
+        let element_size = element_size(&transport_array_type);
+
+        if length * element_size <= self.max_array_size {
+            let msg_id = session.send_message(
+                PutDataArrays {
+                    data_arrays: single_request(PutDataArraysType {
+                        uid: uid.clone(),
+                        array,
+                        custom_data: HashMap::new(),
+                    }),
+                },
+                DATAARRAY_PUTDATAARRAYS,
+                0,
+                MessageHeaderFlags::default(),
+                None,
+            )?;
+            let response =
+                session.read_map_response(msg_id, DATAARRAY_PUTDATAARRAYSRESPONSE, |body| {
+                    Ok(from_value::<PutDataArraysResponse>(body)?.success)
+                })?;
+            check_errors(response)?;
+            return Ok(());
+        }
+
+        let tile = tile_dimensions(&dimensions, &[], element_size, self.max_array_size);
+        let msg_id = session.send_message(
+            PutUninitializedDataArrays {
+                data_arrays: single_request(PutUninitializedDataArrayType {
+                    uid: uid.clone(),
+                    metadata: DataArrayMetadata {
+                        dimensions: array.dimensions.clone(),
+                        preferred_subarray_dimensions: tile.iter().map(|t| *t as i64).collect(),
+                        logical_array_type: logical_type(&transport_array_type),
+                        transport_array_type,
+                        store_last_write: 0,
+                        store_created: 0,
+                        custom_data: HashMap::new(),
+                    },
+                }),
+            },
+            DATAARRAY_PUTUNINITIALIZEDDATAARRAYS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+        let response = session.read_map_response(
+            msg_id,
+            DATAARRAY_PUTUNINITIALIZEDDATAARRAYSRESPONSE,
+            |body| Ok(from_value::<PutUninitializedDataArraysResponse>(body)?.success),
+        )?;
+        check_errors(response)?;
+
+        let tiles = tiles(&dimensions, &tile);
+        for batch in self.batches(&tiles, element_size) {
+            let request = batch
+                .iter()
+                .map(|pos| {
+                    let (starts, counts) = &tiles[*pos];
+                    (
+                        pos.to_string(),
+                        PutDataSubarraysType {
+                            uid: uid.clone(),
+                            data: AnyArray {
+                                item: gather(&array.data.item, &dimensions, starts, counts),
+                            },
+                            starts: starts.iter().map(|s| *s as i64).collect(),
+                            counts: counts.iter().map(|c| *c as i64).collect(),
+                        },
+                    )
+                })
+                .collect();
+            let msg_id = session.send_message(
+                PutDataSubarrays {
+                    data_subarrays: request,
+                },
+                DATAARRAY_PUTDATASUBARRAYS,
+                0,
+                MessageHeaderFlags::default(),
+                None,
+            )?;
+            let response =
+                session.read_map_response(msg_id, DATAARRAY_PUTDATASUBARRAYSRESPONSE, |body| {
+                    Ok(from_value::<PutDataSubarraysResponse>(body)?.success)
+                })?;
+            check_errors(response)?;
+        }
+        Ok(())
+    }
+
+    // Groups tiles (by position) into requests that stay within the message size limit.
+    fn batches(&self, tiles: &[(Vec<usize>, Vec<usize>)], element_size: usize) -> Vec<Vec<usize>> {
+        let mut batches: Vec<Vec<usize>> = vec![];
+        let mut batch_size = 0;
+        for (pos, (_, counts)) in tiles.iter().enumerate() {
+            let size = counts.iter().product::<usize>() * element_size;
+            match batches.last_mut() {
+                Some(batch) if batch_size + size <= self.max_array_size => batch.push(pos),
+                _ => {
+                    batches.push(vec![pos]);
+                    batch_size = 0;
+                }
+            }
+            batch_size += size;
+        }
+        batches
+    }
+}
+
+fn single_request<T>(value: T) -> HashMap<String, T> {
+    HashMap::from([("0".to_string(), value)])
+}
+
+// The result for a single_request, or the error the store returned for it.
+fn single<T>(response: MapResponse<T>) -> Result<T, Error> {
+    check_errors(response)?
+        .remove("0")
+        .ok_or_else(|| Error::Simple("No result in response".to_string()))
+}
+
+fn check_errors<T>(response: MapResponse<T>) -> Result<HashMap<String, T>, Error> {
+    match response.errors.into_values().next() {
+        Some(error) => Err(Error::ProtocolException(error.code, error.message)),
+        None => Ok(response.success),
+    }
+}
+
+fn to_usize(dimensions: &[i64]) -> Result<Vec<usize>, Error> {
+    dimensions
+        .iter()
+        .map(|d| usize::try_from(*d).map_err(|_| Error::Simple(format!("Invalid dimension {}", d))))
+        .collect()
+}
+
+fn element_size(array_type: &AnyArrayType) -> usize {
+    match array_type {
+        AnyArrayType::ArrayOfBoolean | AnyArrayType::Bytes => 1,
+        AnyArrayType::ArrayOfInt | AnyArrayType::ArrayOfFloat => 4,
+        AnyArrayType::ArrayOfLong | AnyArrayType::ArrayOfDouble => 8,
+        AnyArrayType::ArrayOfString => STRING_ELEMENT_SIZE,
+    }
+}
+
+fn transport_type(array: &AnyArrayUnion) -> AnyArrayType {
+    match array {
+        AnyArrayUnion::ArrayOfBoolean(_) => AnyArrayType::ArrayOfBoolean,
+        AnyArrayUnion::ArrayOfInt(_) => AnyArrayType::ArrayOfInt,
+        AnyArrayUnion::ArrayOfLong(_) => AnyArrayType::ArrayOfLong,
+        AnyArrayUnion::ArrayOfFloat(_) => AnyArrayType::ArrayOfFloat,
+        AnyArrayUnion::ArrayOfDouble(_) => AnyArrayType::ArrayOfDouble,
+        AnyArrayUnion::ArrayOfString(_) => AnyArrayType::ArrayOfString,
+        AnyArrayUnion::Bytes(_) => AnyArrayType::Bytes,
+    }
+}
+
+fn logical_type(array_type: &AnyArrayType) -> AnyLogicalArrayType {
+    match array_type {
+        AnyArrayType::ArrayOfBoolean => AnyLogicalArrayType::ArrayOfBoolean,
+        AnyArrayType::ArrayOfInt => AnyLogicalArrayType::ArrayOfInt32Le,
+        AnyArrayType::ArrayOfLong => AnyLogicalArrayType::ArrayOfInt64Le,
+        AnyArrayType::ArrayOfFloat => AnyLogicalArrayType::ArrayOfFloat32Le,
+        AnyArrayType::ArrayOfDouble => AnyLogicalArrayType::ArrayOfDouble64Le,
+        AnyArrayType::ArrayOfString => AnyLogicalArrayType::ArrayOfString,
+        AnyArrayType::Bytes => AnyLogicalArrayType::ArrayOfUInt8,
+    }
+}
+
+fn array_len(array: &AnyArrayUnion) -> usize {
+    match array {
+        AnyArrayUnion::ArrayOfBoolean(a) => a.values.len(),
+        AnyArrayUnion::ArrayOfInt(a) => a.values.len(),
+        AnyArrayUnion::ArrayOfLong(a) => a.values.len(),
+        AnyArrayUnion::ArrayOfFloat(a) => a.values.len(),
+        AnyArrayUnion::ArrayOfDouble(a) => a.values.len(),
+        AnyArrayUnion::ArrayOfString(a) => a.values.len(),
+        AnyArrayUnion::Bytes(a) => a.len(),
+    }
+}
+
+fn empty_array(array_type: &AnyArrayType, length: usize) -> AnyArrayUnion {
+    match array_type {
+        AnyArrayType::ArrayOfBoolean => AnyArrayUnion::ArrayOfBoolean(ArrayOfBoolean {
+            values: vec![false; length],
+        }),
+        AnyArrayType::ArrayOfInt => AnyArrayUnion::ArrayOfInt(ArrayOfInt {
+            values: vec![0; length],
+        }),
+        AnyArrayType::ArrayOfLong => AnyArrayUnion::ArrayOfLong(ArrayOfLong {
+            values: vec![0; length],
+        }),
+        AnyArrayType::ArrayOfFloat => AnyArrayUnion::ArrayOfFloat(ArrayOfFloat {
+            values: vec![0.0; length],
+        }),
+        AnyArrayType::ArrayOfDouble => AnyArrayUnion::ArrayOfDouble(ArrayOfDouble {
+            values: vec![0.0; length],
+        }),
+        AnyArrayType::ArrayOfString => AnyArrayUnion::ArrayOfString(ArrayOfString {
+            values: vec![String::new(); length],
+        }),
+        AnyArrayType::Bytes => AnyArrayUnion::Bytes(vec![0; length]),
+    }
+}
+
+// Dimensions of the tiles an array is split into.  Starts from the preferred subarray dimensions
+// (or the whole array), then halves the slowest varying dimension until a tile fits in 'max_size'.
+fn tile_dimensions(
+    dimensions: &[usize],
+    preferred: &[i64],
+    element_size: usize,
+    max_size: usize,
+) -> Vec<usize> {
+    let mut tile: Vec<usize> =
+        if preferred.len() == dimensions.len() && preferred.iter().all(|p| *p > 0) {
+            preferred
+                .iter()
+                .zip(dimensions)
+                .map(|(p, d)| (*p as usize).min(*d))
+                .collect()
+        } else {
+            dimensions.to_vec()
+        };
+
+    while tile.iter().product::<usize>() * element_size > max_size {
+        match tile.iter_mut().find(|t| **t > 1) {
+            Some(t) => *t = t.div_ceil(2),
+            None => break,
+        }
+    }
+    tile
+}
+
+// Start and count of every tile covering the array, in row-major order.
+fn tiles(dimensions: &[usize], tile: &[usize]) -> Vec<(Vec<usize>, Vec<usize>)> {
+    let mut tiles = vec![];
+    if dimensions.contains(&0) {
+        return tiles;
+    }
+
+    let mut starts = vec![0; dimensions.len()];
+    loop {
+        let counts = starts
+            .iter()
+            .zip(tile)
+            .zip(dimensions)
+            .map(|((s, t), d)| (*t).min(d - s))
+            .collect();
+        tiles.push((starts.clone(), counts));
+
+        let mut axis = dimensions.len();
+        loop {
+            if axis == 0 {
+                return tiles;
+            }
+            axis -= 1;
+            starts[axis] += tile[axis];
+            if starts[axis] < dimensions[axis] {
+                break;
+            }
+            starts[axis] = 0;
+        }
+    }
+}
+
+// Calls 'copy(array_offset, tile_offset, length)' for each contiguous run of a tile within the array.
+fn for_each_run(
+    dimensions: &[usize],
+    starts: &[usize],
+    counts: &[usize],
+    mut copy: impl FnMut(usize, usize, usize),
+) {
+    let rank = dimensions.len();
+    if rank == 0 {
+        copy(0, 0, 1);
+        return;
+    }
+    if counts.contains(&0) {
+        return;
+    }
+
+    let run = counts[rank - 1];
+    let mut pos = vec![0; rank - 1]; // Position within the tile, excluding the fastest varying dimension
+    let mut tile_offset = 0;
+    loop {
+        let array_offset = dimensions.iter().zip(starts).enumerate().fold(
+            0,
+            |offset, (axis, (dimension, start))| {
+                offset * dimension + start + pos.get(axis).copied().unwrap_or(0)
+            },
+        );
+        copy(array_offset, tile_offset, run);
+        tile_offset += run;
+
+        let mut axis = rank - 1;
+        loop {
+            if axis == 0 {
+                return;
+            }
+            axis -= 1;
+            pos[axis] += 1;
+            if pos[axis] < counts[axis] {
+                break;
+            }
+            pos[axis] = 0;
+        }
+    }
+}
+
+fn copy_in<T: Clone>(
+    array: &mut [T],
+    dimensions: &[usize],
+    starts: &[usize],
+    counts: &[usize],
+    tile: &[T],
+) -> Result<(), Error> {
+    if tile.len() != counts.iter().product::<usize>() {
+        return Err(Error::Simple(format!(
+            "Subarray of {} elements does not match counts {:?}",
+            tile.len(),
+            counts
+        )));
+    }
+    for_each_run(dimensions, starts, counts, |a, t, len| {
+        array[a..a + len].clone_from_slice(&tile[t..t + len])
+    });
+    Ok(())
+}
+
+fn copy_out<T: Clone>(
+    array: &[T],
+    dimensions: &[usize],
+    starts: &[usize],
+    counts: &[usize],
+) -> Vec<T> {
+    let mut tile = Vec::with_capacity(counts.iter().product());
+    for_each_run(dimensions, starts, counts, |a, _, len| {
+        tile.extend_from_slice(&array[a..a + len])
+    });
+    tile
+}
+
+// Writes a subarray into its place in the full array.
+fn scatter(
+    array: &mut AnyArrayUnion,
+    dimensions: &[usize],
+    starts: &[usize],
+    counts: &[usize],
+    tile: AnyArrayUnion,
+) -> Result<(), Error> {
+    match (array, tile) {
+        (AnyArrayUnion::ArrayOfBoolean(a), AnyArrayUnion::ArrayOfBoolean(t)) => {
+            copy_in(&mut a.values, dimensions, starts, counts, &t.values)
+        }
+        (AnyArrayUnion::ArrayOfInt(a), AnyArrayUnion::ArrayOfInt(t)) => {
+            copy_in(&mut a.values, dimensions, starts, counts, &t.values)
+        }
+        (AnyArrayUnion::ArrayOfLong(a), AnyArrayUnion::ArrayOfLong(t)) => {
+            copy_in(&mut a.values, dimensions, starts, counts, &t.values)
+        }
+        (AnyArrayUnion::ArrayOfFloat(a), AnyArrayUnion::ArrayOfFloat(t)) => {
+            copy_in(&mut a.values, dimensions, starts, counts, &t.values)
+        }
+        (AnyArrayUnion::ArrayOfDouble(a), AnyArrayUnion::ArrayOfDouble(t)) => {
+            copy_in(&mut a.values, dimensions, starts, counts, &t.values)
+        }
+        (AnyArrayUnion::ArrayOfString(a), AnyArrayUnion::ArrayOfString(t)) => {
+            copy_in(&mut a.values, dimensions, starts, counts, &t.values)
+        }
+        (AnyArrayUnion::Bytes(a), AnyArrayUnion::Bytes(t)) => {
+            copy_in(a, dimensions, starts, counts, &t)
+        }
+        (_, tile) => Err(Error::Simple(format!(
+            "Subarray of type {:?} does not match the array type",
+            transport_type(&tile)
+        ))),
+    }
+}
+
+// Reads a subarray out of the full array.
+fn gather(
+    array: &AnyArrayUnion,
+    dimensions: &[usize],
+    starts: &[usize],
+    counts: &[usize],
+) -> AnyArrayUnion {
+    match array {
+        AnyArrayUnion::ArrayOfBoolean(a) => AnyArrayUnion::ArrayOfBoolean(ArrayOfBoolean {
+            values: copy_out(&a.values, dimensions, starts, counts),
+        }),
+        AnyArrayUnion::ArrayOfInt(a) => AnyArrayUnion::ArrayOfInt(ArrayOfInt {
+            values: copy_out(&a.values, dimensions, starts, counts),
+        }),
+        AnyArrayUnion::ArrayOfLong(a) => AnyArrayUnion::ArrayOfLong(ArrayOfLong {
+            values: copy_out(&a.values, dimensions, starts, counts),
+        }),
+        AnyArrayUnion::ArrayOfFloat(a) => AnyArrayUnion::ArrayOfFloat(ArrayOfFloat {
+            values: copy_out(&a.values, dimensions, starts, counts),
+        }),
+        AnyArrayUnion::ArrayOfDouble(a) => AnyArrayUnion::ArrayOfDouble(ArrayOfDouble {
+            values: copy_out(&a.values, dimensions, starts, counts),
+        }),
+        AnyArrayUnion::ArrayOfString(a) => AnyArrayUnion::ArrayOfString(ArrayOfString {
+            values: copy_out(&a.values, dimensions, starts, counts),
+        }),
+        AnyArrayUnion::Bytes(a) => AnyArrayUnion::Bytes(copy_out(a, dimensions, starts, counts)),
+    }
+}
+
+// The AnyArrayUnion visitor cannot tell the union branches apart, so array responses are read from the
+// Avro Value, where the branch index is known.
+fn data_arrays_from_value(body: &Value, name: &str) -> Result<HashMap<String, DataArray>, Error> {
+    match record_field(body, name)? {
+        Value::Map(arrays) => arrays
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), data_array_from_value(value)?)))
+            .collect(),
+        _ => Err(Error::Simple(format!("{} is not a map", name))),
+    }
+}
+
+fn data_array_from_value(value: &Value) -> Result<DataArray, Error> {
+    let dimensions = from_value::<Vec<i64>>(record_field(value, "dimensions")?)?;
+    let item = match record_field(record_field(value, "data")?, "item")? {
+        Value::Union(0, v) => AnyArrayUnion::ArrayOfBoolean(from_value(v)?),
+        Value::Union(1, v) => AnyArrayUnion::ArrayOfInt(from_value(v)?),
+        Value::Union(2, v) => AnyArrayUnion::ArrayOfLong(from_value(v)?),
+        Value::Union(3, v) => AnyArrayUnion::ArrayOfFloat(from_value(v)?),
+        Value::Union(4, v) => AnyArrayUnion::ArrayOfDouble(from_value(v)?),
+        Value::Union(5, v) => AnyArrayUnion::ArrayOfString(from_value(v)?),
+        Value::Union(6, v) => match v.as_ref() {
+            Value::Bytes(bytes) => AnyArrayUnion::Bytes(bytes.clone()),
+            _ => return Err(Error::Simple("AnyArray bytes expected".to_string())),
+        },
+        _ => return Err(Error::Simple("AnyArray union expected".to_string())),
+    };
+    Ok(DataArray {
+        dimensions,
+        data: AnyArray { item },
+    })
+}
+
+fn record_field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, Error> {
+    match value {
+        Value::Record(fields) => fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| Error::Simple(format!("Field {} not found", name))),
+        _ => Err(Error::Simple(format!("Record expected for field {}", name))),
+    }
+}
+
+#[test]
+fn test_subarray_tiling() {
+    let dimensions = vec![3, 5];
+    let array = AnyArrayUnion::ArrayOfDouble(ArrayOfDouble {
+        values: (0..15).map(|v| v as f64).collect(),
+    });
+
+    // 4 doubles per tile, so 2 x 5 halves to 1 x 5, then 1 x 3
+    let tile = tile_dimensions(&dimensions, &[2, 5], 8, 32);
+    assert_eq!(tile, vec![1, 3]);
+
+    let tiles = tiles(&dimensions, &tile);
+    assert_eq!(tiles.len(), 6);
+    assert_eq!(tiles[1], (vec![0, 3], vec![1, 2]));
+
+    let mut reassembled = empty_array(&AnyArrayType::ArrayOfDouble, 15);
+    for (starts, counts) in &tiles {
+        let subarray = gather(&array, &dimensions, starts, counts);
+        scatter(&mut reassembled, &dimensions, starts, counts, subarray).unwrap();
+    }
+    assert_eq!(reassembled, array);
+
+    let middle = gather(&array, &dimensions, &[1, 1], &[2, 2]);
+    assert_eq!(
+        middle,
+        AnyArrayUnion::ArrayOfDouble(ArrayOfDouble {
+            values: vec![6.0, 7.0, 11.0, 12.0]
+        })
+    );
+}
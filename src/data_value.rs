@@ -0,0 +1,446 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Conversions between DataValue and Rust values.
+//   let value: DataValue = 42i64.into();
+//   let count = i64::try_from(&value)?;
+// Reading a value only widens (eg. an Int reads as i64 or f64, but a Long does not read as i32).
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{error::Error, schema_gen::*};
+use std::fmt;
+
+impl DataValue {
+    pub fn null() -> DataValue {
+        DataValue {
+            item: DataValueEnum::Null,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.item == DataValueEnum::Null
+    }
+
+    // Reads the value as T, or None if it is Null.
+    pub fn get<'a, T>(&'a self) -> Result<Option<T>, Error>
+    where
+        T: TryFrom<&'a DataValue, Error = Error>,
+    {
+        if self.is_null() {
+            return Ok(None);
+        }
+        T::try_from(self).map(Some)
+    }
+
+    // Name of the union branch, for messages.
+    pub fn type_name(&self) -> &'static str {
+        match &self.item {
+            DataValueEnum::Null => "null",
+            DataValueEnum::Boolean(_) => "boolean",
+            DataValueEnum::Int(_) => "int",
+            DataValueEnum::Long(_) => "long",
+            DataValueEnum::Float(_) => "float",
+            DataValueEnum::Double(_) => "double",
+            DataValueEnum::String(_) => "string",
+            DataValueEnum::ArrayOfBoolean(_) => "ArrayOfBoolean",
+            DataValueEnum::ArrayOfNullableBoolean(_) => "ArrayOfNullableBoolean",
+            DataValueEnum::ArrayOfInt(_) => "ArrayOfInt",
+            DataValueEnum::ArrayOfNullableInt(_) => "ArrayOfNullableInt",
+            DataValueEnum::ArrayOfLong(_) => "ArrayOfLong",
+            DataValueEnum::ArrayOfNullableLong(_) => "ArrayOfNullableLong",
+            DataValueEnum::ArrayOfFloat(_) => "ArrayOfFloat",
+            DataValueEnum::ArrayOfDouble(_) => "ArrayOfDouble",
+            DataValueEnum::ArrayOfString(_) => "ArrayOfString",
+            DataValueEnum::ArrayOfBytes(_) => "ArrayOfBytes",
+            DataValueEnum::Bytes(_) => "bytes",
+            DataValueEnum::AnySparseArray(_) => "AnySparseArray",
+        }
+    }
+
+    fn conversion_error(&self, target: &str) -> Error {
+        Error::Simple(format!(
+            "DataValue of type {} cannot be read as {}",
+            self.type_name(),
+            target
+        ))
+    }
+}
+
+impl From<DataValueEnum> for DataValue {
+    fn from(item: DataValueEnum) -> DataValue {
+        DataValue { item }
+    }
+}
+
+impl<T: Into<DataValue>> From<Option<T>> for DataValue {
+    fn from(value: Option<T>) -> DataValue {
+        match value {
+            Some(value) => value.into(),
+            None => DataValue::null(),
+        }
+    }
+}
+
+impl From<&str> for DataValue {
+    fn from(value: &str) -> DataValue {
+        DataValueEnum::String(value.to_string()).into()
+    }
+}
+
+impl From<bool> for DataValue {
+    fn from(value: bool) -> DataValue {
+        DataValueEnum::Boolean(value).into()
+    }
+}
+
+impl From<i32> for DataValue {
+    fn from(value: i32) -> DataValue {
+        DataValueEnum::Int(value).into()
+    }
+}
+
+impl From<i64> for DataValue {
+    fn from(value: i64) -> DataValue {
+        DataValueEnum::Long(value).into()
+    }
+}
+
+impl From<f32> for DataValue {
+    fn from(value: f32) -> DataValue {
+        DataValueEnum::Float(value).into()
+    }
+}
+
+impl From<f64> for DataValue {
+    fn from(value: f64) -> DataValue {
+        DataValueEnum::Double(value).into()
+    }
+}
+
+impl From<String> for DataValue {
+    fn from(value: String) -> DataValue {
+        DataValueEnum::String(value).into()
+    }
+}
+
+impl From<Vec<u8>> for DataValue {
+    fn from(value: Vec<u8>) -> DataValue {
+        DataValueEnum::Bytes(value).into()
+    }
+}
+
+impl From<Vec<bool>> for DataValue {
+    fn from(values: Vec<bool>) -> DataValue {
+        DataValueEnum::ArrayOfBoolean(ArrayOfBoolean { values }).into()
+    }
+}
+
+impl From<Vec<Option<bool>>> for DataValue {
+    fn from(values: Vec<Option<bool>>) -> DataValue {
+        DataValueEnum::ArrayOfNullableBoolean(ArrayOfNullableBoolean { values }).into()
+    }
+}
+
+impl From<Vec<i32>> for DataValue {
+    fn from(values: Vec<i32>) -> DataValue {
+        DataValueEnum::ArrayOfInt(ArrayOfInt { values }).into()
+    }
+}
+
+impl From<Vec<Option<i32>>> for DataValue {
+    fn from(values: Vec<Option<i32>>) -> DataValue {
+        DataValueEnum::ArrayOfNullableInt(ArrayOfNullableInt { values }).into()
+    }
+}
+
+impl From<Vec<i64>> for DataValue {
+    fn from(values: Vec<i64>) -> DataValue {
+        DataValueEnum::ArrayOfLong(ArrayOfLong { values }).into()
+    }
+}
+
+impl From<Vec<Option<i64>>> for DataValue {
+    fn from(values: Vec<Option<i64>>) -> DataValue {
+        DataValueEnum::ArrayOfNullableLong(ArrayOfNullableLong { values }).into()
+    }
+}
+
+impl From<Vec<f32>> for DataValue {
+    fn from(values: Vec<f32>) -> DataValue {
+        DataValueEnum::ArrayOfFloat(ArrayOfFloat { values }).into()
+    }
+}
+
+impl From<Vec<f64>> for DataValue {
+    fn from(values: Vec<f64>) -> DataValue {
+        DataValueEnum::ArrayOfDouble(ArrayOfDouble { values }).into()
+    }
+}
+
+impl From<Vec<String>> for DataValue {
+    fn from(values: Vec<String>) -> DataValue {
+        DataValueEnum::ArrayOfString(ArrayOfString { values }).into()
+    }
+}
+
+impl From<Vec<Vec<u8>>> for DataValue {
+    fn from(values: Vec<Vec<u8>>) -> DataValue {
+        DataValueEnum::ArrayOfBytes(ArrayOfBytes { values }).into()
+    }
+}
+
+impl TryFrom<&DataValue> for bool {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<bool, Error> {
+        match &value.item {
+            DataValueEnum::Boolean(v) => Ok(*v),
+            _ => Err(value.conversion_error("bool")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for i32 {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<i32, Error> {
+        match &value.item {
+            DataValueEnum::Int(v) => Ok(*v),
+            _ => Err(value.conversion_error("i32")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for i64 {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<i64, Error> {
+        match &value.item {
+            DataValueEnum::Int(v) => Ok(*v as i64),
+            DataValueEnum::Long(v) => Ok(*v),
+            _ => Err(value.conversion_error("i64")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for f32 {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<f32, Error> {
+        match &value.item {
+            DataValueEnum::Float(v) => Ok(*v),
+            _ => Err(value.conversion_error("f32")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for f64 {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<f64, Error> {
+        match &value.item {
+            DataValueEnum::Int(v) => Ok(*v as f64),
+            DataValueEnum::Float(v) => Ok(*v as f64),
+            DataValueEnum::Double(v) => Ok(*v),
+            _ => Err(value.conversion_error("f64")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for String {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<String, Error> {
+        match &value.item {
+            DataValueEnum::String(v) => Ok(v.clone()),
+            _ => Err(value.conversion_error("String")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for Vec<u8> {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<Vec<u8>, Error> {
+        match &value.item {
+            DataValueEnum::Bytes(v) => Ok(v.clone()),
+            _ => Err(value.conversion_error("Vec<u8>")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for Vec<bool> {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<Vec<bool>, Error> {
+        match &value.item {
+            DataValueEnum::ArrayOfBoolean(a) => Ok(a.values.clone()),
+            _ => Err(value.conversion_error("Vec<bool>")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for Vec<i32> {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<Vec<i32>, Error> {
+        match &value.item {
+            DataValueEnum::ArrayOfInt(a) => Ok(a.values.clone()),
+            _ => Err(value.conversion_error("Vec<i32>")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for Vec<i64> {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<Vec<i64>, Error> {
+        match &value.item {
+            DataValueEnum::ArrayOfInt(a) => Ok(a.values.iter().map(|v| *v as i64).collect()),
+            DataValueEnum::ArrayOfLong(a) => Ok(a.values.clone()),
+            _ => Err(value.conversion_error("Vec<i64>")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for Vec<f32> {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<Vec<f32>, Error> {
+        match &value.item {
+            DataValueEnum::ArrayOfFloat(a) => Ok(a.values.clone()),
+            _ => Err(value.conversion_error("Vec<f32>")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for Vec<f64> {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<Vec<f64>, Error> {
+        match &value.item {
+            DataValueEnum::ArrayOfInt(a) => Ok(a.values.iter().map(|v| *v as f64).collect()),
+            DataValueEnum::ArrayOfFloat(a) => Ok(a.values.iter().map(|v| *v as f64).collect()),
+            DataValueEnum::ArrayOfDouble(a) => Ok(a.values.clone()),
+            _ => Err(value.conversion_error("Vec<f64>")),
+        }
+    }
+}
+
+impl TryFrom<&DataValue> for Vec<String> {
+    type Error = Error;
+
+    fn try_from(value: &DataValue) -> Result<Vec<String>, Error> {
+        match &value.item {
+            DataValueEnum::ArrayOfString(a) => Ok(a.values.clone()),
+            _ => Err(value.conversion_error("Vec<String>")),
+        }
+    }
+}
+
+impl fmt::Display for DataValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.item {
+            DataValueEnum::Null => write!(f, "null"),
+            DataValueEnum::Boolean(v) => write!(f, "{}", v),
+            DataValueEnum::Int(v) => write!(f, "{}", v),
+            DataValueEnum::Long(v) => write!(f, "{}", v),
+            DataValueEnum::Float(v) => write!(f, "{}", v),
+            DataValueEnum::Double(v) => write!(f, "{}", v),
+            DataValueEnum::String(v) => write!(f, "{}", v),
+            DataValueEnum::ArrayOfBoolean(a) => write_list(f, &a.values),
+            DataValueEnum::ArrayOfNullableBoolean(a) => write_nullable_list(f, &a.values),
+            DataValueEnum::ArrayOfInt(a) => write_list(f, &a.values),
+            DataValueEnum::ArrayOfNullableInt(a) => write_nullable_list(f, &a.values),
+            DataValueEnum::ArrayOfLong(a) => write_list(f, &a.values),
+            DataValueEnum::ArrayOfNullableLong(a) => write_nullable_list(f, &a.values),
+            DataValueEnum::ArrayOfFloat(a) => write_list(f, &a.values),
+            DataValueEnum::ArrayOfDouble(a) => write_list(f, &a.values),
+            DataValueEnum::ArrayOfString(a) => write_list(f, &a.values),
+            DataValueEnum::ArrayOfBytes(a) => {
+                let sizes: Vec<String> = a
+                    .values
+                    .iter()
+                    .map(|v| format!("<{} bytes>", v.len()))
+                    .collect();
+                write_list(f, &sizes)
+            }
+            DataValueEnum::Bytes(v) => write!(f, "<{} bytes>", v.len()),
+            DataValueEnum::AnySparseArray(a) => {
+                write!(f, "<sparse array of {} slices>", a.slices.len())
+            }
+        }
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter, values: &[T]) -> fmt::Result {
+    write!(f, "[")?;
+    for (pos, value) in values.iter().enumerate() {
+        if pos > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", value)?;
+    }
+    write!(f, "]")
+}
+
+fn write_nullable_list<T: fmt::Display>(
+    f: &mut fmt::Formatter,
+    values: &[Option<T>],
+) -> fmt::Result {
+    let values: Vec<String> = values
+        .iter()
+        .map(|value| match value {
+            Some(value) => value.to_string(),
+            None => "null".to_string(),
+        })
+        .collect();
+    write_list(f, &values)
+}
+
+#[test]
+fn test_data_value_conversions() {
+    let value = DataValue::from(42);
+    assert_eq!(value.item, DataValueEnum::Int(42));
+    assert_eq!(i64::try_from(&value).unwrap(), 42);
+    assert_eq!(f64::try_from(&value).unwrap(), 42.0);
+    assert!(bool::try_from(&value).is_err());
+
+    // No narrowing
+    let value = DataValue::from(1i64 << 40);
+    assert!(i32::try_from(&value).is_err());
+
+    let value = DataValue::from(None::<f64>);
+    assert!(value.is_null());
+    assert_eq!(value.get::<f64>().unwrap(), None);
+    assert_eq!(DataValue::from(2.5).get::<f64>().unwrap(), Some(2.5));
+
+    let value = DataValue::from(vec![1, 2, 3]);
+    assert_eq!(Vec::<f64>::try_from(&value).unwrap(), vec![1.0, 2.0, 3.0]);
+    assert_eq!(value.to_string(), "[1, 2, 3]");
+    assert_eq!(
+        DataValue::from(vec![Some(true), None]).to_string(),
+        "[true, null]"
+    );
+    assert_eq!(DataValue::from("Volve").to_string(), "Volve");
+}
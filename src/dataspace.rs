@@ -0,0 +1,165 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Dataspace (Protocol 24) - Customer side.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error,
+    headerflags::*,
+    helpers::time_to_etp_micros,
+    schema::*,
+    schema_gen::*,
+    session::{MapResponse, Session},
+};
+use apache_avro::from_value;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+impl Session {
+    // Lists the dataspaces in the store.  With a filter, only those written since that time are returned.
+    pub fn list_dataspaces(
+        &mut self,
+        store_last_write_filter: Option<SystemTime>,
+    ) -> Result<Vec<Dataspace>, Error> {
+        let msg_id = self.send_message(
+            get_dataspaces_request(store_last_write_filter)?,
+            DATASPACE_GETDATASPACES,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        // The list may be split over several response messages.
+        let mut dataspaces = vec![];
+        loop {
+            let (msg_hdr, msg_body) = self.read_response(msg_id)?;
+            if msg_hdr.msgtype() != DATASPACE_GETDATASPACESRESPONSE {
+                return Err(Error::UnexpectedMessage(
+                    msg_hdr.protocol,
+                    msg_hdr.message_type,
+                ));
+            }
+            dataspaces.extend(from_value::<GetDataspacesResponse>(&msg_body)?.dataspaces);
+
+            if msg_hdr.get_flags().finalmsg {
+                return Ok(dataspaces);
+            }
+        }
+    }
+
+    // Creates or updates dataspaces.  Results are keyed by dataspace URI.
+    pub fn put_dataspaces(
+        &mut self,
+        dataspaces: Vec<Dataspace>,
+    ) -> Result<MapResponse<String>, Error> {
+        let dataspaces: HashMap<String, Dataspace> = dataspaces
+            .into_iter()
+            .map(|dataspace| (dataspace.uri.clone(), dataspace))
+            .collect();
+        let msg_id = self.send_message(
+            PutDataspaces { dataspaces },
+            DATASPACE_PUTDATASPACES,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        self.read_map_response(msg_id, DATASPACE_PUTDATASPACESRESPONSE, |body| {
+            Ok(from_value::<PutDataspacesResponse>(body)?.success)
+        })
+    }
+
+    // Deletes dataspaces, and everything in them.  Results are keyed by dataspace URI.
+    pub fn delete_dataspaces(&mut self, uris: &[&str]) -> Result<MapResponse<String>, Error> {
+        let msg_id = self.send_message(
+            DeleteDataspaces {
+                uris: uris
+                    .iter()
+                    .map(|uri| (uri.to_string(), uri.to_string()))
+                    .collect(),
+            },
+            DATASPACE_DELETEDATASPACES,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        self.read_map_response(msg_id, DATASPACE_DELETEDATASPACESRESPONSE, |body| {
+            Ok(from_value::<DeleteDataspacesResponse>(body)?.success)
+        })
+    }
+}
+
+fn get_dataspaces_request(
+    store_last_write_filter: Option<SystemTime>,
+) -> Result<GetDataspaces, Error> {
+    Ok(GetDataspaces {
+        store_last_write_filter: store_last_write_filter
+            .map(time_to_etp_micros)
+            .transpose()?,
+    })
+}
+
+#[test]
+fn test_roundtrip_dataspaces() {
+    let response = GetDataspacesResponse {
+        dataspaces: vec![Dataspace {
+            uri: "eml:///dataspace('demo/Volve')".to_string(),
+            path: "demo/Volve".to_string(),
+            store_last_write: 1680000000000000,
+            store_created: 1670000000000000,
+            custom_data: HashMap::new(),
+        }],
+    };
+
+    let decoded = roundtrip(DATASPACE_GETDATASPACESRESPONSE, response.clone());
+
+    assert_eq!(
+        from_value::<GetDataspacesResponse>(&decoded).unwrap(),
+        response
+    );
+}
+
+#[test]
+fn test_dataspaces_filter() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // The filter is sent in microseconds
+    let since = UNIX_EPOCH + Duration::from_micros(1680000000123456);
+    assert_eq!(
+        get_dataspaces_request(Some(since))
+            .unwrap()
+            .store_last_write_filter,
+        Some(1680000000123456)
+    );
+    assert_eq!(
+        get_dataspaces_request(None)
+            .unwrap()
+            .store_last_write_filter,
+        None
+    );
+
+    // Times before the epoch can not be sent
+    assert!(get_dataspaces_request(Some(UNIX_EPOCH - Duration::from_secs(1))).is_err());
+}
@@ -0,0 +1,155 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Discovery (Protocol 3) - Customer side.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error, headerflags::*, helpers::time_to_etp_micros, schema::*, schema_gen::*,
+    session::Session,
+};
+use apache_avro::{from_value, types::Value};
+use std::time::SystemTime;
+
+impl Session {
+    // Resources reached from the context.  With a filter, only those written since that time are returned.
+    pub fn get_resources(
+        &mut self,
+        context: ContextInfo,
+        scope: ContextScopeKind,
+        store_last_write_filter: Option<SystemTime>,
+    ) -> Result<Vec<Resource>, Error> {
+        let msg_id = self.send_message(
+            GetResources {
+                context,
+                scope,
+                count_objects: false,
+                store_last_write_filter: store_last_write_filter
+                    .map(time_to_etp_micros)
+                    .transpose()?,
+                active_status_filter: None,
+                include_edges: false,
+            },
+            DISCOVERY_GETRESOURCES,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        // The list may be split over several response messages.
+        let mut resources = vec![];
+        loop {
+            let (msg_hdr, msg_body) = self.read_response(msg_id)?;
+            if add_resources(&mut resources, &msg_hdr, &msg_body)? {
+                return Ok(resources);
+            }
+        }
+    }
+
+    // All the data objects in a dataspace (eg. "eml:///dataspace('demo')", or "eml:///" for the default).
+    pub fn dataspace_resources(&mut self, dataspace_uri: &str) -> Result<Vec<Resource>, Error> {
+        self.get_resources(
+            ContextInfo {
+                uri: dataspace_uri.to_string(),
+                depth: 1,
+                data_object_types: vec![],
+                navigable_edges: RelationshipKind::Primary,
+                include_secondary_targets: false,
+                include_secondary_sources: false,
+            },
+            ContextScopeKind::Targets,
+            None,
+        )
+    }
+}
+
+// Adds the resources from one part of a GetResources response.  Returns true once the response is complete.
+fn add_resources(
+    resources: &mut Vec<Resource>,
+    msg_hdr: &MessageHeader,
+    msg_body: &Value,
+) -> Result<bool, Error> {
+    match msg_hdr.msgtype() {
+        DISCOVERY_GETRESOURCESRESPONSE => {
+            resources.extend(from_value::<GetResourcesResponse>(msg_body)?.resources)
+        }
+        CORE_PROTOCOLEXCEPTION => {
+            let pe = from_value::<ProtocolException>(msg_body)?;
+            if let Some(error) = pe.error {
+                return Err(Error::ProtocolException(error.code, error.message));
+            }
+        }
+        _ => {
+            return Err(Error::UnexpectedMessage(
+                msg_hdr.protocol,
+                msg_hdr.message_type,
+            ))
+        }
+    }
+    Ok(msg_hdr.get_flags().finalmsg)
+}
+
+#[test]
+fn test_multipart_resources() {
+    use std::collections::HashMap;
+
+    let resource = |name: &str| Resource {
+        uri: format!("eml:///witsml20.Well({})", name),
+        alternate_uris: vec![],
+        name: name.to_string(),
+        source_count: None,
+        target_count: None,
+        last_changed: 1680000000000000,
+        store_last_write: 1680000000000000,
+        store_created: 1670000000000000,
+        active_status: ActiveStatusKind::Inactive,
+        custom_data: HashMap::new(),
+    };
+    let part = |names: &[&str], message_flags: i32| {
+        let hdr = MessageHeader {
+            protocol: DISCOVERY_GETRESOURCESRESPONSE.0 as i32,
+            message_type: DISCOVERY_GETRESOURCESRESPONSE.1 as i32,
+            correlation_id: 1,
+            message_id: 2,
+            message_flags,
+        };
+        let body = apache_avro::to_value(GetResourcesResponse {
+            resources: names.iter().map(|name| resource(name)).collect(),
+        })
+        .unwrap();
+        (hdr, body)
+    };
+
+    let mut resources = vec![];
+    let (hdr, body) = part(&["a", "b"], 0);
+    assert!(!add_resources(&mut resources, &hdr, &body).unwrap());
+    let (hdr, body) = part(&[], 0);
+    assert!(!add_resources(&mut resources, &hdr, &body).unwrap());
+    let (hdr, body) = part(&["c"], MSG_FLAG_FINAL);
+    assert!(add_resources(&mut resources, &hdr, &body).unwrap());
+    assert_eq!(resources, vec![resource("a"), resource("b"), resource("c")]);
+
+    let (mut hdr, body) = part(&["d"], MSG_FLAG_FINAL);
+    hdr.message_type = 1;
+    assert!(add_resources(&mut resources, &hdr, &body).is_err());
+}
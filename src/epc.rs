@@ -0,0 +1,558 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// EPC packages (Energistics Packaging Conventions, an OPC zip), exchanged through the Store protocol.
+// Feature: "epc"
+//   [Content_Types].xml              Content type of each part
+//   _rels/.rels                      Package relationships (core properties)
+//   docProps/core.xml                Core properties
+//   obj_<Type>_<uuid>.xml            A data object, eg. content type application/x-resqml+xml;version=2.0;type=..
+//   _rels/obj_<Type>_<uuid>.xml.rels Its destinationObject / sourceObject relationships
+// Only the XML data objects are transferred: external parts (eg. HDF5 arrays) are kept in the package but not
+// put to the store.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error,
+    etp_uri::EtpUri,
+    helpers::time_to_etp_micros,
+    object_payload::ObjectPayload,
+    schema_gen::*,
+    session::{MapResponse, Session},
+};
+#[allow(unused_imports)]
+use log::{info, trace, warn};
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+use std::time::SystemTime;
+
+const CONTENT_TYPES: &str = "[Content_Types].xml";
+const CORE_PROPERTIES: &str = "/docProps/core.xml";
+
+pub const REL_DESTINATION_OBJECT: &str =
+    "http://schemas.energistics.org/package/2012/relationships/destinationObject";
+pub const REL_SOURCE_OBJECT: &str =
+    "http://schemas.energistics.org/package/2012/relationships/sourceObject";
+pub const REL_CORE_PROPERTIES: &str =
+    "http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties";
+
+const CT_RELATIONSHIPS: &str = "application/vnd.openxmlformats-package.relationships+xml";
+const CT_CORE_PROPERTIES: &str = "application/vnd.openxmlformats-package.core-properties+xml";
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EpcPart {
+    pub name: String, // Absolute part name, eg. "/obj_Grid2dRepresentation_<uuid>.xml"
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+impl EpcPart {
+    // Energistics data objects have a content type such as application/x-resqml+xml;version=2.0;type=..
+    pub fn is_data_object(&self) -> bool {
+        self.content_type.starts_with("application/x-") && self.content_type.contains("type=")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EpcRelationship {
+    pub source: String, // Part name, or "/" for the package
+    pub id: String,
+    pub kind: String,   // Relationship type, eg. REL_DESTINATION_OBJECT
+    pub target: String, // Part name, or the target as written when external
+    pub external: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct EpcPackage {
+    pub parts: Vec<EpcPart>,
+    pub relationships: Vec<EpcRelationship>,
+}
+
+impl EpcPackage {
+    pub fn read<R: Read + Seek>(input: R) -> Result<EpcPackage, Error> {
+        let mut archive = zip::ZipArchive::new(input).map_err(zip_error)?;
+        let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+        for n in 0..archive.len() {
+            let mut file = archive.by_index(n).map_err(zip_error)?;
+            if file.is_dir() {
+                continue;
+            }
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            entries.push((format!("/{}", file.name()), data));
+        }
+
+        // Content types are by part name, or by default for the extension.
+        let (_, types) = entries
+            .iter()
+            .find(|(name, _)| name[1..] == *CONTENT_TYPES)
+            .ok_or_else(|| epc_error(&"No [Content_Types].xml"))?;
+        let types = std::str::from_utf8(types).map_err(|err| epc_error(&err))?;
+        let types = roxmltree::Document::parse(types).map_err(|err| epc_error(&err))?;
+        let mut defaults = HashMap::new();
+        let mut overrides = HashMap::new();
+        for node in types.root_element().children().filter(|n| n.is_element()) {
+            let content_type = node
+                .attribute("ContentType")
+                .unwrap_or_default()
+                .to_string();
+            match node.tag_name().name() {
+                "Default" => {
+                    if let Some(extension) = node.attribute("Extension") {
+                        defaults.insert(extension.to_lowercase(), content_type);
+                    }
+                }
+                "Override" => {
+                    if let Some(part) = node.attribute("PartName") {
+                        overrides.insert(part.to_lowercase(), content_type);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut package = EpcPackage::default();
+        for (name, data) in entries {
+            if name[1..] == *CONTENT_TYPES {
+                continue;
+            }
+            if let Some(source) = rels_source(&name) {
+                package.read_relationships(&source, &data)?;
+                continue;
+            }
+            let extension = name.rsplit_once('.').map(|(_, e)| e.to_lowercase());
+            let content_type = overrides
+                .get(&name.to_lowercase())
+                .or_else(|| extension.and_then(|e| defaults.get(&e)))
+                .cloned()
+                .ok_or_else(|| epc_error(&format!("No content type for {}", name)))?;
+            package.parts.push(EpcPart {
+                name,
+                content_type,
+                data,
+            });
+        }
+        Ok(package)
+    }
+
+    fn read_relationships(&mut self, source: &str, data: &[u8]) -> Result<(), Error> {
+        let text = std::str::from_utf8(data).map_err(|err| epc_error(&err))?;
+        let doc = roxmltree::Document::parse(text).map_err(|err| epc_error(&err))?;
+        for node in doc
+            .root_element()
+            .children()
+            .filter(|n| n.is_element() && n.tag_name().name() == "Relationship")
+        {
+            let target = node.attribute("Target").unwrap_or_default();
+            let external = node.attribute("TargetMode") == Some("External");
+            self.relationships.push(EpcRelationship {
+                source: source.to_string(),
+                id: node.attribute("Id").unwrap_or_default().to_string(),
+                kind: node.attribute("Type").unwrap_or_default().to_string(),
+                target: if external {
+                    target.to_string()
+                } else {
+                    resolve_target(source, target)
+                },
+                external,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn write<W: Write + Seek>(&self, out: W) -> Result<(), Error> {
+        let mut zip = zip::ZipWriter::new(out);
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+        let mut types = String::from(concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">"#,
+        ));
+        types.push_str(&format!(
+            r#"<Default Extension="rels" ContentType="{}"/>"#,
+            CT_RELATIONSHIPS
+        ));
+        for part in &self.parts {
+            types.push_str(&format!(
+                r#"<Override PartName="{}" ContentType="{}"/>"#,
+                escape(&part.name),
+                escape(&part.content_type)
+            ));
+        }
+        types.push_str("</Types>");
+        zip.start_file(CONTENT_TYPES, options).map_err(zip_error)?;
+        zip.write_all(types.as_bytes())?;
+
+        // One relationships part per source, in the order first seen.
+        let mut sources: Vec<&str> = Vec::new();
+        for relationship in &self.relationships {
+            if !sources.contains(&relationship.source.as_str()) {
+                sources.push(&relationship.source);
+            }
+        }
+        for source in sources {
+            let mut rels = String::from(concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+            ));
+            for relationship in self.relationships.iter().filter(|r| r.source == source) {
+                rels.push_str(&format!(
+                    r#"<Relationship Id="{}" Type="{}" Target="{}"{}/>"#,
+                    escape(&relationship.id),
+                    escape(&relationship.kind),
+                    escape(&relationship.target),
+                    if relationship.external {
+                        r#" TargetMode="External""#
+                    } else {
+                        ""
+                    }
+                ));
+            }
+            rels.push_str("</Relationships>");
+            zip.start_file(&rels_name(source)[1..], options)
+                .map_err(zip_error)?;
+            zip.write_all(rels.as_bytes())?;
+        }
+
+        for part in &self.parts {
+            zip.start_file(&part.name[1..], options)
+                .map_err(zip_error)?;
+            zip.write_all(&part.data)?;
+        }
+        zip.finish().map_err(zip_error)?;
+        Ok(())
+    }
+
+    // A package of XML data objects, with core properties and the relationships between the objects that
+    // are both in the package.
+    pub fn from_data_objects(data_objects: &[DataObject]) -> Result<EpcPackage, Error> {
+        let mut package = EpcPackage::default();
+        package.parts.push(EpcPart {
+            name: CORE_PROPERTIES.to_string(),
+            content_type: CT_CORE_PROPERTIES.to_string(),
+            data: concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" "#,
+                r#"xmlns:dc="http://purl.org/dc/elements/1.1/">"#,
+                r#"<dc:creator>etp-rs</dc:creator><cp:version>1.0</cp:version></cp:coreProperties>"#,
+            )
+            .as_bytes()
+            .to_vec(),
+        });
+        package.relationships.push(EpcRelationship {
+            source: "/".to_string(),
+            id: "CoreProperties".to_string(),
+            kind: REL_CORE_PROPERTIES.to_string(),
+            target: CORE_PROPERTIES.to_string(),
+            external: false,
+        });
+
+        let mut payloads = Vec::new();
+        for data_object in data_objects {
+            if !matches!(data_object.format.to_lowercase().as_str(), "" | "xml") {
+                return Err(epc_error(&format!(
+                    "{} is not XML",
+                    data_object.resource.uri
+                )));
+            }
+            let payload = data_object.payload()?;
+            package.parts.push(EpcPart {
+                name: part_name(&payload),
+                content_type: content_type(&payload.qualified_type)?,
+                data: data_object.data.clone(),
+            });
+            payloads.push(payload);
+        }
+
+        for payload in &payloads {
+            for reference in &payload.references {
+                let Some(target) = payloads.iter().find(|p| p.uuid == reference.uuid) else {
+                    continue;
+                };
+                let (source, target) = (part_name(payload), part_name(target));
+                package.add_relationship(&source, REL_DESTINATION_OBJECT, &target);
+                package.add_relationship(&target, REL_SOURCE_OBJECT, &source);
+            }
+        }
+        Ok(package)
+    }
+
+    fn add_relationship(&mut self, source: &str, kind: &str, target: &str) {
+        let count = self
+            .relationships
+            .iter()
+            .filter(|r| r.source == source)
+            .count();
+        self.relationships.push(EpcRelationship {
+            source: source.to_string(),
+            id: format!("_{}", count + 1),
+            kind: kind.to_string(),
+            target: target.to_string(),
+            external: false,
+        });
+    }
+
+    // The data objects of the package, addressed in the dataspace.
+    pub fn data_objects(&self, dataspace: &EtpUri) -> Result<Vec<DataObject>, Error> {
+        let now = time_to_etp_micros(SystemTime::now())?;
+        self.parts
+            .iter()
+            .filter(|part| part.is_data_object())
+            .map(|part| {
+                let payload = ObjectPayload::from_xml(&part.data)
+                    .map_err(|err| epc_error(&format!("{}: {}", part.name, err)))?;
+                Ok(DataObject {
+                    resource: payload.resource(dataspace, now)?,
+                    format: "xml".to_string(),
+                    blob_id: None,
+                    data: part.data.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Session {
+    // Puts the data objects of the package into the dataspace.  Results are keyed by URI.
+    pub fn put_epc(
+        &mut self,
+        package: &EpcPackage,
+        dataspace: &EtpUri,
+    ) -> Result<MapResponse<PutResponse>, Error> {
+        let data_objects = package.data_objects(dataspace)?;
+        self.put_data_objects(data_objects, false)
+    }
+
+    // A package of all the data objects in the dataspace.
+    pub fn get_epc(&mut self, dataspace: &EtpUri) -> Result<EpcPackage, Error> {
+        let resources = self.dataspace_resources(&dataspace.dataspace_uri().to_string())?;
+        let uris: Vec<&str> = resources.iter().map(|r| r.uri.as_str()).collect();
+        if uris.is_empty() {
+            return EpcPackage::from_data_objects(&[]);
+        }
+
+        // Fetched in as many requests as the store's limits need.
+        let max_count = self
+            .open_session_msg
+            .protocol_capability(Protocol::Store, ProtocolCapabilityKind::MaxResponseCount)
+            .and_then(|count| usize::try_from(count).ok())
+            .unwrap_or(usize::MAX);
+        let mut data_objects: Vec<DataObject> = vec![];
+        for batch in uri_batches(&uris, max_count, self.max_message_size()) {
+            let response = self.get_data_objects(&batch, "xml")?;
+            if let Some((uri, error)) = response.errors.iter().next() {
+                return Err(Error::ProtocolException(
+                    error.code,
+                    format!("{}: {}", uri, error.message),
+                ));
+            }
+            data_objects.extend(response.success.into_values());
+        }
+        data_objects.sort_by(|a, b| a.resource.uri.cmp(&b.resource.uri));
+        EpcPackage::from_data_objects(&data_objects)
+    }
+}
+
+// Splits URIs into GetDataObjects requests of at most max_count URIs that fit in a message.  Each URI is
+// encoded twice (map key and value), each with a length of up to 10 bytes.
+fn uri_batches<'a>(uris: &[&'a str], max_count: usize, max_size: usize) -> Vec<Vec<&'a str>> {
+    let mut batches: Vec<Vec<&str>> = vec![];
+    let mut batch: Vec<&str> = vec![];
+    let mut batch_size = 0;
+    for uri in uris {
+        let size = 2 * (uri.len() + 10);
+        if !batch.is_empty() && (batch.len() >= max_count || batch_size + size > max_size) {
+            batches.push(std::mem::take(&mut batch));
+            batch_size = 0;
+        }
+        batch.push(uri);
+        batch_size += size;
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+fn epc_error(err: &dyn std::fmt::Display) -> Error {
+    Error::Simple(format!("Invalid EPC package: {}", err))
+}
+
+fn zip_error(err: zip::result::ZipError) -> Error {
+    epc_error(&err)
+}
+
+// eg. "/obj_Grid2dRepresentation_<uuid>.xml"
+fn part_name(payload: &ObjectPayload) -> String {
+    let object_type = payload
+        .qualified_type
+        .split_once('.')
+        .map(|(_, t)| t)
+        .unwrap_or(&payload.qualified_type);
+    format!("/{}_{}.xml", object_type, payload.uuid)
+}
+
+// eg. "resqml20.obj_Grid2dRepresentation" -> "application/x-resqml+xml;version=2.0;type=obj_Grid2dRepresentation"
+fn content_type(qualified_type: &str) -> Result<String, Error> {
+    let invalid = || epc_error(&format!("invalid type {}", qualified_type));
+    let (domain, object_type) = qualified_type.split_once('.').ok_or_else(invalid)?;
+    let version_pos = domain
+        .find(|c: char| c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (domain, version) = domain.split_at(version_pos);
+    let (major, minor) = version.split_at(1);
+    Ok(format!(
+        "application/x-{}+xml;version={}.{};type={}",
+        domain, major, minor, object_type
+    ))
+}
+
+// "/_rels/.rels" is for the package ("/"), "/dir/_rels/part.xml.rels" for "/dir/part.xml".
+fn rels_source(name: &str) -> Option<String> {
+    let (dir, file) = name.rsplit_once('/')?;
+    let file = file.strip_suffix(".rels")?;
+    let dir = dir.strip_suffix("/_rels").or(dir.strip_prefix("_rels"))?;
+    Some(format!("{}/{}", dir, file))
+}
+
+fn rels_name(source: &str) -> String {
+    let (dir, file) = source.rsplit_once('/').unwrap_or(("", source));
+    format!("{}/_rels/{}.rels", dir, file)
+}
+
+// Targets are relative to the directory of the source part.
+fn resolve_target(source: &str, target: &str) -> String {
+    let mut path: Vec<&str> = if target.starts_with('/') {
+        vec![]
+    } else {
+        source.split('/').filter(|s| !s.is_empty()).collect()
+    };
+    if !target.starts_with('/') {
+        path.pop();
+    }
+    for segment in target.split('/').filter(|s| !s.is_empty()) {
+        match segment {
+            "." => {}
+            ".." => {
+                path.pop();
+            }
+            _ => path.push(segment),
+        }
+    }
+    format!("/{}", path.join("/"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn test_epc_roundtrip() {
+    let crs_uuid = "6d1e4b2a-93c5-4f0e-8a7b-2c3d4e5f6a7b";
+    let grid_uuid = "0f9e8d7c-6b5a-4938-a716-253443526170";
+    let crs = format!(
+        r#"<resqml2:obj_LocalDepth3dCrs xmlns:resqml2="http://www.energistics.org/energyml/data/resqmlv2"
+            xmlns:eml="http://www.energistics.org/energyml/data/commonv2" uuid="{}" schemaVersion="2.0">
+            <eml:Citation><eml:Title>CRS</eml:Title></eml:Citation></resqml2:obj_LocalDepth3dCrs>"#,
+        crs_uuid
+    );
+    let grid = format!(
+        r#"<resqml2:obj_Grid2dRepresentation xmlns:resqml2="http://www.energistics.org/energyml/data/resqmlv2"
+            xmlns:eml="http://www.energistics.org/energyml/data/commonv2" uuid="{}" schemaVersion="2.0">
+            <eml:Citation><eml:Title>Top</eml:Title></eml:Citation>
+            <resqml2:LocalCrs>
+              <eml:ContentType>application/x-resqml+xml;version=2.0;type=obj_LocalDepth3dCrs</eml:ContentType>
+              <eml:Title>CRS</eml:Title><eml:UUID>{}</eml:UUID>
+            </resqml2:LocalCrs></resqml2:obj_Grid2dRepresentation>"#,
+        grid_uuid, crs_uuid
+    );
+    let data_object = |xml: &str| DataObject {
+        resource: ObjectPayload::from_xml(xml.as_bytes())
+            .unwrap()
+            .resource(&EtpUri::root(), 0)
+            .unwrap(),
+        format: "xml".to_string(),
+        blob_id: None,
+        data: xml.as_bytes().to_vec(),
+    };
+
+    let package = EpcPackage::from_data_objects(&[data_object(&crs), data_object(&grid)]).unwrap();
+    let mut file = std::io::Cursor::new(Vec::new());
+    package.write(&mut file).unwrap();
+    file.set_position(0);
+    let read = EpcPackage::read(file).unwrap();
+
+    assert_eq!(read.parts.len(), 3);
+    let grid_part = format!("/obj_Grid2dRepresentation_{}.xml", grid_uuid);
+    let crs_part = format!("/obj_LocalDepth3dCrs_{}.xml", crs_uuid);
+    assert!(read.parts.iter().any(|p| p.name == grid_part
+        && p.content_type == "application/x-resqml+xml;version=2.0;type=obj_Grid2dRepresentation"));
+    assert!(read.relationships.iter().any(|r| r.source == grid_part
+        && r.kind == REL_DESTINATION_OBJECT
+        && r.target == crs_part));
+    assert!(read
+        .relationships
+        .iter()
+        .any(|r| r.source == crs_part && r.kind == REL_SOURCE_OBJECT && r.target == grid_part));
+    assert!(read
+        .relationships
+        .iter()
+        .any(|r| r.source == "/" && r.target == CORE_PROPERTIES));
+
+    let objects = read.data_objects(&EtpUri::dataspace("demo")).unwrap();
+    assert_eq!(objects.len(), 2);
+    // Timestamps are in microseconds
+    assert!(objects[0].resource.store_created > 1_600_000_000_000_000);
+    assert!(objects.iter().any(|o| o.resource.uri
+        == format!(
+            "eml:///dataspace('demo')/resqml20.obj_Grid2dRepresentation({})",
+            grid_uuid
+        )));
+}
+
+#[test]
+fn test_uri_batches() {
+    let uris = ["eml:///a", "eml:///b", "eml:///c", "eml:///d", "eml:///e"];
+
+    let sizes = |batches: Vec<Vec<&str>>| batches.iter().map(|b| b.len()).collect::<Vec<_>>();
+    assert_eq!(sizes(uri_batches(&uris, usize::MAX, 1000)), vec![5]);
+    assert_eq!(sizes(uri_batches(&uris, 2, 1000)), vec![2, 2, 1]);
+    // 36 bytes a URI
+    assert_eq!(sizes(uri_batches(&uris, usize::MAX, 80)), vec![2, 2, 1]);
+    assert_eq!(
+        sizes(uri_batches(&uris, usize::MAX, 10)),
+        vec![1, 1, 1, 1, 1]
+    );
+    assert_eq!(
+        uri_batches(&uris, 3, 1000),
+        vec![
+            vec!["eml:///a", "eml:///b", "eml:///c"],
+            vec!["eml:///d", "eml:///e"]
+        ]
+    );
+    assert!(uri_batches(&[], 3, 1000).is_empty());
+}
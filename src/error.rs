@@ -37,6 +37,21 @@ pub enum Error {
     #[error("ProtocolException: {0}, {1}")]
     ProtocolException(i32, String),
 
+    #[error("Unexpected message received: Protocol {0}, MessageType {1}")]
+    UnexpectedMessage(i32, i32),
+
+    #[error("Invalid ETP URI {0}: {1}")]
+    InvalidUri(String, String),
+
+    #[error("Unknown unit of measure {0}")]
+    UnknownUnit(String),
+
+    #[error("Cannot convert {0} to {1}")]
+    IncompatibleUnits(String, String),
+
+    #[error("JSON Error {0}")]
+    JsonError(serde_json::Error),
+
     #[error("URL Parse Error: {0}")]
     ParseError(url::ParseError),
 
@@ -62,6 +77,12 @@ impl From<tungstenite::Error> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::JsonError(err)
+    }
+}
+
 impl From<url::ParseError> for Error {
     fn from(err: url::ParseError) -> Self {
         Error::ParseError(err)
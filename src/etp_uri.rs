@@ -0,0 +1,460 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// ETP URIs  (ETP 1.2 Specification, Appendix: Energistics Identifiers)
+//   eml:///                                                   Default dataspace
+//   eml:///dataspace('demo/Volve')                            Dataspace
+//   eml:///dataspace('demo')/witsml20.Well(<uuid>)            Data object
+//   eml:///witsml20.Well(uuid=<uuid>,version='1.0')           Data object, specific version
+//   eml:///dataspace('demo')/witsml20.Well                    Collection of a type in a dataspace
+//   eml:///witsml20.Well(<uuid>)/witsml20.Wellbore            Collection of a type related to an object
+//   ...?$format=json                                          Any of the above, with a query
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+const SCHEME: &str = "eml:///";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EtpUriKind {
+    Dataspace, // Including the default dataspace
+    DataObject,
+    Collection,
+}
+
+// One object or collection in the path of a URI, eg. witsml20.Well(<uuid>)
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct UriSegment {
+    pub domain: String,         // eg. "witsml"
+    pub domain_version: String, // eg. "20"
+    pub object_type: String,    // eg. "Well"
+    pub uuid: Option<String>,   // None for a collection
+    pub version: Option<String>,
+}
+
+impl UriSegment {
+    // eg. "witsml20.Well"
+    pub fn qualified_type(&self) -> String {
+        format!(
+            "{}{}.{}",
+            self.domain, self.domain_version, self.object_type
+        )
+    }
+
+    fn parse(text: &str) -> Result<UriSegment, String> {
+        let (qualified_type, args) = match text.find('(') {
+            Some(pos) => match text[pos..]
+                .strip_prefix('(')
+                .and_then(|a| a.strip_suffix(')'))
+            {
+                Some(args) => (&text[..pos], Some(args)),
+                None => return Err(format!("unbalanced brackets in '{}'", text)),
+            },
+            None => (text, None),
+        };
+        let (domain, domain_version, object_type) = split_qualified_type(qualified_type)?;
+
+        let mut uuid = None;
+        let mut version = None;
+        if let Some(args) = args {
+            for arg in split_outside_quotes(args, ',') {
+                match arg.split_once('=') {
+                    Some(("uuid", value)) => uuid = Some(value.to_string()),
+                    Some(("version", value)) => version = Some(unquote(value)?),
+                    Some((key, _)) => return Err(format!("unknown key '{}'", key)),
+                    None if uuid.is_none() => uuid = Some(arg.to_string()),
+                    None => return Err(format!("unexpected '{}'", arg)),
+                }
+            }
+            match &uuid {
+                Some(value) => match uuid::Uuid::parse_str(value) {
+                    Ok(parsed) => uuid = Some(parsed.hyphenated().to_string()),
+                    Err(_) => return Err(format!("invalid uuid '{}'", value)),
+                },
+                None => return Err(format!("no uuid in '{}'", text)),
+            }
+        }
+
+        Ok(UriSegment {
+            domain,
+            domain_version,
+            object_type,
+            uuid,
+            version,
+        })
+    }
+}
+
+impl fmt::Display for UriSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.qualified_type())?;
+        match (&self.uuid, &self.version) {
+            (Some(uuid), Some(version)) => {
+                write!(f, "(uuid={},version={})", uuid, quote(version))
+            }
+            (Some(uuid), None) => write!(f, "({})", uuid),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct EtpUri {
+    pub dataspace: Option<String>, // None for the default dataspace
+    pub segments: Vec<UriSegment>,
+    pub query: Option<String>, // Without the leading '?'
+}
+
+impl EtpUri {
+    // The default dataspace, eml:///
+    pub fn root() -> EtpUri {
+        EtpUri::default()
+    }
+
+    pub fn dataspace(path: &str) -> EtpUri {
+        EtpUri {
+            dataspace: if path.is_empty() {
+                None
+            } else {
+                Some(path.to_string())
+            },
+            ..EtpUri::default()
+        }
+    }
+
+    pub fn parse(uri: &str) -> Result<EtpUri, Error> {
+        EtpUri::parse_inner(uri).map_err(|reason| Error::InvalidUri(uri.to_string(), reason))
+    }
+
+    fn parse_inner(uri: &str) -> Result<EtpUri, String> {
+        let rest = match uri.strip_prefix(SCHEME) {
+            Some(rest) => rest,
+            None => return Err(format!("does not start with {}", SCHEME)),
+        };
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query.to_string())),
+            None => (rest, None),
+        };
+
+        let mut parts = split_outside_quotes(path, '/').into_iter().peekable();
+        let mut dataspace = None;
+        if let Some(first) = parts.peek() {
+            if let Some(quoted) = first
+                .strip_prefix("dataspace(")
+                .and_then(|d| d.strip_suffix(')'))
+            {
+                let path = unquote(quoted)?;
+                if !path.is_empty() {
+                    dataspace = Some(path);
+                }
+                parts.next();
+            }
+        }
+
+        let mut segments = vec![];
+        for part in parts {
+            if part.is_empty() {
+                // Trailing slash on the root / dataspace is allowed
+                continue;
+            }
+            segments.push(UriSegment::parse(part)?);
+        }
+        if let Some(pos) = segments[..segments.len().saturating_sub(1)]
+            .iter()
+            .position(|s| s.uuid.is_none())
+        {
+            return Err(format!(
+                "collection '{}' must be the last segment",
+                segments[pos].qualified_type()
+            ));
+        }
+
+        Ok(EtpUri {
+            dataspace,
+            segments,
+            query,
+        })
+    }
+
+    pub fn kind(&self) -> EtpUriKind {
+        match self.segments.last() {
+            None => EtpUriKind::Dataspace,
+            Some(segment) if segment.uuid.is_some() => EtpUriKind::DataObject,
+            Some(_) => EtpUriKind::Collection,
+        }
+    }
+
+    // The last object or collection in the path
+    pub fn last(&self) -> Option<&UriSegment> {
+        self.segments.last()
+    }
+
+    pub fn domain(&self) -> Option<&str> {
+        self.last().map(|s| s.domain.as_str())
+    }
+
+    pub fn domain_version(&self) -> Option<&str> {
+        self.last().map(|s| s.domain_version.as_str())
+    }
+
+    pub fn object_type(&self) -> Option<&str> {
+        self.last().map(|s| s.object_type.as_str())
+    }
+
+    pub fn qualified_type(&self) -> Option<String> {
+        self.last().map(|s| s.qualified_type())
+    }
+
+    pub fn uuid(&self) -> Option<&str> {
+        self.last().and_then(|s| s.uuid.as_deref())
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.last().and_then(|s| s.version.as_deref())
+    }
+
+    // Value of $format in the query, if any
+    pub fn format(&self) -> Option<&str> {
+        self.query.as_deref().and_then(|query| {
+            query
+                .split('&')
+                .find_map(|param| param.strip_prefix("$format="))
+        })
+    }
+
+    // The dataspace this URI is in
+    pub fn dataspace_uri(&self) -> EtpUri {
+        EtpUri {
+            dataspace: self.dataspace.clone(),
+            ..EtpUri::default()
+        }
+    }
+
+    // The canonical form: a data object is addressed directly in its dataspace, without the path it
+    // was reached by, and without a query.  Other URIs only lose the query.
+    pub fn canonical(&self) -> EtpUri {
+        let segments = match self.kind() {
+            EtpUriKind::DataObject => self.segments[self.segments.len() - 1..].to_vec(),
+            _ => self.segments.clone(),
+        };
+        EtpUri {
+            dataspace: self.dataspace.clone(),
+            segments,
+            query: None,
+        }
+    }
+
+    // A data object under this URI (or in this dataspace), eg. object("witsml20.Wellbore", uuid)
+    pub fn object(&self, qualified_type: &str, uuid: &str) -> Result<EtpUri, Error> {
+        self.child(&format!("{}({})", qualified_type, uuid))
+    }
+
+    // A collection of a type under this URI (or in this dataspace), eg. collection("witsml20.Wellbore")
+    pub fn collection(&self, qualified_type: &str) -> Result<EtpUri, Error> {
+        self.child(qualified_type)
+    }
+
+    pub fn with_query(&self, query: &str) -> EtpUri {
+        EtpUri {
+            query: Some(query.trim_start_matches('?').to_string()),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_format(&self, format: &str) -> EtpUri {
+        let mut params: Vec<String> = match &self.query {
+            Some(query) => query
+                .split('&')
+                .filter(|param| !param.starts_with("$format="))
+                .map(|param| param.to_string())
+                .collect(),
+            None => vec![],
+        };
+        params.push(format!("$format={}", format));
+        self.with_query(&params.join("&"))
+    }
+
+    fn child(&self, segment: &str) -> Result<EtpUri, Error> {
+        if self.kind() == EtpUriKind::Collection {
+            return Err(Error::InvalidUri(
+                self.to_string(),
+                "a collection has no children".to_string(),
+            ));
+        }
+        let segment = UriSegment::parse(segment)
+            .map_err(|reason| Error::InvalidUri(segment.to_string(), reason))?;
+
+        let mut uri = self.clone();
+        uri.segments.push(segment);
+        uri.query = None;
+        Ok(uri)
+    }
+}
+
+impl fmt::Display for EtpUri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", SCHEME)?;
+        let mut parts = vec![];
+        if let Some(dataspace) = &self.dataspace {
+            parts.push(format!("dataspace({})", quote(dataspace)));
+        }
+        parts.extend(self.segments.iter().map(|s| s.to_string()));
+        write!(f, "{}", parts.join("/"))?;
+        if let Some(query) = &self.query {
+            write!(f, "?{}", query)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for EtpUri {
+    type Err = Error;
+
+    fn from_str(uri: &str) -> Result<EtpUri, Error> {
+        EtpUri::parse(uri)
+    }
+}
+
+impl TryFrom<&str> for EtpUri {
+    type Error = Error;
+
+    fn try_from(uri: &str) -> Result<EtpUri, Error> {
+        EtpUri::parse(uri)
+    }
+}
+
+impl TryFrom<String> for EtpUri {
+    type Error = Error;
+
+    fn try_from(uri: String) -> Result<EtpUri, Error> {
+        EtpUri::parse(&uri)
+    }
+}
+
+impl From<EtpUri> for String {
+    fn from(uri: EtpUri) -> String {
+        uri.to_string()
+    }
+}
+
+// "witsml20.Well" to ("witsml", "20", "Well")
+fn split_qualified_type(qualified_type: &str) -> Result<(String, String, String), String> {
+    let (domain, object_type) = match qualified_type.split_once('.') {
+        Some(split) => split,
+        None => return Err(format!("'{}' is not a qualified type", qualified_type)),
+    };
+    let digits = domain.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    let name = &domain[..domain.len() - digits.len()];
+
+    if name.is_empty() || digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("'{}' is not a versioned domain", domain));
+    }
+    if object_type.is_empty() || !object_type.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(format!("'{}' is not a data object type", object_type));
+    }
+    Ok((
+        name.to_string(),
+        digits.to_string(),
+        object_type.to_string(),
+    ))
+}
+
+// Splits on 'separator', except inside single quoted strings.
+fn split_outside_quotes(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (pos, c) in text.char_indices() {
+        if c == '\'' {
+            quoted = !quoted;
+        } else if c == separator && !quoted {
+            parts.push(&text[start..pos]);
+            start = pos + 1;
+        }
+    }
+    if start < text.len() || !parts.is_empty() {
+        parts.push(&text[start..]);
+    }
+    parts
+}
+
+// 'it''s' to it's
+fn unquote(text: &str) -> Result<String, String> {
+    match text.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+        Some(inner) => Ok(inner.replace("''", "'")),
+        None => Err(format!("{} is not quoted", text)),
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+#[test]
+fn test_etp_uri() {
+    let uri = EtpUri::parse(
+        "eml:///dataspace('demo/Volve')/witsml20.Well(2A4F1B3E-8C9D-4E5F-A1B2-C3D4E5F60718)",
+    )
+    .unwrap();
+    assert_eq!(uri.kind(), EtpUriKind::DataObject);
+    assert_eq!(uri.dataspace.as_deref(), Some("demo/Volve"));
+    assert_eq!(uri.domain(), Some("witsml"));
+    assert_eq!(uri.domain_version(), Some("20"));
+    assert_eq!(uri.qualified_type().as_deref(), Some("witsml20.Well"));
+    assert_eq!(uri.uuid(), Some("2a4f1b3e-8c9d-4e5f-a1b2-c3d4e5f60718"));
+
+    let wellbores = uri.collection("witsml20.Wellbore").unwrap();
+    assert_eq!(wellbores.kind(), EtpUriKind::Collection);
+    assert_eq!(
+        String::from(wellbores.with_format("json")),
+        "eml:///dataspace('demo/Volve')/witsml20.Well(2a4f1b3e-8c9d-4e5f-a1b2-c3d4e5f60718)/witsml20.Wellbore?$format=json"
+    );
+
+    let wellbore = uri
+        .object("witsml20.Wellbore", "0b8ac9f2-9a3e-4d2b-8b7c-1f0e5d4c3b2a")
+        .unwrap();
+    assert_eq!(
+        wellbore.canonical().to_string(),
+        "eml:///dataspace('demo/Volve')/witsml20.Wellbore(0b8ac9f2-9a3e-4d2b-8b7c-1f0e5d4c3b2a)"
+    );
+
+    let versioned = EtpUri::try_from(
+        "eml:///resqml20.obj_Grid2dRepresentation(uuid=0b8ac9f2-9a3e-4d2b-8b7c-1f0e5d4c3b2a,version='2.0')",
+    )
+    .unwrap();
+    assert_eq!(versioned.version(), Some("2.0"));
+    assert_eq!(versioned.dataspace, None);
+
+    assert_eq!(EtpUri::parse("eml:///").unwrap(), EtpUri::root());
+    assert_eq!(
+        EtpUri::parse("eml:///dataspace('it''s')")
+            .unwrap()
+            .dataspace,
+        Some("it's".to_string())
+    );
+    assert!(EtpUri::parse("eml:///witsml20.Well(not-a-uuid)").is_err());
+    assert!(EtpUri::parse("eml:///witsml20.Well/witsml20.Wellbore").is_err());
+    assert!(EtpUri::parse("http://example.com").is_err());
+}
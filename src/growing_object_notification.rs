@@ -0,0 +1,345 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Growing Object Notification (Protocol 7) - Customer side.
+// Subscribes to part changes of growing data objects, and turns the notification messages into PartEvents.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error,
+    headerflags::*,
+    schema::*,
+    schema_gen::*,
+    session::{MapResponse, Session},
+};
+use apache_avro::{from_value, types::Value};
+#[allow(unused_imports)]
+use log::{info, trace, warn};
+use std::collections::HashMap;
+
+// A typed notification for a growing data object.
+#[derive(Debug, PartialEq, Clone)]
+pub enum PartEvent {
+    Changed(PartsChanged),
+    Deleted(PartsDeleted),
+    ReplacedByRange(PartsReplacedByRange),
+    // The store ended the subscription.  'uri' is the growing object, if the subscription was known.
+    SubscriptionEnded {
+        request_uuid: Uuid,
+        uri: Option<String>,
+        reason: String,
+    },
+}
+
+impl PartEvent {
+    pub fn request_uuid(&self) -> Uuid {
+        match self {
+            PartEvent::Changed(pc) => pc.request_uuid,
+            PartEvent::Deleted(pd) => pd.request_uuid,
+            PartEvent::ReplacedByRange(pr) => pr.request_uuid,
+            PartEvent::SubscriptionEnded { request_uuid, .. } => *request_uuid,
+        }
+    }
+
+    // URI of the growing data object the event is about.
+    pub fn uri(&self) -> Option<&str> {
+        match self {
+            PartEvent::Changed(pc) => Some(&pc.uri),
+            PartEvent::Deleted(pd) => Some(&pd.uri),
+            PartEvent::ReplacedByRange(pr) => Some(&pr.uri),
+            PartEvent::SubscriptionEnded { uri, .. } => uri.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PartSubscription {
+    pub info: SubscriptionInfo,
+    pub unsolicited: bool, // Created by the store (UnsolicitedPartNotifications), not requested by us
+    pub last_change_time: i64, // ChangeTime of the most recent notification, 0 if none yet
+}
+
+impl PartSubscription {
+    pub fn uri(&self) -> &str {
+        &self.info.context.uri
+    }
+}
+
+// Tracks the active part subscriptions of a session, keyed by request UUID.
+#[derive(Debug, Default)]
+pub struct PartSubscriptionManager {
+    subscriptions: HashMap<Uuid, PartSubscription>,
+}
+
+impl PartSubscriptionManager {
+    pub fn new() -> PartSubscriptionManager {
+        PartSubscriptionManager::default()
+    }
+
+    // Subscribes to part notifications for each growing object URI.
+    // Returns the request UUID per URI, and the error for any URI the store refused.
+    pub fn subscribe(
+        &mut self,
+        session: &mut Session,
+        uris: &[&str],
+        include_object_data: bool,
+        format: &str,
+    ) -> Result<MapResponse<Uuid>, Error> {
+        let mut request: HashMap<String, SubscriptionInfo> = HashMap::new();
+        for uri in uris {
+            request.insert(
+                uri.to_string(),
+                SubscriptionInfo {
+                    context: ContextInfo {
+                        uri: uri.to_string(),
+                        depth: 1,
+                        data_object_types: vec![],
+                        navigable_edges: RelationshipKind::Primary,
+                        include_secondary_targets: false,
+                        include_secondary_sources: false,
+                    },
+                    scope: ContextScopeKind::Self_,
+                    request_uuid: *uuid::Uuid::new_v4().as_bytes(),
+                    include_object_data,
+                    format: format.to_string(),
+                },
+            );
+        }
+
+        let msg_id = session.send_message(
+            SubscribePartNotifications {
+                request: request.clone(),
+            },
+            GROWINGOBJECTNOTIFICATION_SUBSCRIBEPARTNOTIFICATIONS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let response = session.read_map_response(
+            msg_id,
+            GROWINGOBJECTNOTIFICATION_SUBSCRIBEPARTNOTIFICATIONSRESPONSE,
+            |body| Ok(from_value::<SubscribePartNotificationsResponse>(body)?.success),
+        )?;
+
+        let mut result = MapResponse {
+            success: HashMap::new(),
+            errors: response.errors,
+        };
+        for key in response.success.keys() {
+            if let Some(info) = request.remove(key) {
+                result.success.insert(key.clone(), info.request_uuid);
+                self.subscriptions.insert(
+                    info.request_uuid,
+                    PartSubscription {
+                        info,
+                        unsolicited: false,
+                        last_change_time: 0,
+                    },
+                );
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Ends a subscription.  The store confirms with PartSubscriptionEnded.
+    pub fn unsubscribe(&mut self, session: &mut Session, request_uuid: Uuid) -> Result<(), Error> {
+        let msg_id = session.send_message(
+            UnsubscribePartNotification { request_uuid },
+            GROWINGOBJECTNOTIFICATION_UNSUBSCRIBEPARTNOTIFICATION,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let (msg_hdr, _msg_body) = session.read_response(msg_id)?;
+        self.subscriptions.remove(&request_uuid);
+
+        match msg_hdr.msgtype() {
+            GROWINGOBJECTNOTIFICATION_PARTSUBSCRIPTIONENDED => Ok(()),
+            _ => Err(Error::UnexpectedMessage(
+                msg_hdr.protocol,
+                msg_hdr.message_type,
+            )),
+        }
+    }
+
+    // Processes a message read from the session.
+    // Returns None for messages that are not Growing Object Notifications, so the caller can handle them.
+    pub fn handle_message(
+        &mut self,
+        msg_hdr: &MessageHeader,
+        msg_body: &Value,
+    ) -> Result<Option<PartEvent>, Error> {
+        let event = match msg_hdr.msgtype() {
+            GROWINGOBJECTNOTIFICATION_PARTSCHANGED => {
+                PartEvent::Changed(from_value::<PartsChanged>(msg_body)?)
+            }
+            GROWINGOBJECTNOTIFICATION_PARTSDELETED => {
+                PartEvent::Deleted(from_value::<PartsDeleted>(msg_body)?)
+            }
+            GROWINGOBJECTNOTIFICATION_PARTSREPLACEDBYRANGE => {
+                PartEvent::ReplacedByRange(from_value::<PartsReplacedByRange>(msg_body)?)
+            }
+            GROWINGOBJECTNOTIFICATION_PARTSUBSCRIPTIONENDED => {
+                let ended = from_value::<PartSubscriptionEnded>(msg_body)?;
+                let uri = self
+                    .subscriptions
+                    .remove(&ended.request_uuid)
+                    .map(|sub| sub.info.context.uri);
+                return Ok(Some(PartEvent::SubscriptionEnded {
+                    request_uuid: ended.request_uuid,
+                    uri,
+                    reason: ended.reason,
+                }));
+            }
+            GROWINGOBJECTNOTIFICATION_UNSOLICITEDPARTNOTIFICATIONS => {
+                let unsolicited = from_value::<UnsolicitedPartNotifications>(msg_body)?;
+                for info in unsolicited.subscriptions {
+                    self.subscriptions.insert(
+                        info.request_uuid,
+                        PartSubscription {
+                            info,
+                            unsolicited: true,
+                            last_change_time: 0,
+                        },
+                    );
+                }
+                return Ok(None);
+            }
+            _ => return Ok(None),
+        };
+
+        let change_time = match &event {
+            PartEvent::Changed(pc) => pc.change_time,
+            PartEvent::Deleted(pd) => pd.change_time,
+            PartEvent::ReplacedByRange(pr) => pr.change_time,
+            PartEvent::SubscriptionEnded { .. } => 0,
+        };
+        match self.subscriptions.get_mut(&event.request_uuid()) {
+            Some(sub) => sub.last_change_time = change_time,
+            None => warn!("Part notification for unknown subscription"),
+        }
+
+        Ok(Some(event))
+    }
+
+    pub fn get(&self, request_uuid: &Uuid) -> Option<&PartSubscription> {
+        self.subscriptions.get(request_uuid)
+    }
+
+    // Active subscriptions for a growing data object
+    pub fn subscriptions_for(&self, uri: &str) -> Vec<&PartSubscription> {
+        self.subscriptions
+            .values()
+            .filter(|sub| sub.uri() == uri)
+            .collect()
+    }
+
+    pub fn subscriptions(&self) -> impl Iterator<Item = &PartSubscription> {
+        self.subscriptions.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+}
+
+#[test]
+fn test_part_notifications() {
+    let mut manager = PartSubscriptionManager::new();
+    let request_uuid = *uuid::Uuid::new_v4().as_bytes();
+    let uri = "eml:///witsml20.Log(e3e6fd0d-5b7e-4b16-a85a-64e0a6f6a3e4)";
+
+    // Store initiated subscription
+    let unsolicited = UnsolicitedPartNotifications {
+        subscriptions: vec![SubscriptionInfo {
+            context: ContextInfo {
+                uri: uri.to_string(),
+                depth: 1,
+                data_object_types: vec![],
+                navigable_edges: RelationshipKind::Primary,
+                include_secondary_targets: false,
+                include_secondary_sources: false,
+            },
+            scope: ContextScopeKind::Self_,
+            request_uuid,
+            include_object_data: true,
+            format: "xml".to_string(),
+        }],
+    };
+    let body = roundtrip(
+        GROWINGOBJECTNOTIFICATION_UNSOLICITEDPARTNOTIFICATIONS,
+        unsolicited,
+    );
+    let hdr = MessageHeader {
+        protocol: GROWINGOBJECTNOTIFICATION_UNSOLICITEDPARTNOTIFICATIONS.0 as i32,
+        message_type: GROWINGOBJECTNOTIFICATION_UNSOLICITEDPARTNOTIFICATIONS.1 as i32,
+        correlation_id: 0,
+        message_id: 1,
+        message_flags: MSG_FLAG_FINAL,
+    };
+    assert_eq!(manager.handle_message(&hdr, &body).unwrap(), None);
+    assert_eq!(manager.subscriptions_for(uri).len(), 1);
+
+    let changed = PartsChanged {
+        uri: uri.to_string(),
+        request_uuid,
+        change_kind: ObjectChangeKind::Insert,
+        change_time: 1000,
+        format: "xml".to_string(),
+        parts: vec![ObjectPart {
+            uid: "p1".to_string(),
+            data: b"<part/>".to_vec(),
+        }],
+    };
+    let body = roundtrip(GROWINGOBJECTNOTIFICATION_PARTSCHANGED, changed.clone());
+    let hdr = MessageHeader {
+        protocol: GROWINGOBJECTNOTIFICATION_PARTSCHANGED.0 as i32,
+        message_type: GROWINGOBJECTNOTIFICATION_PARTSCHANGED.1 as i32,
+        correlation_id: 0,
+        message_id: 3,
+        message_flags: MSG_FLAG_FINAL,
+    };
+    let event = manager.handle_message(&hdr, &body).unwrap().unwrap();
+    assert_eq!(event.uri(), Some(uri));
+    assert_eq!(event, PartEvent::Changed(changed));
+    assert_eq!(manager.get(&request_uuid).unwrap().last_change_time, 1000);
+
+    let ended = PartSubscriptionEnded {
+        reason: "Store shutting down".to_string(),
+        request_uuid,
+    };
+    let body = roundtrip(GROWINGOBJECTNOTIFICATION_PARTSUBSCRIPTIONENDED, ended);
+    let hdr = MessageHeader {
+        protocol: GROWINGOBJECTNOTIFICATION_PARTSUBSCRIPTIONENDED.0 as i32,
+        message_type: GROWINGOBJECTNOTIFICATION_PARTSUBSCRIPTIONENDED.1 as i32,
+        correlation_id: 0,
+        message_id: 5,
+        message_flags: MSG_FLAG_FINAL,
+    };
+    let event = manager.handle_message(&hdr, &body).unwrap().unwrap();
+    assert_eq!(event.uri(), Some(uri));
+    assert!(manager.is_empty());
+}
@@ -0,0 +1,267 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Growing Object Query (Protocol 16) - Customer side.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error,
+    headerflags::*,
+    schema::*,
+    schema_gen::*,
+    session::{protocol_exception, Session},
+};
+use apache_avro::from_value;
+use std::collections::VecDeque;
+
+// Part data, decoded according to the format it was requested in.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PartPayload {
+    Xml(String),
+    Json(String),
+    Other(String, Vec<u8>), // Format, Raw Data
+}
+
+impl PartPayload {
+    pub fn decode(format: &str, data: &[u8]) -> Result<PartPayload, Error> {
+        match format {
+            "xml" | "json" => {
+                let text = match std::str::from_utf8(data) {
+                    Ok(text) => text.to_string(),
+                    Err(err) => {
+                        return Err(Error::Simple(format!(
+                            "Part payload is not valid UTF-8 {}: {}",
+                            format, err
+                        )))
+                    }
+                };
+                if format == "xml" {
+                    Ok(PartPayload::Xml(text))
+                } else {
+                    Ok(PartPayload::Json(text))
+                }
+            }
+            _ => Ok(PartPayload::Other(format.to_string(), data.to_vec())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FoundPart {
+    pub uid: String,
+    pub payload: PartPayload,
+}
+
+// Iterator over the parts matching a FindParts query.
+// Response messages are read from the session as the iterator is consumed.
+pub struct FindPartsStream<'a> {
+    session: &'a mut Session,
+    request_id: i64,
+    format: String,
+    server_sort_order: Option<String>,
+    parts: VecDeque<ObjectPart>,
+    finished: bool,
+}
+
+impl<'a> FindPartsStream<'a> {
+    // Sort order reported by the store.  Only known once the first response has been read.
+    pub fn server_sort_order(&mut self) -> Result<Option<&str>, Error> {
+        if self.server_sort_order.is_none() && !self.finished {
+            self.read_next()?;
+        }
+        Ok(self.server_sort_order.as_deref())
+    }
+
+    fn read_next(&mut self) -> Result<(), Error> {
+        let (msg_hdr, msg_body) = match self.session.read_correlated(self.request_id) {
+            Ok(msg) => msg,
+            Err(err) => {
+                // Nothing more can be read
+                self.finished = true;
+                return Err(err);
+            }
+        };
+        self.finished = msg_hdr.get_flags().finalmsg;
+
+        match msg_hdr.msgtype() {
+            GROWINGOBJECTQUERY_FINDPARTSRESPONSE => {}
+            CORE_PROTOCOLEXCEPTION => return Err(protocol_exception(&msg_hdr, &msg_body)),
+            _ => {
+                return Err(Error::UnexpectedMessage(
+                    msg_hdr.protocol,
+                    msg_hdr.message_type,
+                ))
+            }
+        }
+
+        let response = from_value::<FindPartsResponse>(&msg_body)?;
+        if self.server_sort_order.is_none() {
+            self.server_sort_order = Some(response.server_sort_order);
+        }
+        self.format = response.format;
+        self.parts.extend(response.parts);
+        Ok(())
+    }
+}
+
+// Stopping early leaves the rest of the response to be discarded, rather than returned by later reads.
+impl<'a> Drop for FindPartsStream<'a> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.session.discard_response(self.request_id);
+        }
+    }
+}
+
+impl<'a> Iterator for FindPartsStream<'a> {
+    type Item = Result<FoundPart, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.parts.is_empty() && !self.finished {
+            if let Err(err) = self.read_next() {
+                return Some(Err(err));
+            }
+        }
+
+        let part = self.parts.pop_front()?;
+        Some(
+            PartPayload::decode(&self.format, &part.data).map(|payload| FoundPart {
+                uid: part.uid,
+                payload,
+            }),
+        )
+    }
+}
+
+impl Session {
+    // Finds the parts of a growing data object.  The query is expressed in the URI (eg. a $filter on the part
+    // collection), and the parts are returned in the requested format ("xml" or "json").
+    pub fn find_parts(&mut self, uri: &str, format: &str) -> Result<FindPartsStream<'_>, Error> {
+        let request_id = self.send_message(
+            FindParts {
+                uri: uri.to_string(),
+                format: format.to_string(),
+            },
+            GROWINGOBJECTQUERY_FINDPARTS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        Ok(FindPartsStream {
+            session: self,
+            request_id,
+            format: format.to_string(),
+            server_sort_order: None,
+            parts: VecDeque::new(),
+            finished: false,
+        })
+    }
+}
+
+#[test]
+fn test_part_payload_decode() {
+    let payload = PartPayload::decode("xml", b"<TrajectoryStation uid=\"1\"/>").unwrap();
+    assert_eq!(
+        payload,
+        PartPayload::Xml("<TrajectoryStation uid=\"1\"/>".to_string())
+    );
+
+    let payload = PartPayload::decode("json", b"{\"uid\":\"1\"}").unwrap();
+    assert_eq!(payload, PartPayload::Json("{\"uid\":\"1\"}".to_string()));
+
+    let payload = PartPayload::decode("bin", &[0, 159, 146, 150]).unwrap();
+    assert_eq!(
+        payload,
+        PartPayload::Other("bin".to_string(), vec![0, 159, 146, 150])
+    );
+
+    assert!(PartPayload::decode("xml", &[0, 159, 146, 150]).is_err());
+}
+
+#[test]
+fn test_find_parts_exception() {
+    use crate::session::TestPeer;
+
+    let (mut session, mut peer) = TestPeer::connect();
+    let response = |uid: &str| FindPartsResponse {
+        uri: "eml:///witsml20.Log(a)".to_string(),
+        server_sort_order: "".to_string(),
+        format: "xml".to_string(),
+        parts: vec![ObjectPart {
+            uid: uid.to_string(),
+            data: b"<Part/>".to_vec(),
+        }],
+    };
+    let exception = ProtocolException {
+        error: None,
+        errors: [(
+            "0".to_string(),
+            ErrorInfo {
+                message: "Invalid filter".to_string(),
+                code: 5,
+            },
+        )]
+        .into_iter()
+        .collect(),
+    };
+
+    // The exception is the store's error, and does not end the response
+    let mut parts = session
+        .find_parts("eml:///witsml20.Log(a)/Data", "xml")
+        .unwrap();
+    let request_id = parts.request_id;
+    peer.send(exception, CORE_PROTOCOLEXCEPTION, request_id, false);
+    peer.send(
+        response("1"),
+        GROWINGOBJECTQUERY_FINDPARTSRESPONSE,
+        request_id,
+        false,
+    );
+    peer.send(
+        response("2"),
+        GROWINGOBJECTQUERY_FINDPARTSRESPONSE,
+        request_id,
+        true,
+    );
+    peer.send(
+        Pong {
+            current_date_time: 0,
+        },
+        CORE_PONG,
+        0,
+        true,
+    );
+    match parts.next() {
+        Some(Err(Error::ProtocolException(code, message))) => {
+            assert_eq!((code, message.as_str()), (5, "Invalid filter"))
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(parts.next().unwrap().unwrap().uid, "1");
+
+    // Dropped before the end, the rest of the response is not returned to later reads
+    drop(parts);
+    let (msg_hdr, _) = session.read_message().unwrap();
+    assert_eq!(msg_hdr.msgtype(), CORE_PONG);
+}
@@ -20,6 +20,7 @@
 //
 // Author: Mark Farnan
 
+use crate::error::Error;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn time_to_etp(time: SystemTime) -> i64 {
@@ -29,3 +30,14 @@ pub fn time_to_etp(time: SystemTime) -> i64 {
     let in_ms = since_the_epoch.as_millis();
     return in_ms.try_into().unwrap();
 }
+
+// ETP timestamps (store_last_write, filters etc) are microseconds since the epoch.
+pub fn time_to_etp_micros(time: SystemTime) -> Result<i64, Error> {
+    let since_the_epoch = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| Error::Simple("Time is before the Unix epoch".to_string()))?;
+    since_the_epoch
+        .as_micros()
+        .try_into()
+        .map_err(|_| Error::Simple("Time is out of range for an ETP timestamp".to_string()))
+}
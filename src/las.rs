@@ -0,0 +1,490 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// LAS 2.0 export of channel data.
+// The first curve is the primary index (DEPT for depths, TIME for times, in seconds since the Unix epoch
+// for DateTime indexes).  Each row is one index value; channels without a value there get the NULL value.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    channel_data_frame::Frame,
+    channel_index::{ChannelIndex, Index},
+    channel_subscribe::ChannelSample,
+    error::Error,
+    schema_gen::*,
+};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LasItem {
+    pub mnemonic: String,
+    pub unit: String,
+    pub value: String,
+    pub description: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LasWriter {
+    pub null_value: f64,
+    pub well: Vec<LasItem>, // ~Well items after STRT, STOP, STEP and NULL, eg. WELL, COMP, FLD, UWI
+}
+
+impl Default for LasWriter {
+    fn default() -> Self {
+        LasWriter {
+            null_value: -999.25,
+            well: vec![],
+        }
+    }
+}
+
+// Curve data to be written: the index, and a value per curve for each index.
+struct LasTable {
+    index: IndexMetadataRecord,
+    curves: Vec<LasItem>,
+    rows: BTreeMap<ChannelIndex, Vec<DataValue>>,
+}
+
+impl LasWriter {
+    pub fn new() -> LasWriter {
+        LasWriter::default()
+    }
+
+    pub fn well_item(mut self, mnemonic: &str, unit: &str, value: &str, description: &str) -> Self {
+        self.well.push(LasItem {
+            mnemonic: mnemonic.to_string(),
+            unit: unit.to_string(),
+            value: value.to_string(),
+            description: description.to_string(),
+        });
+        self
+    }
+
+    // Channels collected from ChannelSubscribe (or a ChannelCache).  The channels must all have the same
+    // kind of primary index; samples of other channels are ignored.
+    pub fn write_channels<W: Write>(
+        &self,
+        out: W,
+        channels: &[ChannelMetadataRecord],
+        samples: &[ChannelSample],
+    ) -> Result<(), Error> {
+        let index = primary_index(channels.iter().map(|c| (c.uri.as_str(), &c.indexes)))?;
+        let mut table = LasTable::new(index);
+        let columns: Vec<(i64, usize)> = channels
+            .iter()
+            .map(|channel| {
+                let column = table.add_curve(
+                    &channel.channel_name,
+                    &channel.uom,
+                    &channel.uri,
+                    &channel.axis_vector_lengths,
+                )?;
+                Ok((channel.id, column))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        for sample in samples {
+            if let Some((_, column)) = columns.iter().find(|(id, _)| *id == sample.channel_id) {
+                table.set(sample.indexes.first(), *column, &sample.value)?;
+            }
+        }
+        self.write_table(out, &table)
+    }
+
+    // A ChannelDataFrame response.
+    pub fn write_frame<W: Write>(&self, out: W, frame: &Frame) -> Result<(), Error> {
+        let index = frame
+            .index_metadata
+            .first()
+            .ok_or_else(|| Error::Simple("Frame has no index".to_string()))?;
+        let mut table = LasTable::new(index.clone());
+
+        for column in &frame.columns {
+            let curve = match &column.metadata {
+                Some(m) => {
+                    table.add_curve(&m.channel_name, &m.uom, &m.uri, &m.axis_vector_lengths)?
+                }
+                None => table.add_curve(&column.uri, "", &column.uri, &[])?,
+            };
+            for (row, value) in column.values.iter().enumerate() {
+                let index = frame.indexes.first().and_then(|indexes| indexes.get(row));
+                table.set(index, curve, value)?;
+            }
+        }
+        self.write_table(out, &table)
+    }
+
+    fn write_table<W: Write>(&self, mut out: W, table: &LasTable) -> Result<(), Error> {
+        let null = format_number(self.null_value);
+        let is_time = matches!(
+            table.index.index_kind,
+            ChannelIndexKind::DateTime | ChannelIndexKind::ElapsedTime
+        );
+        let is_pass = table.index.index_kind == ChannelIndexKind::PassIndexedDepth;
+        let index_unit = match table.index.index_kind {
+            ChannelIndexKind::DateTime => "s".to_string(),
+            _ => table.index.uom.clone(),
+        };
+
+        let index_values: Vec<f64> = table.rows.keys().map(index_number).collect();
+        let first = index_values.first().map(|v| format_number(*v));
+        let last = index_values.last().map(|v| format_number(*v));
+
+        writeln!(out, "~VERSION INFORMATION")?;
+        write_item(
+            &mut out,
+            "VERS",
+            "",
+            "2.0",
+            "CWLS LOG ASCII STANDARD - VERSION 2.0",
+        )?;
+        write_item(&mut out, "WRAP", "", "NO", "ONE LINE PER DEPTH STEP")?;
+
+        writeln!(out, "~WELL INFORMATION")?;
+        let (start, stop) = if is_time {
+            ("START TIME", "STOP TIME")
+        } else {
+            ("START DEPTH", "STOP DEPTH")
+        };
+        write_item(
+            &mut out,
+            "STRT",
+            &index_unit,
+            first.as_deref().unwrap_or(&null),
+            start,
+        )?;
+        write_item(
+            &mut out,
+            "STOP",
+            &index_unit,
+            last.as_deref().unwrap_or(&null),
+            stop,
+        )?;
+        write_item(
+            &mut out,
+            "STEP",
+            &index_unit,
+            &format_number(step(&index_values)),
+            "STEP",
+        )?;
+        write_item(&mut out, "NULL", "", &null, "NULL VALUE")?;
+        for item in &self.well {
+            write_item(
+                &mut out,
+                &item.mnemonic,
+                &item.unit,
+                &item.value,
+                &item.description,
+            )?;
+        }
+
+        writeln!(out, "~CURVE INFORMATION")?;
+        let index_description = match table.index.index_kind {
+            ChannelIndexKind::DateTime => "SECONDS SINCE 1970-01-01T00:00:00Z".to_string(),
+            _ => table.index.name.clone(),
+        };
+        let index_mnemonic = if is_time { "TIME" } else { "DEPT" };
+        write_item(
+            &mut out,
+            index_mnemonic,
+            &index_unit,
+            "",
+            &index_description,
+        )?;
+        if is_pass {
+            write_item(&mut out, "PASS", "", "", "PASS NUMBER")?;
+        }
+        for curve in &table.curves {
+            write_item(
+                &mut out,
+                &curve.mnemonic,
+                &curve.unit,
+                "",
+                &curve.description,
+            )?;
+        }
+
+        writeln!(out, "~ASCII")?;
+        for ((index, values), number) in table.rows.iter().zip(&index_values) {
+            let mut line = format!("{:>14}", format_number(*number));
+            if let Index::PassIndexedDepth(pass) = &index.index {
+                line.push_str(&format!(" {:>14}", pass.pass));
+            }
+            for (value, curve) in values.iter().zip(&table.curves) {
+                let value = format_value(value)
+                    .map_err(|err| Error::Simple(format!("Curve {}: {}", curve.mnemonic, err)))?;
+                line.push_str(&format!(" {:>14}", value.as_deref().unwrap_or(&null)));
+            }
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl LasTable {
+    fn new(index: IndexMetadataRecord) -> LasTable {
+        LasTable {
+            index,
+            curves: vec![],
+            rows: BTreeMap::new(),
+        }
+    }
+
+    // Adds a curve, with a unique mnemonic made from the name.  Returns its column.
+    fn add_curve(
+        &mut self,
+        name: &str,
+        uom: &str,
+        description: &str,
+        axis_vector_lengths: &[i32],
+    ) -> Result<usize, Error> {
+        if !axis_vector_lengths.is_empty() {
+            return Err(Error::Simple(format!(
+                "Array channel {} cannot be written to LAS",
+                name
+            )));
+        }
+
+        let base: String = name
+            .chars()
+            .map(|c| match c {
+                '.' | ':' | '~' | '#' => '_',
+                c if c.is_whitespace() => '_',
+                c => c,
+            })
+            .collect();
+        let taken: HashSet<&str> = self
+            .curves
+            .iter()
+            .map(|c| c.mnemonic.as_str())
+            .chain(["DEPT", "TIME", "PASS"])
+            .collect();
+        let mut mnemonic = base.clone();
+        let mut n = 1;
+        while taken.contains(mnemonic.as_str()) {
+            mnemonic = format!("{}_{}", base, n);
+            n += 1;
+        }
+
+        self.curves.push(LasItem {
+            mnemonic,
+            unit: uom.to_string(),
+            value: String::new(),
+            description: description.to_string(),
+        });
+        for values in self.rows.values_mut() {
+            values.push(DataValue::null());
+        }
+        Ok(self.curves.len() - 1)
+    }
+
+    fn set(
+        &mut self,
+        index: Option<&IndexValue>,
+        column: usize,
+        value: &DataValue,
+    ) -> Result<(), Error> {
+        let Some(index) = index
+            .map(|index| ChannelIndex::from_value(index, &self.index))
+            .transpose()?
+            .flatten()
+        else {
+            return Err(Error::Simple("Data without an index value".to_string()));
+        };
+        let columns = self.curves.len();
+        let values = self
+            .rows
+            .entry(index)
+            .or_insert_with(|| vec![DataValue::null(); columns]);
+        values[column] = value.clone();
+        Ok(())
+    }
+}
+
+fn primary_index<'a>(
+    mut indexes: impl Iterator<Item = (&'a str, &'a Vec<IndexMetadataRecord>)>,
+) -> Result<IndexMetadataRecord, Error> {
+    let (_, first) = indexes
+        .next()
+        .ok_or_else(|| Error::Simple("No channels to write".to_string()))?;
+    let index = first
+        .first()
+        .ok_or_else(|| Error::Simple("Channel has no index".to_string()))?;
+    for (uri, other) in indexes {
+        if other.first().map(|i| &i.index_kind) != Some(&index.index_kind) {
+            return Err(Error::Simple(format!(
+                "Channel {} is not indexed by {:?}",
+                uri, index.index_kind
+            )));
+        }
+    }
+    Ok(index.clone())
+}
+
+fn index_number(index: &ChannelIndex) -> f64 {
+    match &index.index {
+        Index::DateTime(v) => *v as f64 / 1e6,
+        Index::ElapsedTime(v) => *v as f64,
+        Index::Depth(v) | Index::Scalar(v) => *v,
+        Index::PassIndexedDepth(v) => v.depth,
+    }
+}
+
+// The spacing of the index, or 0 if it is not constant.
+fn step(values: &[f64]) -> f64 {
+    let Some(step) = values.windows(2).map(|pair| pair[1] - pair[0]).next() else {
+        return 0.0;
+    };
+    let tolerance = step.abs() * 1e-6;
+    if values
+        .windows(2)
+        .all(|pair| ((pair[1] - pair[0]) - step).abs() <= tolerance)
+    {
+        step
+    } else {
+        0.0
+    }
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{:.1}", value)
+    } else {
+        format!("{}", value)
+    }
+}
+
+// None for nulls.  LAS 2.0 data must be numeric.
+fn format_value(value: &DataValue) -> Result<Option<String>, Error> {
+    Ok(match &value.item {
+        DataValueEnum::Null => None,
+        DataValueEnum::Boolean(v) => Some(if *v { "1" } else { "0" }.to_string()),
+        DataValueEnum::Int(v) => Some(v.to_string()),
+        DataValueEnum::Long(v) => Some(v.to_string()),
+        DataValueEnum::Float(v) => Some(format_number(*v as f64)),
+        DataValueEnum::Double(v) => Some(format_number(*v)),
+        _ => {
+            return Err(Error::Simple(format!(
+                "{} values cannot be written to LAS 2.0",
+                value.type_name()
+            )))
+        }
+    })
+}
+
+fn write_item<W: Write>(
+    out: &mut W,
+    mnemonic: &str,
+    unit: &str,
+    value: &str,
+    description: &str,
+) -> Result<(), Error> {
+    writeln!(
+        out,
+        " {:<8}.{:<10} {:>20} : {}",
+        mnemonic, unit, value, description
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_las_writer() {
+    let index = IndexMetadataRecord {
+        index_kind: ChannelIndexKind::MeasuredDepth,
+        interval: IndexInterval {
+            start_index: IndexValue { item: None },
+            end_index: IndexValue { item: None },
+            uom: "m".to_string(),
+            depth_datum: "".to_string(),
+        },
+        direction: IndexDirection::Increasing,
+        name: "MD".to_string(),
+        uom: "m".to_string(),
+        depth_datum: "".to_string(),
+        index_property_kind_uri: "".to_string(),
+        filterable: true,
+    };
+    let channel = |id: i64, name: &str, uom: &str| ChannelMetadataRecord {
+        uri: format!("eml:///witsml20.Channel({})", name),
+        id,
+        indexes: vec![index.clone()],
+        channel_name: name.to_string(),
+        data_kind: ChannelDataKind::TypeDouble,
+        uom: uom.to_string(),
+        depth_datum: "".to_string(),
+        channel_class_uri: "".to_string(),
+        status: ActiveStatusKind::Active,
+        source: "".to_string(),
+        axis_vector_lengths: vec![],
+        attribute_metadata: vec![],
+        custom_data: std::collections::HashMap::new(),
+    };
+    let sample = |id: i64, depth: f64, value: DataValue| ChannelSample {
+        channel_id: id,
+        uri: "".to_string(),
+        channel_name: "".to_string(),
+        indexes: vec![IndexValue {
+            item: Some(UnionLongDoublePassIndexedDepth::Double(depth)),
+        }],
+        value,
+        value_attributes: vec![],
+    };
+
+    let channels = vec![channel(1, "ROP", "m/h"), channel(2, "Hook Load", "kN")];
+    let samples = vec![
+        sample(1, 100.5, DataValue::from(12.5)),
+        sample(2, 100.0, DataValue::from(250)),
+        sample(1, 100.0, DataValue::from(11.0)),
+    ];
+
+    let mut out = Vec::new();
+    LasWriter::new()
+        .well_item("WELL", "", "Well 1", "WELL")
+        .write_channels(&mut out, &channels, &samples)
+        .unwrap();
+    let las = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = las.lines().collect();
+
+    assert_eq!(lines[0], "~VERSION INFORMATION");
+    assert!(lines.contains(&" STEP    .m                           0.5 : STEP"));
+    assert!(lines.contains(&" WELL    .                         Well 1 : WELL"));
+    assert!(lines
+        .iter()
+        .any(|l| l.starts_with(" Hook_Load.kN")
+            && l.ends_with(": eml:///witsml20.Channel(Hook Load)")));
+    let ascii = lines.iter().position(|l| *l == "~ASCII").unwrap();
+    assert_eq!(
+        lines[ascii + 1].split_whitespace().collect::<Vec<_>>(),
+        vec!["100.0", "11.0", "250"]
+    );
+    assert_eq!(
+        lines[ascii + 2].split_whitespace().collect::<Vec<_>>(),
+        vec!["100.5", "12.5", "-999.25"]
+    );
+
+    let text = vec![sample(1, 101.0, DataValue::from("x"))];
+    assert!(LasWriter::new()
+        .write_channels(&mut Vec::new(), &channels, &text)
+        .is_err());
+}
@@ -24,13 +24,45 @@
 #![allow(unused_variables)]
 #![allow(unused_imports)]
 
+#[cfg(feature = "arrow")]
+pub mod arrow_export;
+pub mod avro_json;
+pub mod channel_cache;
+#[cfg(feature = "csv")]
+pub mod channel_csv;
+pub mod channel_data_frame;
+pub mod channel_data_load;
+pub mod channel_index;
+pub mod channel_streaming;
+pub mod channel_subscribe;
+pub mod data_array;
+pub mod data_value;
+pub mod dataspace;
+pub mod discovery;
+#[cfg(feature = "epc")]
+pub mod epc;
 pub mod error;
+pub mod etp_uri;
+pub mod growing_object_notification;
+pub mod growing_object_query;
 pub mod headerflags;
 pub mod helpers;
+pub mod las;
+pub mod logical_array;
+#[cfg(feature = "ndarray")]
+pub mod ndarray_support;
+#[cfg(feature = "payload")]
+pub mod object_payload;
 pub mod schema;
 pub mod schema_extensions;
 pub mod schema_gen;
 pub mod session;
+pub mod store;
+pub mod supported_types;
+pub mod transaction;
+#[cfg(feature = "uom")]
+pub mod uom;
+pub mod witsml_soap;
 
 use crate::{headerflags::*, schema::*, schema_gen::*};
 use apache_avro::from_value;
@@ -39,7 +71,7 @@ use http_auth_basic::Credentials;
 
 #[allow(unused_imports)]
 use log::{info, trace, warn};
-use session::Session;
+use session::{Encoding, Session};
 use tungstenite::{connect, handshake::client::generate_key, http::Request};
 use url::Url;
 
@@ -55,6 +87,14 @@ pub struct Config {
 }
 
 pub fn etp_get_server_capabilities(u: &str) -> Result<ServerCapabilities, error::Error> {
+    etp_get_server_capabilities_with_encoding(u, Encoding::Binary)
+}
+
+// As etp_get_server_capabilities, requesting the capabilities in the given encoding ($format=binary or json).
+pub fn etp_get_server_capabilities_with_encoding(
+    u: &str,
+    encoding: Encoding,
+) -> Result<ServerCapabilities, error::Error> {
     // Fix up URL for well known endpoint
     let mut url = Url::parse(u)?;
 
@@ -67,9 +107,10 @@ pub fn etp_get_server_capabilities(u: &str) -> Result<ServerCapabilities, error:
     url.path_segments_mut().unwrap().push(""); // Ensure trailing slash so it dosn't get pruned !
 
     let url = url
-        .join(
-            "./.well-known/etp-server-capabilities?GetVersion=etp12.energistics.org&$format=binary",
-        )
+        .join(&format!(
+            "./.well-known/etp-server-capabilities?GetVersion=etp12.energistics.org&$format={}",
+            encoding.as_str()
+        ))
         .unwrap();
 
     // Get the Server Cap
@@ -86,12 +127,22 @@ pub fn etp_get_server_capabilities(u: &str) -> Result<ServerCapabilities, error:
         )));
     }
 
+    let content_type = match encoding {
+        Encoding::Binary => "avro/binary",
+        Encoding::Json => "application/json",
+    };
     match resp.headers().get("content-type") {
         Some(ct) => {
-            if ct != "avro/binary" {
-                return Err(error::Error::Simple(
-                    "Content Type not avro/binary".to_string(),
-                ));
+            // JSON may come with a charset parameter
+            if !ct
+                .to_str()
+                .map(|ct| ct.starts_with(content_type))
+                .unwrap_or(false)
+            {
+                return Err(error::Error::Simple(format!(
+                    "Content Type not {}",
+                    content_type
+                )));
             }
         }
         None => return Err(error::Error::Simple("No Content Type".to_string())),
@@ -99,20 +150,24 @@ pub fn etp_get_server_capabilities(u: &str) -> Result<ServerCapabilities, error:
 
     // Deserialize
     let msg_schema = MsgSchema::new();
+    let key = (
+        "Energistics.Etp.v12.Datatypes".to_string(),
+        "ServerCapabilities".to_string(),
+    );
 
-    let mut cap_bytes = match resp.bytes() {
+    let cap_bytes = match resp.bytes() {
         // Read Body
         Err(err) => return Err(error::Error::Simple(err.to_string())),
-        Ok(cap) => cap.reader(),
+        Ok(cap) => cap,
     };
 
-    let servercap = from_value::<ServerCapabilities>(&msg_schema.deserialize_any(
-        (
-            "Energistics.Etp.v12.Datatypes".to_string(),
-            "ServerCapabilities".to_string(),
-        ),
-        &mut cap_bytes,
-    )?)?;
+    let value = match encoding {
+        Encoding::Binary => msg_schema.deserialize_any(key, &mut cap_bytes.reader())?,
+        Encoding::Json => {
+            msg_schema.deserialize_any_json(key, &serde_json::from_slice(&cap_bytes)?)?
+        }
+    };
+    let servercap = from_value::<ServerCapabilities>(&value)?;
 
     Ok(servercap)
 }
@@ -122,6 +177,17 @@ pub fn etp_connect(
     uname: &str,
     password: &str,
     request_session: RequestSession,
+) -> Result<Session, error::Error> {
+    etp_connect_with_encoding(url, uname, password, request_session, Encoding::Binary)
+}
+
+// As etp_connect, with the message encoding for the session (sent as the etp-encoding header).
+pub fn etp_connect_with_encoding(
+    url: &str,
+    uname: &str,
+    password: &str,
+    request_session: RequestSession,
+    encoding: Encoding,
 ) -> Result<Session, error::Error> {
     let credentials = Credentials::new(uname, password);
     let credentials = credentials.as_http_header();
@@ -137,6 +203,7 @@ pub fn etp_connect(
         .header("Sec-WebSocket-Protocol", "etp12.energistics.org")
         .header("MaxWebSocketFramePayloadSize", "4194304")
         .header("MaxWebSocketMessagePayloadSize", "16777216")
+        .header("etp-encoding", encoding.as_str())
         .body(())
         .unwrap();
 
@@ -145,6 +212,7 @@ pub fn etp_connect(
     info!("Connected to server at {}", "fred");
 
     let mut session = Session::new(ws_con);
+    session.set_encoding(encoding);
 
     session.send_message(
         &request_session,
@@ -0,0 +1,644 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Logical array types.
+// A store may transport an array as bytes, or as a wider Avro type than its logical type (eg. uint16 in
+// arrayOfInt).  LogicalArray holds the values as the logical type says, and packs them back for sending.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{error::Error, schema_gen::*};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LogicalArray {
+    Boolean(Vec<bool>),
+    Int8(Vec<i8>),
+    UInt8(Vec<u8>),
+    Int16(Vec<i16>),
+    UInt16(Vec<u16>),
+    Int32(Vec<i32>),
+    UInt32(Vec<u32>),
+    Int64(Vec<i64>),
+    UInt64(Vec<u64>),
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+    String(Vec<String>),
+}
+
+// Bytes per value when packed.  None for strings and custom arrays.
+pub fn element_size(logical_type: &AnyLogicalArrayType) -> Option<usize> {
+    use AnyLogicalArrayType::*;
+    match logical_type {
+        ArrayOfBoolean | ArrayOfInt8 | ArrayOfUInt8 => Some(1),
+        ArrayOfInt16Le | ArrayOfInt16Be | ArrayOfUInt16Le | ArrayOfUInt16Be => Some(2),
+        ArrayOfInt32Le | ArrayOfInt32Be | ArrayOfUInt32Le | ArrayOfUInt32Be => Some(4),
+        ArrayOfFloat32Le | ArrayOfFloat32Be => Some(4),
+        ArrayOfInt64Le | ArrayOfInt64Be | ArrayOfUInt64Le | ArrayOfUInt64Be => Some(8),
+        ArrayOfDouble64Le | ArrayOfDouble64Be => Some(8),
+        ArrayOfString | ArrayOfCustom => None,
+    }
+}
+
+pub fn byte_order(logical_type: &AnyLogicalArrayType) -> ByteOrder {
+    use AnyLogicalArrayType::*;
+    match logical_type {
+        ArrayOfInt16Be | ArrayOfInt32Be | ArrayOfInt64Be | ArrayOfUInt16Be | ArrayOfUInt32Be
+        | ArrayOfUInt64Be | ArrayOfFloat32Be | ArrayOfDouble64Be => ByteOrder::BigEndian,
+        _ => ByteOrder::LittleEndian,
+    }
+}
+
+impl LogicalArray {
+    // Decodes a transported array.  Values in a wider Avro type are narrowed to the logical type,
+    // and it is an error if one does not fit.
+    pub fn decode(
+        array: &AnyArrayUnion,
+        logical_type: &AnyLogicalArrayType,
+    ) -> Result<LogicalArray, Error> {
+        use AnyLogicalArrayType::*;
+        match (array, logical_type) {
+            (AnyArrayUnion::Bytes(bytes), _) => LogicalArray::from_bytes(bytes, logical_type),
+            (AnyArrayUnion::ArrayOfBoolean(a), ArrayOfBoolean) => {
+                Ok(LogicalArray::Boolean(a.values.clone()))
+            }
+            (AnyArrayUnion::ArrayOfString(a), ArrayOfString) => {
+                Ok(LogicalArray::String(a.values.clone()))
+            }
+            (AnyArrayUnion::ArrayOfInt(a), _) => {
+                LogicalArray::from_integers(&a.values, array, logical_type)
+            }
+            (AnyArrayUnion::ArrayOfLong(a), _) => {
+                LogicalArray::from_integers(&a.values, array, logical_type)
+            }
+            (AnyArrayUnion::ArrayOfFloat(a), ArrayOfFloat32Le | ArrayOfFloat32Be) => {
+                Ok(LogicalArray::Float32(a.values.clone()))
+            }
+            (AnyArrayUnion::ArrayOfFloat(a), ArrayOfDouble64Le | ArrayOfDouble64Be) => Ok(
+                LogicalArray::Float64(a.values.iter().map(|v| *v as f64).collect()),
+            ),
+            (AnyArrayUnion::ArrayOfDouble(a), ArrayOfDouble64Le | ArrayOfDouble64Be) => {
+                Ok(LogicalArray::Float64(a.values.clone()))
+            }
+            _ => Err(mismatch(array, logical_type)),
+        }
+    }
+
+    // Decodes an array with its dimensions (eg. from DataArrayMetadata), checking it holds exactly
+    // the number of values the dimensions give.
+    pub fn decode_array(
+        data: &AnyArray,
+        dimensions: &[i64],
+        logical_type: &AnyLogicalArrayType,
+    ) -> Result<LogicalArray, Error> {
+        let count = value_count(dimensions)?;
+
+        if let (AnyArrayUnion::Bytes(bytes), Some(size)) = (&data.item, element_size(logical_type))
+        {
+            if Some(bytes.len()) != count.checked_mul(size) {
+                return Err(Error::Simple(format!(
+                    "{} bytes of {:?}, expected {} values for dimensions {:?}",
+                    bytes.len(),
+                    logical_type,
+                    count,
+                    dimensions
+                )));
+            }
+        }
+
+        let array = LogicalArray::decode(&data.item, logical_type)?;
+        if array.len() != count {
+            return Err(Error::Simple(format!(
+                "{} values, expected {} for dimensions {:?}",
+                array.len(),
+                count,
+                dimensions
+            )));
+        }
+        Ok(array)
+    }
+
+    // Packs the values in bytes (strings stay an arrayOfString), with the logical type to declare for them.
+    pub fn encode(&self, order: ByteOrder) -> (AnyArrayUnion, AnyLogicalArrayType) {
+        use AnyLogicalArrayType::*;
+        let big = order == ByteOrder::BigEndian;
+        let (bytes, logical_type) = match self {
+            LogicalArray::Boolean(v) => (v.iter().map(|b| *b as u8).collect(), ArrayOfBoolean),
+            LogicalArray::Int8(v) => (pack(v, i8::to_le_bytes), ArrayOfInt8),
+            LogicalArray::UInt8(v) => (v.clone(), ArrayOfUInt8),
+            LogicalArray::Int16(v) if big => (pack(v, i16::to_be_bytes), ArrayOfInt16Be),
+            LogicalArray::Int16(v) => (pack(v, i16::to_le_bytes), ArrayOfInt16Le),
+            LogicalArray::UInt16(v) if big => (pack(v, u16::to_be_bytes), ArrayOfUInt16Be),
+            LogicalArray::UInt16(v) => (pack(v, u16::to_le_bytes), ArrayOfUInt16Le),
+            LogicalArray::Int32(v) if big => (pack(v, i32::to_be_bytes), ArrayOfInt32Be),
+            LogicalArray::Int32(v) => (pack(v, i32::to_le_bytes), ArrayOfInt32Le),
+            LogicalArray::UInt32(v) if big => (pack(v, u32::to_be_bytes), ArrayOfUInt32Be),
+            LogicalArray::UInt32(v) => (pack(v, u32::to_le_bytes), ArrayOfUInt32Le),
+            LogicalArray::Int64(v) if big => (pack(v, i64::to_be_bytes), ArrayOfInt64Be),
+            LogicalArray::Int64(v) => (pack(v, i64::to_le_bytes), ArrayOfInt64Le),
+            LogicalArray::UInt64(v) if big => (pack(v, u64::to_be_bytes), ArrayOfUInt64Be),
+            LogicalArray::UInt64(v) => (pack(v, u64::to_le_bytes), ArrayOfUInt64Le),
+            LogicalArray::Float32(v) if big => (pack(v, f32::to_be_bytes), ArrayOfFloat32Be),
+            LogicalArray::Float32(v) => (pack(v, f32::to_le_bytes), ArrayOfFloat32Le),
+            LogicalArray::Float64(v) if big => (pack(v, f64::to_be_bytes), ArrayOfDouble64Be),
+            LogicalArray::Float64(v) => (pack(v, f64::to_le_bytes), ArrayOfDouble64Le),
+            LogicalArray::String(v) => {
+                return (
+                    AnyArrayUnion::ArrayOfString(crate::schema_gen::ArrayOfString {
+                        values: v.clone(),
+                    }),
+                    ArrayOfString,
+                )
+            }
+        };
+        (AnyArrayUnion::Bytes(bytes), logical_type)
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            LogicalArray::Boolean(v) => v.len(),
+            LogicalArray::Int8(v) => v.len(),
+            LogicalArray::UInt8(v) => v.len(),
+            LogicalArray::Int16(v) => v.len(),
+            LogicalArray::UInt16(v) => v.len(),
+            LogicalArray::Int32(v) => v.len(),
+            LogicalArray::UInt32(v) => v.len(),
+            LogicalArray::Int64(v) => v.len(),
+            LogicalArray::UInt64(v) => v.len(),
+            LogicalArray::Float32(v) => v.len(),
+            LogicalArray::Float64(v) => v.len(),
+            LogicalArray::String(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn from_bytes(bytes: &[u8], logical_type: &AnyLogicalArrayType) -> Result<LogicalArray, Error> {
+        use AnyLogicalArrayType::*;
+        Ok(match logical_type {
+            ArrayOfBoolean => LogicalArray::Boolean(bytes.iter().map(|b| *b != 0).collect()),
+            ArrayOfInt8 => LogicalArray::Int8(unpack(bytes, i8::from_le_bytes)?),
+            ArrayOfUInt8 => LogicalArray::UInt8(bytes.to_vec()),
+            ArrayOfInt16Le => LogicalArray::Int16(unpack(bytes, i16::from_le_bytes)?),
+            ArrayOfInt16Be => LogicalArray::Int16(unpack(bytes, i16::from_be_bytes)?),
+            ArrayOfUInt16Le => LogicalArray::UInt16(unpack(bytes, u16::from_le_bytes)?),
+            ArrayOfUInt16Be => LogicalArray::UInt16(unpack(bytes, u16::from_be_bytes)?),
+            ArrayOfInt32Le => LogicalArray::Int32(unpack(bytes, i32::from_le_bytes)?),
+            ArrayOfInt32Be => LogicalArray::Int32(unpack(bytes, i32::from_be_bytes)?),
+            ArrayOfUInt32Le => LogicalArray::UInt32(unpack(bytes, u32::from_le_bytes)?),
+            ArrayOfUInt32Be => LogicalArray::UInt32(unpack(bytes, u32::from_be_bytes)?),
+            ArrayOfInt64Le => LogicalArray::Int64(unpack(bytes, i64::from_le_bytes)?),
+            ArrayOfInt64Be => LogicalArray::Int64(unpack(bytes, i64::from_be_bytes)?),
+            ArrayOfUInt64Le => LogicalArray::UInt64(unpack(bytes, u64::from_le_bytes)?),
+            ArrayOfUInt64Be => LogicalArray::UInt64(unpack(bytes, u64::from_be_bytes)?),
+            ArrayOfFloat32Le => LogicalArray::Float32(unpack(bytes, f32::from_le_bytes)?),
+            ArrayOfFloat32Be => LogicalArray::Float32(unpack(bytes, f32::from_be_bytes)?),
+            ArrayOfDouble64Le => LogicalArray::Float64(unpack(bytes, f64::from_le_bytes)?),
+            ArrayOfDouble64Be => LogicalArray::Float64(unpack(bytes, f64::from_be_bytes)?),
+            ArrayOfString | ArrayOfCustom => {
+                return Err(Error::Simple(format!(
+                    "{:?} cannot be decoded from bytes",
+                    logical_type
+                )))
+            }
+        })
+    }
+
+    fn from_integers<V: Copy + Into<i64>>(
+        values: &[V],
+        array: &AnyArrayUnion,
+        logical_type: &AnyLogicalArrayType,
+    ) -> Result<LogicalArray, Error> {
+        use AnyLogicalArrayType::*;
+        Ok(match logical_type {
+            ArrayOfInt8 => LogicalArray::Int8(narrow(values, logical_type)?),
+            ArrayOfUInt8 => LogicalArray::UInt8(narrow(values, logical_type)?),
+            ArrayOfInt16Le | ArrayOfInt16Be => LogicalArray::Int16(narrow(values, logical_type)?),
+            ArrayOfUInt16Le | ArrayOfUInt16Be => {
+                LogicalArray::UInt16(narrow(values, logical_type)?)
+            }
+            ArrayOfInt32Le | ArrayOfInt32Be => LogicalArray::Int32(narrow(values, logical_type)?),
+            ArrayOfUInt32Le | ArrayOfUInt32Be => {
+                LogicalArray::UInt32(narrow(values, logical_type)?)
+            }
+            ArrayOfInt64Le | ArrayOfInt64Be => LogicalArray::Int64(narrow(values, logical_type)?),
+            ArrayOfUInt64Le | ArrayOfUInt64Be => {
+                LogicalArray::UInt64(narrow(values, logical_type)?)
+            }
+            _ => return Err(mismatch(array, logical_type)),
+        })
+    }
+}
+
+impl DataArray {
+    pub fn decode(&self, logical_type: &AnyLogicalArrayType) -> Result<LogicalArray, Error> {
+        LogicalArray::decode_array(&self.data, &self.dimensions, logical_type)
+    }
+}
+
+fn value_count(dimensions: &[i64]) -> Result<usize, Error> {
+    dimensions.iter().try_fold(1usize, |count, d| {
+        usize::try_from(*d)
+            .ok()
+            .and_then(|d| count.checked_mul(d))
+            .ok_or_else(|| Error::Simple(format!("Invalid dimensions {:?}", dimensions)))
+    })
+}
+
+fn mismatch(array: &AnyArrayUnion, logical_type: &AnyLogicalArrayType) -> Error {
+    let transport = match array {
+        AnyArrayUnion::ArrayOfBoolean(_) => "arrayOfBoolean",
+        AnyArrayUnion::ArrayOfInt(_) => "arrayOfInt",
+        AnyArrayUnion::ArrayOfLong(_) => "arrayOfLong",
+        AnyArrayUnion::ArrayOfFloat(_) => "arrayOfFloat",
+        AnyArrayUnion::ArrayOfDouble(_) => "arrayOfDouble",
+        AnyArrayUnion::ArrayOfString(_) => "arrayOfString",
+        AnyArrayUnion::Bytes(_) => "bytes",
+    };
+    Error::Simple(format!(
+        "{:?} cannot be transported as {}",
+        logical_type, transport
+    ))
+}
+
+fn narrow<V: Copy + Into<i64>, T: TryFrom<i64>>(
+    values: &[V],
+    logical_type: &AnyLogicalArrayType,
+) -> Result<Vec<T>, Error> {
+    values
+        .iter()
+        .map(|v| {
+            let v: i64 = (*v).into();
+            T::try_from(v)
+                .map_err(|_| Error::Simple(format!("{} is out of range for {:?}", v, logical_type)))
+        })
+        .collect()
+}
+
+// Splits packed bytes into N byte values.
+fn unpack<const N: usize, T>(
+    bytes: &[u8],
+    convert: impl Fn([u8; N]) -> T,
+) -> Result<Vec<T>, Error> {
+    let chunks = bytes.chunks_exact(N);
+    if !chunks.remainder().is_empty() {
+        return Err(Error::Simple(format!(
+            "{} bytes is not a whole number of {} byte values",
+            bytes.len(),
+            N
+        )));
+    }
+    Ok(chunks
+        .map(|chunk| {
+            let mut value = [0; N];
+            value.copy_from_slice(chunk);
+            convert(value)
+        })
+        .collect())
+}
+
+fn pack<const N: usize, T: Copy>(values: &[T], convert: impl Fn(T) -> [u8; N]) -> Vec<u8> {
+    values.iter().flat_map(|v| convert(*v)).collect()
+}
+
+impl From<Vec<bool>> for LogicalArray {
+    fn from(values: Vec<bool>) -> LogicalArray {
+        LogicalArray::Boolean(values)
+    }
+}
+
+impl From<Vec<i8>> for LogicalArray {
+    fn from(values: Vec<i8>) -> LogicalArray {
+        LogicalArray::Int8(values)
+    }
+}
+
+impl From<Vec<u8>> for LogicalArray {
+    fn from(values: Vec<u8>) -> LogicalArray {
+        LogicalArray::UInt8(values)
+    }
+}
+
+impl From<Vec<i16>> for LogicalArray {
+    fn from(values: Vec<i16>) -> LogicalArray {
+        LogicalArray::Int16(values)
+    }
+}
+
+impl From<Vec<u16>> for LogicalArray {
+    fn from(values: Vec<u16>) -> LogicalArray {
+        LogicalArray::UInt16(values)
+    }
+}
+
+impl From<Vec<i32>> for LogicalArray {
+    fn from(values: Vec<i32>) -> LogicalArray {
+        LogicalArray::Int32(values)
+    }
+}
+
+impl From<Vec<u32>> for LogicalArray {
+    fn from(values: Vec<u32>) -> LogicalArray {
+        LogicalArray::UInt32(values)
+    }
+}
+
+impl From<Vec<i64>> for LogicalArray {
+    fn from(values: Vec<i64>) -> LogicalArray {
+        LogicalArray::Int64(values)
+    }
+}
+
+impl From<Vec<u64>> for LogicalArray {
+    fn from(values: Vec<u64>) -> LogicalArray {
+        LogicalArray::UInt64(values)
+    }
+}
+
+impl From<Vec<f32>> for LogicalArray {
+    fn from(values: Vec<f32>) -> LogicalArray {
+        LogicalArray::Float32(values)
+    }
+}
+
+impl From<Vec<f64>> for LogicalArray {
+    fn from(values: Vec<f64>) -> LogicalArray {
+        LogicalArray::Float64(values)
+    }
+}
+
+impl From<Vec<String>> for LogicalArray {
+    fn from(values: Vec<String>) -> LogicalArray {
+        LogicalArray::String(values)
+    }
+}
+
+// Taking the values out only widens, the same as DataValue: eg. an Int16 array can be read as
+// Vec<i32>, but not as Vec<u16>.
+
+fn conversion_error(array: &LogicalArray, target: &str) -> Error {
+    let name = match array {
+        LogicalArray::Boolean(_) => "boolean",
+        LogicalArray::Int8(_) => "int8",
+        LogicalArray::UInt8(_) => "uint8",
+        LogicalArray::Int16(_) => "int16",
+        LogicalArray::UInt16(_) => "uint16",
+        LogicalArray::Int32(_) => "int32",
+        LogicalArray::UInt32(_) => "uint32",
+        LogicalArray::Int64(_) => "int64",
+        LogicalArray::UInt64(_) => "uint64",
+        LogicalArray::Float32(_) => "float32",
+        LogicalArray::Float64(_) => "float64",
+        LogicalArray::String(_) => "string",
+    };
+    Error::Simple(format!("Cannot convert {} array to {}", name, target))
+}
+
+fn widen<V: Copy + Into<T>, T>(values: Vec<V>) -> Vec<T> {
+    values.into_iter().map(|v| v.into()).collect()
+}
+
+impl TryFrom<LogicalArray> for Vec<bool> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::Boolean(v) => Ok(v),
+            _ => Err(conversion_error(&array, "bool")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<i8> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::Int8(v) => Ok(v),
+            _ => Err(conversion_error(&array, "i8")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<u8> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::UInt8(v) => Ok(v),
+            _ => Err(conversion_error(&array, "u8")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<i16> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::Int8(v) => Ok(widen(v)),
+            LogicalArray::UInt8(v) => Ok(widen(v)),
+            LogicalArray::Int16(v) => Ok(v),
+            _ => Err(conversion_error(&array, "i16")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<u16> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::UInt8(v) => Ok(widen(v)),
+            LogicalArray::UInt16(v) => Ok(v),
+            _ => Err(conversion_error(&array, "u16")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<i32> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::Int8(v) => Ok(widen(v)),
+            LogicalArray::UInt8(v) => Ok(widen(v)),
+            LogicalArray::Int16(v) => Ok(widen(v)),
+            LogicalArray::UInt16(v) => Ok(widen(v)),
+            LogicalArray::Int32(v) => Ok(v),
+            _ => Err(conversion_error(&array, "i32")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<u32> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::UInt8(v) => Ok(widen(v)),
+            LogicalArray::UInt16(v) => Ok(widen(v)),
+            LogicalArray::UInt32(v) => Ok(v),
+            _ => Err(conversion_error(&array, "u32")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<i64> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::Int8(v) => Ok(widen(v)),
+            LogicalArray::UInt8(v) => Ok(widen(v)),
+            LogicalArray::Int16(v) => Ok(widen(v)),
+            LogicalArray::UInt16(v) => Ok(widen(v)),
+            LogicalArray::Int32(v) => Ok(widen(v)),
+            LogicalArray::UInt32(v) => Ok(widen(v)),
+            LogicalArray::Int64(v) => Ok(v),
+            _ => Err(conversion_error(&array, "i64")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<u64> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::UInt8(v) => Ok(widen(v)),
+            LogicalArray::UInt16(v) => Ok(widen(v)),
+            LogicalArray::UInt32(v) => Ok(widen(v)),
+            LogicalArray::UInt64(v) => Ok(v),
+            _ => Err(conversion_error(&array, "u64")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<f32> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::Int8(v) => Ok(widen(v)),
+            LogicalArray::UInt8(v) => Ok(widen(v)),
+            LogicalArray::Int16(v) => Ok(widen(v)),
+            LogicalArray::UInt16(v) => Ok(widen(v)),
+            LogicalArray::Float32(v) => Ok(v),
+            _ => Err(conversion_error(&array, "f32")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<f64> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::Int8(v) => Ok(widen(v)),
+            LogicalArray::UInt8(v) => Ok(widen(v)),
+            LogicalArray::Int16(v) => Ok(widen(v)),
+            LogicalArray::UInt16(v) => Ok(widen(v)),
+            LogicalArray::Int32(v) => Ok(widen(v)),
+            LogicalArray::UInt32(v) => Ok(widen(v)),
+            LogicalArray::Float32(v) => Ok(widen(v)),
+            LogicalArray::Float64(v) => Ok(v),
+            _ => Err(conversion_error(&array, "f64")),
+        }
+    }
+}
+
+impl TryFrom<LogicalArray> for Vec<String> {
+    type Error = Error;
+
+    fn try_from(array: LogicalArray) -> Result<Self, Self::Error> {
+        match array {
+            LogicalArray::String(v) => Ok(v),
+            _ => Err(conversion_error(&array, "String")),
+        }
+    }
+}
+
+#[test]
+fn test_logical_array_codec() {
+    let arrays = vec![
+        LogicalArray::Boolean(vec![true, false, true]),
+        LogicalArray::Int8(vec![-128, 0, 127]),
+        LogicalArray::UInt8(vec![0, 1, 255]),
+        LogicalArray::Int16(vec![i16::MIN, -1, i16::MAX]),
+        LogicalArray::UInt16(vec![0, 258, u16::MAX]),
+        LogicalArray::Int32(vec![i32::MIN, -1, i32::MAX]),
+        LogicalArray::UInt32(vec![0, 1, u32::MAX]),
+        LogicalArray::Int64(vec![i64::MIN, -1, i64::MAX]),
+        LogicalArray::UInt64(vec![0, 1, u64::MAX]),
+        LogicalArray::Float32(vec![-1.5, 0.0, f32::MAX]),
+        LogicalArray::Float64(vec![-1.5, 0.0, f64::MAX]),
+        LogicalArray::String(vec!["a".to_string(), "".to_string(), "c".to_string()]),
+    ];
+    for array in arrays {
+        for order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            let (item, logical_type) = array.encode(order);
+            let data = AnyArray { item };
+            assert_eq!(
+                LogicalArray::decode_array(&data, &[3], &logical_type).unwrap(),
+                array
+            );
+            assert!(LogicalArray::decode_array(&data, &[2, 2], &logical_type).is_err());
+        }
+    }
+
+    // uint16 258 big endian, and little endian
+    let (item, logical_type) = LogicalArray::UInt16(vec![258]).encode(ByteOrder::BigEndian);
+    assert_eq!(item, AnyArrayUnion::Bytes(vec![1, 2]));
+    assert_eq!(logical_type, AnyLogicalArrayType::ArrayOfUInt16Be);
+    assert_eq!(
+        LogicalArray::decode(&item, &AnyLogicalArrayType::ArrayOfUInt16Le).unwrap(),
+        LogicalArray::UInt16(vec![513])
+    );
+
+    // Bytes that are not a whole number of values
+    assert!(LogicalArray::decode(
+        &AnyArrayUnion::Bytes(vec![0, 0, 0]),
+        &AnyLogicalArrayType::ArrayOfInt16Le
+    )
+    .is_err());
+
+    // Narrowing a wider transport, and widening the values
+    let int = AnyArrayUnion::ArrayOfInt(ArrayOfInt {
+        values: vec![0, 65535],
+    });
+    let array = LogicalArray::decode(&int, &AnyLogicalArrayType::ArrayOfUInt16Le).unwrap();
+    assert_eq!(array, LogicalArray::UInt16(vec![0, 65535]));
+    assert_eq!(
+        Vec::<f64>::try_from(array.clone()).unwrap(),
+        vec![0.0, 65535.0]
+    );
+    assert!(Vec::<i16>::try_from(array).is_err());
+    assert!(LogicalArray::decode(&int, &AnyLogicalArrayType::ArrayOfInt8).is_err());
+}
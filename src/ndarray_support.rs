@@ -0,0 +1,144 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Conversion of AnyArray (flat values + dimensions) to and from ndarray ArrayD.  Feature: "ndarray"
+// Values are row-major, as in ETP, and decoded by their logical type (see logical_array).
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error,
+    logical_array::{ByteOrder, LogicalArray},
+    schema_gen::*,
+};
+use ndarray::{ArrayD, IxDyn};
+
+// A Rust type an AnyArray can be read into, and written from: any type LogicalArray converts to and from.
+pub trait ArrayElement: Sized + Clone {
+    fn from_transport(
+        array: &AnyArrayUnion,
+        logical_type: &AnyLogicalArrayType,
+    ) -> Result<Vec<Self>, Error>;
+
+    // The values in the byte order, with the logical type to declare for them
+    fn to_transport(values: Vec<Self>, order: ByteOrder) -> (AnyArrayUnion, AnyLogicalArrayType);
+}
+
+impl<T: Clone> ArrayElement for T
+where
+    LogicalArray: From<Vec<T>>,
+    Vec<T>: TryFrom<LogicalArray, Error = Error>,
+{
+    fn from_transport(
+        array: &AnyArrayUnion,
+        logical_type: &AnyLogicalArrayType,
+    ) -> Result<Vec<T>, Error> {
+        Vec::<T>::try_from(LogicalArray::decode(array, logical_type)?)
+    }
+
+    fn to_transport(values: Vec<T>, order: ByteOrder) -> (AnyArrayUnion, AnyLogicalArrayType) {
+        LogicalArray::from(values).encode(order)
+    }
+}
+
+// Reads an array with its dimensions (eg. from DataArrayMetadata) into an n-dimensional array.
+// The values can be read as any type that holds the logical type without loss (see LogicalArray).
+pub fn to_ndarray<T: ArrayElement>(
+    data: &AnyArray,
+    dimensions: &[i64],
+    logical_type: &AnyLogicalArrayType,
+) -> Result<ArrayD<T>, Error> {
+    let shape = dimensions
+        .iter()
+        .map(|d| usize::try_from(*d).map_err(|_| Error::Simple(format!("Invalid dimension {}", d))))
+        .collect::<Result<Vec<usize>, Error>>()?;
+    let values = T::from_transport(&data.item, logical_type)?;
+
+    ArrayD::from_shape_vec(IxDyn(&shape), values).map_err(|err| {
+        Error::Simple(format!(
+            "Array does not match dimensions {:?}: {}",
+            dimensions, err
+        ))
+    })
+}
+
+// Writes an n-dimensional array as a DataArray in the byte order, with the logical type to declare for it.
+pub fn from_ndarray<T: ArrayElement>(
+    array: &ArrayD<T>,
+    order: ByteOrder,
+) -> (DataArray, AnyLogicalArrayType) {
+    let (item, logical_type) = T::to_transport(array.iter().cloned().collect(), order);
+    (
+        DataArray {
+            dimensions: array.shape().iter().map(|d| *d as i64).collect(),
+            data: AnyArray { item },
+        },
+        logical_type,
+    )
+}
+
+impl DataArray {
+    pub fn to_ndarray<T: ArrayElement>(
+        &self,
+        logical_type: &AnyLogicalArrayType,
+    ) -> Result<ArrayD<T>, Error> {
+        to_ndarray(&self.data, &self.dimensions, logical_type)
+    }
+}
+
+#[test]
+fn test_ndarray_conversions() {
+    // 2 x 3 int16 big endian, packed in bytes
+    let data = AnyArray {
+        item: AnyArrayUnion::Bytes(vec![0, 1, 0, 2, 0, 3, 0, 4, 0, 5, 255, 255]),
+    };
+    let array: ArrayD<i16> =
+        to_ndarray(&data, &[2, 3], &AnyLogicalArrayType::ArrayOfInt16Be).unwrap();
+    assert_eq!(array.shape(), &[2, 3]);
+    assert_eq!(array[[1, 2]], -1);
+    assert_eq!(array[[0, 1]], 2);
+
+    // Widened, wrong shape, and an element type that cannot hold the logical type
+    let wide: ArrayD<f64> =
+        to_ndarray(&data, &[2, 3], &AnyLogicalArrayType::ArrayOfInt16Be).unwrap();
+    assert_eq!(wide[[1, 2]], -1.0);
+    assert!(to_ndarray::<i16>(&data, &[4, 2], &AnyLogicalArrayType::ArrayOfInt16Be).is_err());
+    assert!(to_ndarray::<u16>(&data, &[2, 3], &AnyLogicalArrayType::ArrayOfInt16Be).is_err());
+
+    let doubles =
+        ArrayD::from_shape_vec(IxDyn(&[3, 2]), vec![0.5, 1.5, 2.5, 3.5, 4.5, 5.5]).unwrap();
+    let (data_array, logical_type) = from_ndarray(&doubles, ByteOrder::LittleEndian);
+    assert_eq!(data_array.dimensions, vec![3, 2]);
+    assert_eq!(logical_type, AnyLogicalArrayType::ArrayOfDouble64Le);
+    assert_eq!(
+        data_array.to_ndarray::<f64>(&logical_type).unwrap(),
+        doubles
+    );
+
+    let (data_array, logical_type) = from_ndarray(&doubles, ByteOrder::BigEndian);
+    assert_eq!(data_array.dimensions, vec![3, 2]);
+    assert_eq!(logical_type, AnyLogicalArrayType::ArrayOfDouble64Be);
+    assert_eq!(
+        data_array.to_ndarray::<f64>(&logical_type).unwrap(),
+        doubles
+    );
+}
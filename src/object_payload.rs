@@ -0,0 +1,468 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Energistics data object payloads (DataObject.data).  Feature: "payload"
+// Reads the identifying fields of a WITSML / RESQML / PRODML 2.x object, in XML or Energistics JSON, without a
+// domain model: the object type, uuid, title, schema and object versions, and the data objects it references.
+//   XML:   <witsml:Wellbore xmlns:witsml="http://www.energistics.org/energyml/data/witsmlv2" uuid=".."
+//              schemaVersion="2.0"> <eml:Citation><eml:Title>..</eml:Title>..</eml:Citation>
+//              <witsml:Well><eml:ContentType>application/x-witsml+xml;version=2.0;type=Well</eml:ContentType>
+//              <eml:Title>..</eml:Title><eml:Uuid>..</eml:Uuid></witsml:Well> ..
+//   JSON:  {"$type": "witsml21.Wellbore", "Uuid": "..", "SchemaVersion": "2.1", "Citation": {"Title": ".."},
+//              "Well": {"$type": "eml23.DataObjectReference", "QualifiedType": "witsml21.Well", "Uuid": ".."}, ..}
+// References are found as elements with a Uuid and a ContentType (EML 2.0 / 2.1) or QualifiedType (EML 2.3).
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{error::Error, etp_uri::EtpUri, schema_gen::*};
+#[allow(unused_imports)]
+use log::{info, trace, warn};
+use std::collections::HashMap;
+
+// A DataObjectReference in a payload.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ObjectReference {
+    pub qualified_type: String, // eg. "witsml20.Well"
+    pub uuid: String,
+    pub title: Option<String>,
+    pub version: Option<String>, // Object version, when the reference is to a specific version
+}
+
+impl ObjectReference {
+    pub fn uri(&self, dataspace: &EtpUri) -> Result<EtpUri, Error> {
+        object_uri(dataspace, &self.qualified_type, &self.uuid, &self.version)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ObjectPayload {
+    pub qualified_type: String, // eg. "witsml20.Wellbore"
+    pub uuid: String,
+    pub title: Option<String>,
+    pub schema_version: Option<String>,
+    pub object_version: Option<String>,
+    pub references: Vec<ObjectReference>, // Without duplicates
+}
+
+impl ObjectPayload {
+    // Format as in DataObject.format: "xml" (the default when empty) or "json".
+    pub fn parse(data: &[u8], format: &str) -> Result<ObjectPayload, Error> {
+        match format.to_lowercase().as_str() {
+            "" | "xml" => ObjectPayload::from_xml(data),
+            "json" => ObjectPayload::from_json(data),
+            _ => Err(Error::Simple(format!(
+                "Unsupported payload format {}",
+                format
+            ))),
+        }
+    }
+
+    pub fn from_xml(data: &[u8]) -> Result<ObjectPayload, Error> {
+        let text = std::str::from_utf8(data).map_err(|err| payload_error(&err))?;
+        let doc = roxmltree::Document::parse(text).map_err(|err| payload_error(&err))?;
+        let root = doc.root_element();
+
+        let schema_version = root.attribute("schemaVersion").map(|v| v.to_string());
+        let domain = root
+            .tag_name()
+            .namespace()
+            .and_then(namespace_domain)
+            .ok_or_else(|| {
+                payload_error(&format!(
+                    "{} is not an Energistics object",
+                    root.tag_name().name()
+                ))
+            })?;
+        let domain_version = schema_version
+            .as_deref()
+            .and_then(domain_version)
+            .ok_or_else(|| payload_error(&"No schemaVersion"))?;
+
+        let title =
+            xml_child(root, "Citation").and_then(|citation| xml_child_text(citation, "Title"));
+
+        let mut references = Vec::new();
+        for node in root.descendants().skip(1).filter(|n| n.is_element()) {
+            let Some(uuid) = xml_child_text(node, "Uuid") else {
+                continue;
+            };
+            let qualified_type = match xml_child_text(node, "QualifiedType") {
+                Some(qualified_type) => qualified_type,
+                None => match xml_child_text(node, "ContentType") {
+                    Some(content_type) => content_type_qualified_type(&content_type)?,
+                    None => continue,
+                },
+            };
+            add_reference(
+                &mut references,
+                ObjectReference {
+                    qualified_type,
+                    uuid: normalise_uuid(&uuid)?,
+                    title: xml_child_text(node, "Title"),
+                    version: xml_child_text(node, "VersionString")
+                        .or_else(|| xml_child_text(node, "ObjectVersion")),
+                },
+            );
+        }
+
+        Ok(ObjectPayload {
+            qualified_type: format!("{}{}.{}", domain, domain_version, root.tag_name().name()),
+            uuid: normalise_uuid(
+                root.attribute("uuid")
+                    .or_else(|| root.attribute("UUID"))
+                    .ok_or_else(|| payload_error(&"No uuid"))?,
+            )?,
+            title,
+            schema_version,
+            object_version: root.attribute("objectVersion").map(|v| v.to_string()),
+            references,
+        })
+    }
+
+    pub fn from_json(data: &[u8]) -> Result<ObjectPayload, Error> {
+        let value: serde_json::Value =
+            serde_json::from_slice(data).map_err(|err| payload_error(&err))?;
+        let root = value
+            .as_object()
+            .ok_or_else(|| payload_error(&"Not a JSON object"))?;
+
+        let qualified_type = json_text(root, "$type").ok_or_else(|| payload_error(&"No $type"))?;
+        if !qualified_type.contains('.') {
+            return Err(payload_error(&format!(
+                "$type {} is not a qualified type",
+                qualified_type
+            )));
+        }
+
+        let mut references = Vec::new();
+        for child in root.values() {
+            json_references(child, &mut references)?;
+        }
+
+        Ok(ObjectPayload {
+            qualified_type,
+            uuid: normalise_uuid(
+                &json_text(root, "Uuid").ok_or_else(|| payload_error(&"No uuid"))?,
+            )?,
+            title: json_field(root, "Citation")
+                .and_then(|citation| citation.as_object())
+                .and_then(|citation| json_text(citation, "Title")),
+            schema_version: json_text(root, "SchemaVersion"),
+            object_version: json_text(root, "ObjectVersion"),
+            references,
+        })
+    }
+
+    pub fn uri(&self, dataspace: &EtpUri) -> Result<EtpUri, Error> {
+        object_uri(dataspace, &self.qualified_type, &self.uuid, &None)
+    }
+
+    // A Resource for the object, eg. to put it with its payload.
+    pub fn resource(&self, dataspace: &EtpUri, last_changed: i64) -> Result<Resource, Error> {
+        Ok(Resource {
+            uri: self.uri(dataspace)?.to_string(),
+            alternate_uris: vec![],
+            name: self.title.clone().unwrap_or_else(|| self.uuid.clone()),
+            source_count: None,
+            target_count: Some(self.references.len() as i32),
+            last_changed,
+            store_last_write: last_changed,
+            store_created: last_changed,
+            active_status: ActiveStatusKind::Active,
+            custom_data: HashMap::new(),
+        })
+    }
+
+    // The primary relationships from this object to the ones it references.
+    pub fn edges(&self, dataspace: &EtpUri) -> Result<Vec<Edge>, Error> {
+        let source_uri = self.uri(dataspace)?.to_string();
+        self.references
+            .iter()
+            .map(|reference| {
+                Ok(Edge {
+                    source_uri: source_uri.clone(),
+                    target_uri: reference.uri(dataspace)?.to_string(),
+                    relationship_kind: RelationshipKind::Primary,
+                    custom_data: HashMap::new(),
+                })
+            })
+            .collect()
+    }
+
+    // Checks that a URI addresses this object: same type and uuid.
+    pub fn check_uri(&self, uri: &EtpUri) -> Result<(), Error> {
+        match (uri.qualified_type(), uri.uuid()) {
+            (Some(qualified_type), Some(uuid))
+                if qualified_type.eq_ignore_ascii_case(&self.qualified_type)
+                    && uuid == self.uuid =>
+            {
+                Ok(())
+            }
+            _ => Err(Error::Simple(format!(
+                "Payload is {}({}), not {}",
+                self.qualified_type, self.uuid, uri
+            ))),
+        }
+    }
+}
+
+impl DataObject {
+    pub fn payload(&self) -> Result<ObjectPayload, Error> {
+        ObjectPayload::parse(&self.data, &self.format)
+    }
+
+    // Checks a data object before a put: the payload can be read and matches the resource URI.
+    pub fn validate(&self) -> Result<ObjectPayload, Error> {
+        let payload = self.payload()?;
+        payload.check_uri(&EtpUri::parse(&self.resource.uri)?)?;
+        Ok(payload)
+    }
+}
+
+fn payload_error(err: &dyn std::fmt::Display) -> Error {
+    Error::Simple(format!("Invalid data object payload: {}", err))
+}
+
+fn object_uri(
+    dataspace: &EtpUri,
+    qualified_type: &str,
+    uuid: &str,
+    version: &Option<String>,
+) -> Result<EtpUri, Error> {
+    let mut uri = dataspace.dataspace_uri().object(qualified_type, uuid)?;
+    if let Some(segment) = uri.segments.last_mut() {
+        segment.version = version.clone();
+    }
+    Ok(uri)
+}
+
+fn add_reference(references: &mut Vec<ObjectReference>, reference: ObjectReference) {
+    if !references
+        .iter()
+        .any(|r| r.uuid == reference.uuid && r.qualified_type == reference.qualified_type)
+    {
+        references.push(reference);
+    }
+}
+
+fn normalise_uuid(uuid: &str) -> Result<String, Error> {
+    uuid::Uuid::parse_str(uuid.trim())
+        .map(|uuid| uuid.hyphenated().to_string())
+        .map_err(|_| payload_error(&format!("invalid uuid {}", uuid)))
+}
+
+// eg. "http://www.energistics.org/energyml/data/witsmlv2" -> "witsml", ".../commonv2" -> "eml"
+fn namespace_domain(namespace: &str) -> Option<String> {
+    let name = namespace.trim_end_matches('/').rsplit('/').next()?;
+    let domain = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    let domain = domain.strip_suffix('v').unwrap_or(domain);
+    match domain {
+        "common" => Some("eml".to_string()),
+        "witsml" | "resqml" | "prodml" | "eml" => Some(domain.to_string()),
+        _ => None,
+    }
+}
+
+// eg. "2.0" -> "20", "2.0.1" -> "20", "2.3" -> "23"
+fn domain_version(schema_version: &str) -> Option<String> {
+    let mut parts = schema_version.trim_start_matches('v').split('.');
+    let major = parts.next().filter(|p| p.parse::<u32>().is_ok())?;
+    let minor = parts.next().unwrap_or("0");
+    minor.parse::<u32>().ok()?;
+    Some(format!("{}{}", major, minor))
+}
+
+// eg. "application/x-witsml+xml;version=2.0;type=Well" -> "witsml20.Well".  The type may already be
+// qualified, eg. "application/x-eml+xml;version=2.1;type=eml21.PropertyKind".
+fn content_type_qualified_type(content_type: &str) -> Result<String, Error> {
+    let invalid = || payload_error(&format!("invalid content type {}", content_type));
+    let mut parts = content_type.split(';').map(|part| part.trim());
+    let domain = parts
+        .next()
+        .and_then(|media| media.strip_prefix("application/x-"))
+        .and_then(|media| media.split('+').next())
+        .ok_or_else(invalid)?;
+    let mut version = None;
+    let mut object_type = None;
+    for part in parts {
+        match part.split_once('=') {
+            Some(("version", value)) => version = domain_version(value),
+            Some(("type", value)) => object_type = Some(value),
+            _ => {}
+        }
+    }
+    match (object_type, version) {
+        (Some(object_type), _) if object_type.contains('.') => Ok(object_type.to_string()),
+        (Some(object_type), Some(version)) => Ok(format!("{}{}.{}", domain, version, object_type)),
+        _ => Err(invalid()),
+    }
+}
+
+// Child elements are matched on local name, ignoring case (EML 2.0 uses "UUID").
+fn xml_child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name().eq_ignore_ascii_case(name))
+}
+
+fn xml_child_text(node: roxmltree::Node, name: &str) -> Option<String> {
+    xml_child(node, name)
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+// Keys are matched ignoring case, as some writers use camel case.
+fn json_field<'a>(
+    object: &'a serde_json::Map<String, serde_json::Value>,
+    name: &str,
+) -> Option<&'a serde_json::Value> {
+    object
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+fn json_text(object: &serde_json::Map<String, serde_json::Value>, name: &str) -> Option<String> {
+    json_field(object, name)
+        .and_then(|value| value.as_str())
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+fn json_references(
+    value: &serde_json::Value,
+    references: &mut Vec<ObjectReference>,
+) -> Result<(), Error> {
+    match value {
+        serde_json::Value::Array(values) => {
+            for value in values {
+                json_references(value, references)?;
+            }
+        }
+        serde_json::Value::Object(object) => {
+            if let Some(uuid) = json_text(object, "Uuid") {
+                let qualified_type = match json_text(object, "QualifiedType") {
+                    Some(qualified_type) => Some(qualified_type),
+                    None => json_text(object, "ContentType")
+                        .map(|content_type| content_type_qualified_type(&content_type))
+                        .transpose()?,
+                };
+                if let Some(qualified_type) = qualified_type {
+                    add_reference(
+                        references,
+                        ObjectReference {
+                            qualified_type,
+                            uuid: normalise_uuid(&uuid)?,
+                            title: json_text(object, "Title"),
+                            version: json_text(object, "VersionString")
+                                .or_else(|| json_text(object, "ObjectVersion")),
+                        },
+                    );
+                }
+            }
+            for child in object.values() {
+                json_references(child, references)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[test]
+fn test_object_payload() {
+    let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+        <witsml:Wellbore xmlns:witsml="http://www.energistics.org/energyml/data/witsmlv2"
+            xmlns:eml="http://www.energistics.org/energyml/data/commonv2"
+            uuid="8F2C3D4E-1A2B-4C5D-9E8F-0A1B2C3D4E5F" schemaVersion="2.0">
+          <eml:Citation><eml:Title>Wellbore 01</eml:Title><eml:Originator>test</eml:Originator></eml:Citation>
+          <witsml:Well>
+            <eml:ContentType>application/x-witsml+xml;version=2.0;type=Well</eml:ContentType>
+            <eml:Title>Well 01</eml:Title>
+            <eml:Uuid>1b2c3d4e-5f60-4718-9a2b-3c4d5e6f7a8b</eml:Uuid>
+          </witsml:Well>
+        </witsml:Wellbore>"#;
+
+    let payload = ObjectPayload::parse(xml.as_bytes(), "xml").unwrap();
+    assert_eq!(payload.qualified_type, "witsml20.Wellbore");
+    assert_eq!(payload.uuid, "8f2c3d4e-1a2b-4c5d-9e8f-0a1b2c3d4e5f");
+    assert_eq!(payload.title.as_deref(), Some("Wellbore 01"));
+    assert_eq!(payload.schema_version.as_deref(), Some("2.0"));
+    assert_eq!(
+        payload.references,
+        vec![ObjectReference {
+            qualified_type: "witsml20.Well".to_string(),
+            uuid: "1b2c3d4e-5f60-4718-9a2b-3c4d5e6f7a8b".to_string(),
+            title: Some("Well 01".to_string()),
+            version: None,
+        }]
+    );
+
+    let dataspace = EtpUri::dataspace("demo");
+    let edges = payload.edges(&dataspace).unwrap();
+    assert_eq!(
+        edges[0].source_uri,
+        "eml:///dataspace('demo')/witsml20.Wellbore(8f2c3d4e-1a2b-4c5d-9e8f-0a1b2c3d4e5f)"
+    );
+    assert_eq!(
+        edges[0].target_uri,
+        "eml:///dataspace('demo')/witsml20.Well(1b2c3d4e-5f60-4718-9a2b-3c4d5e6f7a8b)"
+    );
+
+    let json = r#"{
+        "$type": "witsml21.Wellbore",
+        "Uuid": "8f2c3d4e-1a2b-4c5d-9e8f-0a1b2c3d4e5f",
+        "SchemaVersion": "2.1",
+        "ObjectVersion": "3",
+        "Citation": {"$type": "eml23.Citation", "Title": "Wellbore 01"},
+        "Well": {"$type": "eml23.DataObjectReference", "QualifiedType": "witsml21.Well",
+                 "Uuid": "1b2c3d4e-5f60-4718-9a2b-3c4d5e6f7a8b", "Title": "Well 01"}
+    }"#;
+    let payload = ObjectPayload::parse(json.as_bytes(), "json").unwrap();
+    assert_eq!(payload.qualified_type, "witsml21.Wellbore");
+    assert_eq!(payload.object_version.as_deref(), Some("3"));
+    assert_eq!(payload.references[0].qualified_type, "witsml21.Well");
+
+    let resource = payload.resource(&dataspace, 0).unwrap();
+    assert_eq!(resource.name, "Wellbore 01");
+    let mut data_object = DataObject {
+        resource,
+        format: "json".to_string(),
+        blob_id: None,
+        data: json.as_bytes().to_vec(),
+    };
+    assert!(data_object.validate().is_ok());
+    data_object.resource.uri =
+        "eml:///dataspace('demo')/witsml21.Well(8f2c3d4e-1a2b-4c5d-9e8f-0a1b2c3d4e5f)".to_string();
+    assert!(data_object.validate().is_err());
+
+    assert_eq!(
+        content_type_qualified_type(
+            "application/x-resqml+xml;version=2.0;type=obj_Grid2dRepresentation"
+        )
+        .unwrap(),
+        "resqml20.obj_Grid2dRepresentation"
+    );
+}
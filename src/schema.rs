@@ -23,11 +23,15 @@
 //#![allow(dead_code)]
 //#![allow(unused_variables)]
 
+use crate::avro_json::{collect_names, from_json, render, to_json, RenderOptions, SchemaNames};
+use crate::headerflags::*;
 use crate::schema_gen::*;
 use apache_avro::{
     from_avro_datum, from_avro_datum_schemata, from_value, to_avro_datum, to_avro_datum_schemata,
     to_value, types::Value, AvroResult, Error, Schema,
 };
+use flate2::read::GzDecoder;
+use serde_json::Value as JsonValue;
 use std::collections::HashMap;
 use std::io::Read;
 
@@ -41,6 +45,7 @@ pub struct MsgSchema {
     messageid_name: HashMap<(usize, usize), String>,
     any_schema: HashMap<(String, String), usize>,
     messageheader_schema: Schema,
+    names: SchemaNames, // Named types, for the JSON encoding
 }
 
 impl MsgSchema {
@@ -54,8 +59,10 @@ impl MsgSchema {
         let mut messageid_schema: HashMap<(usize, usize), usize> = HashMap::new();
         let mut messageid_name: HashMap<(usize, usize), String> = HashMap::new();
         let mut any_schema: HashMap<(String, String), usize> = HashMap::new();
+        let mut names = SchemaNames::new();
 
         for (pos, schema) in schemata.iter().enumerate() {
+            collect_names(schema, &mut names);
             // Make the lookup table for protocol message Schemas
 
             match schema {
@@ -110,6 +117,7 @@ impl MsgSchema {
             messageid_name: messageid_name,
             messageheader_schema: messageheader_schema,
             any_schema: any_schema,
+            names: names,
         }
     }
 
@@ -187,6 +195,108 @@ impl MsgSchema {
         let record = from_avro_datum(&self.messageheader_schema, header, None)?;
         return from_value::<MessageHeader>(&record);
     }
+
+    // Avro JSON encoding.  Values are put through the binary encoding first, so unions and enums are in
+    // the same form whatever produced them.
+    pub fn serialize_message_json<T: Into<Value>>(
+        &self,
+        message: (usize, usize),
+        value: T,
+    ) -> AvroResult<JsonValue> {
+        let encoded = self.serialize_message(message, value)?;
+        let value = self.deserialize_message(message, &mut encoded.as_slice())?;
+        to_json(&value, self.message_schema(message)?, &self.names)
+    }
+
+    pub fn deserialize_message_json(
+        &self,
+        message: (usize, usize),
+        json: &JsonValue,
+    ) -> AvroResult<Value> {
+        from_json(json, self.message_schema(message)?, &self.names)
+    }
+
+    pub fn deserialize_any_json(
+        &self,
+        key: (String, String),
+        json: &JsonValue,
+    ) -> AvroResult<Value> {
+        match self.any_schema.get(&key) {
+            None => Err(Error::ValidationWithReason(
+                "Can't find root schema for specified record".to_string(),
+            )),
+            Some(v) => from_json(json, &self.parsed_schemata[*v], &self.names),
+        }
+    }
+
+    pub fn serialize_header_json(&self, header: &MessageHeader) -> AvroResult<JsonValue> {
+        to_json(&to_value(header)?, &self.messageheader_schema, &self.names)
+    }
+
+    pub fn deserialize_header_json(&self, json: &JsonValue) -> AvroResult<MessageHeader> {
+        let record = from_json(json, &self.messageheader_schema, &self.names)?;
+        from_value::<MessageHeader>(&record)
+    }
+
+    // A whole binary WebSocket message: header, then the body, which may be compressed.
+    pub fn decode_message(
+        &self,
+        msg: &[u8],
+    ) -> Result<(MessageHeader, Value), crate::error::Error> {
+        let mut msg_bytes = msg;
+        let msg_hdr = self.deserialize_header(&mut msg_bytes)?;
+
+        let mut msg_unzip: Vec<u8> = vec![];
+        if msg_hdr.get_flags().compress {
+            let mut gz = GzDecoder::new(&mut msg_bytes);
+            let _result = gz.read_to_end(&mut msg_unzip);
+            msg_bytes = msg_unzip.as_slice();
+        }
+
+        let msg_value = self.deserialize_message(msg_hdr.msgtype(), &mut msg_bytes)?;
+        Ok((msg_hdr, msg_value))
+    }
+
+    // A whole text WebSocket message: [header, body], or [header, extension, body] when the header has
+    // the extension flag.
+    pub fn decode_message_json(
+        &self,
+        text: &str,
+    ) -> Result<(MessageHeader, Value), crate::error::Error> {
+        let parts: Vec<JsonValue> = serde_json::from_str(text)?;
+        if parts.len() < 2 {
+            return Err(crate::error::Error::UnsupportedWSMessage);
+        }
+        let msg_hdr = self.deserialize_header_json(&parts[0])?;
+        let msg_value =
+            self.deserialize_message_json(msg_hdr.msgtype(), &parts[parts.len() - 1])?;
+        Ok((msg_hdr, msg_value))
+    }
+
+    // Readable JSON of a decoded message, for logs:
+    //   {"message": "Core.RequestSession", "header": {..}, "body": {..}}
+    pub fn render_message(
+        &self,
+        header: &MessageHeader,
+        body: &Value,
+        options: RenderOptions,
+    ) -> AvroResult<JsonValue> {
+        let message = header.msgtype();
+        Ok(serde_json::json!({
+            "message": self.msg_name(message).unwrap_or("Unknown"),
+            "header": to_json(&to_value(header)?, &self.messageheader_schema, &self.names)?,
+            "body": render(body, self.message_schema(message)?, &self.names, options)?,
+        }))
+    }
+
+    fn message_schema(&self, message: (usize, usize)) -> AvroResult<&Schema> {
+        match self.messageid_schema.get(&message) {
+            None => Err(Error::ValidationWithReason(
+                "Can't find root schema for specified message".to_string(),
+            )),
+            Some(v) => Ok(&self.parsed_schemata[*v]),
+        }
+    }
 }
 
 pub enum Role {
@@ -211,6 +321,17 @@ impl Role {
     }
 }
 
+// Encodes and decodes a message body, as it would go over the wire
+#[cfg(test)]
+pub(crate) fn roundtrip<T: serde::Serialize>(message: (usize, usize), body: T) -> Value {
+    let es = MsgSchema::new();
+    let encoded = es
+        .serialize_message(message, to_value(body).unwrap())
+        .unwrap();
+    es.deserialize_message(message, &mut encoded.as_slice())
+        .unwrap()
+}
+
 #[test]
 fn test_roundtrip_message_header() {
     let header = MessageHeader {
@@ -315,6 +436,57 @@ fn test_roundtrip_channeldata() {
     println!("{:?}", pe_back);
 }
 
+#[test]
+fn test_roundtrip_json() {
+    let cs = ChannelData {
+        data: vec![DataItem {
+            channel_id: 1,
+            indexes: vec![IndexValue {
+                item: Some(UnionLongDoublePassIndexedDepth::Double(2.5)),
+            }],
+            value: DataValue {
+                item: DataValueEnum::Double(34.1),
+            },
+            value_attributes: Vec::new(),
+        }],
+    };
+
+    let es = MsgSchema::new();
+
+    let json = es
+        .serialize_message_json(CHANNELSUBSCRIBE_CHANNELDATA, to_value(cs.clone()).unwrap())
+        .unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"data": [{
+            "channelId": 1,
+            "indexes": [{"item": {"double": 2.5}}],
+            "value": {"item": {"double": 34.1}},
+            "valueAttributes": []
+        }]})
+    );
+
+    let back = es
+        .deserialize_message_json(CHANNELSUBSCRIBE_CHANNELDATA, &json)
+        .unwrap();
+    assert_eq!(from_value::<ChannelData>(&back).unwrap(), cs);
+}
+
+#[test]
+fn test_decode_message_json_errors() {
+    use crate::error::Error as EtpError;
+
+    let es = MsgSchema::new();
+    assert!(matches!(
+        es.decode_message_json("[{\"protocol\": 0"),
+        Err(EtpError::JsonError(_))
+    ));
+    assert!(matches!(
+        es.decode_message_json("[{}]"),
+        Err(EtpError::UnsupportedWSMessage)
+    ));
+}
+
 #[test]
 fn test_msg_name() {
     let es = MsgSchema::new();
@@ -365,6 +537,8 @@ pub const ETP11VERSION: Version = Version {
 // Channel Streaming
 pub const CHANNELSTREAMING_CHANNELMETADATA: (usize, usize) = (1, 1);
 pub const CHANNELSTREAMING_CHANNELDATA: (usize, usize) = (1, 2);
+pub const CHANNELSTREAMING_STARTSTREAMING: (usize, usize) = (1, 3);
+pub const CHANNELSTREAMING_STOPSTREAMING: (usize, usize) = (1, 4);
 pub const CHANNELSTREAMING_TRUNCATECHANNELS_CS: (usize, usize) = (1, 5);
 
 // Channel Data Frame
@@ -539,7 +713,7 @@ pub const WITSMLSOAP_WMLS_UPDATEINSTORERESPONSE: (usize, usize) = (2100, 14);
 // ------------------------------------------------------------------------------------------------------------
 static ETP_MESSAGE_HEADER: &str = r##"{"type": "record","namespace": "Energistics.Etp.v12.Datatypes","name": "MessageHeader","fields":[{ "name": "protocol", "type": "int" },{ "name": "messageType", "type": "int" },{ "name": "correlationId", "type": "long" },{ "name": "messageId", "type": "long" },{ "name": "messageFlags", "type": "int" }]}"##;
 
-static ETP_SCHEMA_EMBED: [&str; 207] = [
+static ETP_SCHEMA_EMBED: [&str; 209] = [
     // Kinds
     r##"{"type": "enum","namespace": "Energistics.Etp.v12.Datatypes","name": "Protocol","symbols":["Core","ChannelStreaming","ChannelDataFrame","Discovery","Store","StoreNotification","GrowingObject","GrowingObjectNotification","DEPRECATED_8","DataArray","RESERVED_10","RESERVED_11","RESERVED_12","DiscoveryQuery","StoreQuery","RESERVED_15","GrowingObjectQuery","RESERVED_17","Transaction","RESERVED_19","RESERVED_20","ChannelSubscribe","ChannelDataLoad","RESERVED_23","Dataspace","SupportedTypes"]}"##,
     r##"{"type": "enum","namespace": "Energistics.Etp.v12.Datatypes.Object","name": "ActiveStatusKind","symbols":["Active","Inactive"]}"##,
@@ -641,6 +815,8 @@ static ETP_SCHEMA_EMBED: [&str; 207] = [
     r##"{"type": "record","namespace": "Energistics.Etp.v12.Protocol.ChannelStreaming","name": "TruncateChannels_CS","protocol": "1","messageType": "5","senderRole": "producer","protocolRoles": "producer,consumer","multipartFlag": false,  "fields":[{ "name": "channels","type": { "type": "array", "items": "Energistics.Etp.v12.Datatypes.ChannelData.TruncateInfo" }}]}"##,
     r##"{"type": "record","namespace": "Energistics.Etp.v12.Protocol.ChannelStreaming","name": "ChannelMetadata","protocol": "1","messageType": "1","senderRole": "producer","protocolRoles": "producer,consumer","multipartFlag": false,  "fields":[{ "name": "channels","type": { "type": "array", "items": "Energistics.Etp.v12.Datatypes.ChannelData.ChannelMetadataRecord" }}]}"##,
     r##"{"type": "record","namespace": "Energistics.Etp.v12.Protocol.ChannelStreaming","name": "ChannelData","protocol": "1","messageType": "2","senderRole": "producer","protocolRoles": "producer,consumer","multipartFlag": false,  "fields":[{ "name": "data","type": { "type": "array", "items": "Energistics.Etp.v12.Datatypes.ChannelData.DataItem" }}]}"##,
+    r##"{"type": "record","namespace": "Energistics.Etp.v12.Protocol.ChannelStreaming","name": "StartStreaming","protocol": "1","messageType": "3","senderRole": "consumer","protocolRoles": "producer,consumer","multipartFlag": false,  "fields":[]}"##,
+    r##"{"type": "record","namespace": "Energistics.Etp.v12.Protocol.ChannelStreaming","name": "StopStreaming","protocol": "1","messageType": "4","senderRole": "consumer","protocolRoles": "producer,consumer","multipartFlag": false,  "fields":[]}"##,
     // ------------------------------------------------------
     // 2 - ChannelDataFrame
     // ------------------------------------------------------
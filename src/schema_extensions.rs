@@ -141,3 +141,26 @@ impl Default for OpenSession {
         }
     }
 }
+
+impl OpenSession {
+    // Integer value of a protocol capability the store advertised for a protocol.
+    pub fn protocol_capability(
+        &self,
+        protocol: Protocol,
+        capability: ProtocolCapabilityKind,
+    ) -> Option<i64> {
+        let protocol = protocol as i32;
+        self.supported_protocols
+            .iter()
+            .find(|sp| sp.protocol == protocol)
+            .and_then(|sp| sp.protocol_capabilities.get(&format!("{:?}", capability)))
+            .and_then(|value| i64::try_from(value).ok())
+    }
+
+    // Integer value of an endpoint capability the store advertised.
+    pub fn endpoint_capability(&self, capability: EndpointCapabilityKind) -> Option<i64> {
+        self.endpoint_capabilities
+            .get(&format!("{:?}", capability))
+            .and_then(|value| i64::try_from(value).ok())
+    }
+}
@@ -1236,6 +1236,26 @@ pub struct TruncateChannelsCs {
     pub channels: Vec<TruncateInfo>,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct StartStreaming {}
+
+impl Default for StartStreaming {
+    fn default() -> StartStreaming {
+        StartStreaming {}
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct StopStreaming {}
+
+impl Default for StopStreaming {
+    fn default() -> StopStreaming {
+        StopStreaming {}
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, serde::Deserialize, serde::Serialize)]
 pub struct ChannelMetadata {
     pub channels: Vec<ChannelMetadataRecord>,
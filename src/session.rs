@@ -21,18 +21,45 @@
 // Author: Mark Farnan
 
 use crate::{error::Error, headerflags::*, helpers::time_to_etp, schema::*, schema_gen::*};
-use apache_avro::{to_value, types::Value};
-use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use apache_avro::{from_value, to_value, types::Value};
+use flate2::{write::GzEncoder, Compression};
 #[allow(unused_imports)]
 use log::{info, trace, warn};
 use serde::Serialize;
 
-use std::io::{Read, Write};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
 use std::net::TcpStream;
 use std::time::SystemTime;
 use std::{usize, vec};
 use tungstenite::{stream::*, Message, WebSocket};
 
+// Used when the store does not advertise MaxWebSocketMessagePayloadSize.  Matches what etp_connect requests.
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 16777216;
+// Room left in each message for the header and message framing.
+const MESSAGE_HEADROOM: usize = 1024;
+// Worst case growth of a message body in the JSON encoding: a byte can take six characters ("\u00XX").
+const JSON_EXPANSION: usize = 6;
+
+// Encoding of messages on the WebSocket, chosen when connecting: Avro binary in binary messages, or
+// Avro JSON in text messages, as [header, body].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Encoding {
+    #[default]
+    Binary,
+    Json,
+}
+
+impl Encoding {
+    // As in the etp-encoding header and the $format query of the capabilities endpoint.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Binary => "binary",
+            Encoding::Json => "json",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Session {
     pub ws_conn: WebSocket<MaybeTlsStream<TcpStream>>,
@@ -45,8 +72,40 @@ pub struct Session {
     gzip: bool,            // If GZip is enabled on the connection
     compress_all: bool, // If we want to force compression for all messages, regardless of what the caller to send_message wants in the header
     extension_allowed: bool, // If message Extensions are allowed to this endpoint
+    encoding: Encoding, // Message encoding requested at connection.  JSON messages are never compressed
     pub request_session_msg: RequestSession, // Message sent to request the session, - stored for later reference use (Protocols, etc)
     pub open_session_msg: OpenSession, // Message returned from Request Session - Stored for later use
+    pending: VecDeque<(MessageHeader, Value)>, // Messages read while waiting for a specific response, returned by later reads
+    discarded: HashSet<i64>, // Requests whose remaining responses are dropped as they arrive
+}
+
+// Success and Error maps for a 'map' style request, where each key of the request map is answered
+// either in the positive response message(s), or in the 'errors' map of a ProtocolException.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MapResponse<T> {
+    pub success: HashMap<String, T>,
+    pub errors: HashMap<String, ErrorInfo>,
+}
+
+impl<T> Default for MapResponse<T> {
+    fn default() -> MapResponse<T> {
+        MapResponse {
+            success: HashMap::new(),
+            errors: HashMap::new(),
+        }
+    }
+}
+
+// The error reported by a ProtocolException: its 'error', or else the first of its 'errors'.
+pub fn protocol_exception(msg_hdr: &MessageHeader, msg_body: &Value) -> Error {
+    let pe = match from_value::<ProtocolException>(msg_body) {
+        Ok(pe) => pe,
+        Err(err) => return Error::from(err),
+    };
+    match pe.error.or_else(|| pe.errors.into_values().next()) {
+        Some(error) => Error::ProtocolException(error.code, error.message),
+        None => Error::UnexpectedMessage(msg_hdr.protocol, msg_hdr.message_type),
+    }
 }
 
 impl Session {
@@ -62,8 +121,11 @@ impl Session {
             gzip: false,
             compress_all: true,
             extension_allowed: false,
+            encoding: Encoding::Binary,
             request_session_msg: RequestSession::default(),
             open_session_msg: OpenSession::default(),
+            pending: VecDeque::new(),
+            discarded: HashSet::new(),
         }
     }
 
@@ -82,6 +144,39 @@ impl Session {
         self.version = 12;
     }
 
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    // Must match the etp-encoding the WebSocket was opened with.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    // Processed ETP Schema used by this session, for encoding messages outside of send_message.
+    pub fn schema(&self) -> &MsgSchema {
+        &self.etp_schema
+    }
+
+    // Upper limit on the size of a message body as sent in the session encoding, from what the store
+    // advertised in OpenSession.
+    pub fn max_encoded_size(&self) -> usize {
+        self.open_session_msg
+            .endpoint_capability(EndpointCapabilityKind::MaxWebSocketMessagePayloadSize)
+            .and_then(|size| usize::try_from(size).ok())
+            .unwrap_or(DEFAULT_MAX_MESSAGE_SIZE)
+            .saturating_sub(MESSAGE_HEADROOM)
+    }
+
+    // Upper limit on the Avro binary size of a message body, for batching by binary (or raw byte) size.
+    // Reduced in JSON sessions to allow for the larger text encoding.
+    pub fn max_message_size(&self) -> usize {
+        match self.encoding {
+            Encoding::Binary => self.max_encoded_size(),
+            Encoding::Json => self.max_encoded_size() / JSON_EXPANSION,
+        }
+    }
+
     // Ack is special, as it has no body, just a header.
     pub fn send_ack(&mut self, corr_id: i64) -> Result<(), Error> {
         let hdr = MessageHeader {
@@ -94,8 +189,14 @@ impl Session {
 
         // Make and Send the Message
         self.sent_msg_id = self.sent_msg_id + 2; // Next msgID. Even Client, Odd Server.  Global for Connection.
-        let message = self.etp_schema.serialize_header(&hdr)?;
-        self.ws_conn.write_message(Message::Binary(message))?;
+        let message = match self.encoding {
+            Encoding::Binary => Message::Binary(self.etp_schema.serialize_header(&hdr)?),
+            Encoding::Json => Message::Text(serde_json::to_string(&[
+                self.etp_schema.serialize_header_json(&hdr)?,
+                serde_json::json!({}),
+            ])?),
+        };
+        self.ws_conn.write_message(message)?;
         return Ok(());
     }
 
@@ -121,6 +222,11 @@ impl Session {
             flags.compress = true
         }
 
+        // Text messages are not compressed
+        if self.encoding == Encoding::Json {
+            flags.compress = false
+        }
+
         // Disable compress for messages that must NEVER be compressed
         // ACK, ProtocolException,  or anything in protocol 0
         // .1 = MsgType,  .0 = Protocol
@@ -149,6 +255,15 @@ impl Session {
 
         // Make the Message
         let body_value = to_value(body)?;
+        if self.encoding == Encoding::Json {
+            let message = serde_json::to_string(&[
+                self.etp_schema.serialize_header_json(&hdr)?,
+                self.etp_schema
+                    .serialize_message_json(msgtype, body_value)?,
+            ])?;
+            self.ws_conn.write_message(Message::Text(message))?;
+            return Ok(hdr.message_id);
+        }
         let msg = self.etp_schema.serialize_message(msgtype, body_value)?;
 
         let mut message = self.etp_schema.serialize_header(&hdr)?;
@@ -166,62 +281,150 @@ impl Session {
         return Ok(hdr.message_id);
     }
 
+    // Returns the next message, starting with any that were queued while waiting for a response.
+    pub fn read_message(&mut self) -> Result<(MessageHeader, Value), Error> {
+        match self.pending.pop_front() {
+            Some(msg) => Ok(msg),
+            None => self.read_ws_message(),
+        }
+    }
+
+    // Reads the next message correlated to the request with the given MessageID.
+    // Anything else received in the meantime (notifications, responses to other requests) is queued for read_message.
+    // A ProtocolException with a single 'error' fails the request.  One with an 'errors' map is returned to the caller,
+    // as it only covers some keys of a map request.
+    pub fn read_response(&mut self, request_id: i64) -> Result<(MessageHeader, Value), Error> {
+        let (msg_hdr, msg_body) = self.read_correlated(request_id)?;
+
+        if msg_hdr.msgtype() == CORE_PROTOCOLEXCEPTION {
+            let pe = from_value::<ProtocolException>(&msg_body)?;
+            if let Some(errinfo) = pe.error {
+                return Err(Error::ProtocolException(errinfo.code, errinfo.message));
+            }
+        }
+
+        Ok((msg_hdr, msg_body))
+    }
+
+    // As read_response, but a ProtocolException is returned like any other message, for callers that need
+    // its header (eg. to know if the response is complete).
+    pub fn read_correlated(&mut self, request_id: i64) -> Result<(MessageHeader, Value), Error> {
+        match self
+            .pending
+            .iter()
+            .position(|(hdr, _)| hdr.correlation_id == request_id)
+        {
+            Some(pos) => Ok(self.pending.remove(pos).unwrap()),
+            None => loop {
+                let (hdr, body) = self.read_ws_message()?;
+                if hdr.correlation_id == request_id {
+                    return Ok((hdr, body));
+                }
+                self.pending.push_back((hdr, body));
+            },
+        }
+    }
+
+    // Drops the rest of a multipart response that is no longer wanted: the parts already queued, and any
+    // that arrive later, up to the final one.
+    pub fn discard_response(&mut self, request_id: i64) {
+        let mut finished = false;
+        self.pending.retain(|(hdr, _)| {
+            if hdr.correlation_id != request_id {
+                return true;
+            }
+            finished |= hdr.get_flags().finalmsg;
+            false
+        });
+        if !finished {
+            self.discarded.insert(request_id);
+        }
+    }
+
+    // Collects all parts of a multipart response to a 'map' style request.
+    // 'extract' pulls the success map out of each positive response message of type 'response_type'.
+    pub fn read_map_response<T, F>(
+        &mut self,
+        request_id: i64,
+        response_type: (usize, usize),
+        mut extract: F,
+    ) -> Result<MapResponse<T>, Error>
+    where
+        F: FnMut(&Value) -> Result<HashMap<String, T>, Error>,
+    {
+        let mut result = MapResponse::default();
+        loop {
+            let (msg_hdr, msg_body) = self.read_response(request_id)?;
+            match msg_hdr.msgtype() {
+                CORE_PROTOCOLEXCEPTION => {
+                    let pe = from_value::<ProtocolException>(&msg_body)?;
+                    result.errors.extend(pe.errors);
+                }
+                msgtype if msgtype == response_type => {
+                    result.success.extend(extract(&msg_body)?);
+                }
+                _ => {
+                    return Err(Error::UnexpectedMessage(
+                        msg_hdr.protocol,
+                        msg_hdr.message_type,
+                    ))
+                }
+            }
+
+            if msg_hdr.get_flags().finalmsg {
+                return Ok(result);
+            }
+        }
+    }
+
     // Loop until valid msg to return
     // Handles responding to Ping internally and waits for next message
     // Automatically Responds with 'Ack's to received messages if required.
-    pub fn read_message(&mut self) -> Result<(MessageHeader, Value), Error> {
+    fn read_ws_message(&mut self) -> Result<(MessageHeader, Value), Error> {
         loop {
             let message = self.ws_conn.read_message()?;
-            match message {
-                Message::Binary(msg) => {
-                    let mut msg_bytes = msg.as_slice();
-                    let msg_hdr = self.etp_schema.deserialize_header(&mut msg_bytes)?;
-
-                    self.rcv_msg_id = msg_hdr.message_id; // Store last rcvd ID
-
-                    let mut msg_unzip: Vec<u8> = vec![];
-                    if msg_hdr.get_flags().compress {
-                        let mut gz = GzDecoder::new(&mut msg_bytes);
-                        let _result = gz.read_to_end(&mut msg_unzip);
-                        msg_bytes = msg_unzip.as_slice();
-                        //println!("{:?}", result);
-                    }
+            let (msg_hdr, msg_value) = match message {
+                Message::Binary(msg) => self.etp_schema.decode_message(&msg)?,
+                Message::Text(text) => self.etp_schema.decode_message_json(&text)?,
+                _ => {
+                    // Note: Websocket Ping/Pong already handled inside ws_conn.read_message
+                    return Err(Error::UnsupportedWSMessage);
+                }
+            };
 
-                    let msg_value = self.etp_schema.deserialize_message(
-                        (msg_hdr.protocol as usize, msg_hdr.message_type as usize),
-                        &mut msg_bytes,
-                    )?;
-
-                    // Handle Ping, pong and any other housekeeping.
-                    match msg_hdr.msgtype() {
-                        CORE_PING => {
-                            _ = self.send_message(
-                                Pong {
-                                    current_date_time: time_to_etp(SystemTime::now()),
-                                },
-                                CORE_PONG,
-                                0,
-                                MessageHeaderFlags::default(),
-                                None,
-                            )?
-                        }
+            self.rcv_msg_id = msg_hdr.message_id; // Store last rcvd ID
 
-                        _ => {
-                            // Handle 'Ack Requested'.
-                            if msg_hdr.get_flags().reqack {
-                                self.send_ack(msg_hdr.message_id)?;
-                            }
-                            return Ok((msg_hdr, msg_value));
-                        }
-                    }
+            // Handle Ping, pong and any other housekeeping.
+            match msg_hdr.msgtype() {
+                CORE_PING => {
+                    _ = self.send_message(
+                        Pong {
+                            current_date_time: time_to_etp(SystemTime::now()),
+                        },
+                        CORE_PONG,
+                        0,
+                        MessageHeaderFlags::default(),
+                        None,
+                    )?
                 }
+
                 _ => {
-                    // Note: Websocket Ping/Pong already handled inside ws_conn.read_message
-                    return Err(Error::UnsupportedWSMessage);
+                    // Handle 'Ack Requested'.
+                    if msg_hdr.get_flags().reqack {
+                        self.send_ack(msg_hdr.message_id)?;
+                    }
+                    if self.discarded.contains(&msg_hdr.correlation_id) {
+                        if msg_hdr.get_flags().finalmsg {
+                            self.discarded.remove(&msg_hdr.correlation_id);
+                        }
+                        continue;
+                    }
+                    return Ok((msg_hdr, msg_value));
                 }
             }
         }
     }
+
     // Closes the session.  This is very conservative and dosn't error.
     // Checks if Session is open or not, if it is, sends Close Session
     // If the WS connection is already closed, it just returns (dosn't error)
@@ -247,3 +450,67 @@ impl Session {
         }
     }
 }
+
+// The store end of a session, for tests: messages go over a local socket, with no handshake.
+#[cfg(test)]
+pub(crate) struct TestPeer {
+    ws_conn: WebSocket<TcpStream>,
+    etp_schema: MsgSchema,
+    sent_msg_id: i64,
+}
+
+#[cfg(test)]
+impl TestPeer {
+    pub(crate) fn connect() -> (Session, TestPeer) {
+        use tungstenite::protocol::Role as WsRole;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let session = Session::new(WebSocket::from_raw_socket(
+            MaybeTlsStream::Plain(client),
+            WsRole::Client,
+            None,
+        ));
+        let peer = TestPeer {
+            ws_conn: WebSocket::from_raw_socket(server, WsRole::Server, None),
+            etp_schema: MsgSchema::new(),
+            sent_msg_id: -1,
+        };
+        (session, peer)
+    }
+
+    // Sends an uncompressed message, correlated to a request (or 0).
+    pub(crate) fn send<S: Serialize>(
+        &mut self,
+        body: S,
+        msgtype: (usize, usize),
+        correlation_id: i64,
+        finalmsg: bool,
+    ) {
+        self.sent_msg_id += 2; // Odd for the server
+        let hdr = MessageHeader {
+            protocol: msgtype.0 as i32,
+            message_type: msgtype.1 as i32,
+            correlation_id,
+            message_id: self.sent_msg_id,
+            message_flags: MessageHeaderFlags {
+                finalmsg,
+                compress: false,
+                reqack: false,
+                extension: false,
+            }
+            .as_i32(),
+        };
+        let mut message = self.etp_schema.serialize_header(&hdr).unwrap();
+        message.extend(
+            self.etp_schema
+                .serialize_message(msgtype, to_value(body).unwrap())
+                .unwrap(),
+        );
+        self.ws_conn
+            .write_message(Message::Binary(message))
+            .unwrap();
+    }
+}
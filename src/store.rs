@@ -0,0 +1,215 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Store (Protocol 4) - Customer side.
+// Objects larger than a message are sent, and received, as a DataObject with a blobId and no data, followed
+// by Chunk messages in the same multipart message.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{
+    error::Error,
+    headerflags::*,
+    schema::*,
+    schema_gen::*,
+    session::{MapResponse, Session},
+};
+use apache_avro::from_value;
+#[allow(unused_imports)]
+use log::{info, trace, warn};
+use std::collections::HashMap;
+
+impl Session {
+    // Gets data objects, in "xml" or "json".  Results are keyed by URI.
+    pub fn get_data_objects(
+        &mut self,
+        uris: &[&str],
+        format: &str,
+    ) -> Result<MapResponse<DataObject>, Error> {
+        let msg_id = self.send_message(
+            GetDataObjects {
+                uris: uris
+                    .iter()
+                    .map(|uri| (uri.to_string(), uri.to_string()))
+                    .collect(),
+                format: format.to_string(),
+            },
+            STORE_GETDATAOBJECTS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let mut result: MapResponse<DataObject> = MapResponse::default();
+        loop {
+            let (msg_hdr, msg_body) = self.read_response(msg_id)?;
+            match msg_hdr.msgtype() {
+                STORE_GETDATAOBJECTSRESPONSE => result
+                    .success
+                    .extend(from_value::<GetDataObjectsResponse>(&msg_body)?.data_objects),
+                STORE_CHUNK => {
+                    let chunk = from_value::<Chunk>(&msg_body)?;
+                    match result
+                        .success
+                        .values_mut()
+                        .find(|object| object.blob_id == Some(chunk.blob_id))
+                    {
+                        Some(object) => object.data.extend(chunk.data),
+                        None => warn!("Chunk for unknown blob {:?} skipped", chunk.blob_id),
+                    }
+                }
+                CORE_PROTOCOLEXCEPTION => {
+                    let pe = from_value::<ProtocolException>(&msg_body)?;
+                    result.errors.extend(pe.errors);
+                }
+                _ => {
+                    return Err(Error::UnexpectedMessage(
+                        msg_hdr.protocol,
+                        msg_hdr.message_type,
+                    ))
+                }
+            }
+
+            if msg_hdr.get_flags().finalmsg {
+                for object in result.success.values_mut() {
+                    object.blob_id = None;
+                }
+                return Ok(result);
+            }
+        }
+    }
+
+    // Creates or updates data objects, in as few messages as fit.  Results are keyed by URI.
+    pub fn put_data_objects(
+        &mut self,
+        data_objects: Vec<DataObject>,
+        prune_contained_objects: bool,
+    ) -> Result<MapResponse<PutResponse>, Error> {
+        let max_size = self.max_message_size();
+        let mut result: MapResponse<PutResponse> = MapResponse::default();
+
+        let mut batch: HashMap<String, DataObject> = HashMap::new();
+        let mut batch_size = 0;
+        for data_object in data_objects {
+            if data_object.data.len() > max_size {
+                let response = self.put_chunked(data_object, prune_contained_objects, max_size)?;
+                result.success.extend(response.success);
+                result.errors.extend(response.errors);
+                continue;
+            }
+            if batch_size + data_object.data.len() > max_size && !batch.is_empty() {
+                let response =
+                    self.put_batch(std::mem::take(&mut batch), prune_contained_objects)?;
+                result.success.extend(response.success);
+                result.errors.extend(response.errors);
+                batch_size = 0;
+            }
+            batch_size += data_object.data.len();
+            batch.insert(data_object.resource.uri.clone(), data_object);
+        }
+        if !batch.is_empty() {
+            let response = self.put_batch(batch, prune_contained_objects)?;
+            result.success.extend(response.success);
+            result.errors.extend(response.errors);
+        }
+        Ok(result)
+    }
+
+    fn put_batch(
+        &mut self,
+        data_objects: HashMap<String, DataObject>,
+        prune_contained_objects: bool,
+    ) -> Result<MapResponse<PutResponse>, Error> {
+        let msg_id = self.send_message(
+            PutDataObjects {
+                data_objects,
+                prune_contained_objects,
+            },
+            STORE_PUTDATAOBJECTS,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+        self.read_map_response(msg_id, STORE_PUTDATAOBJECTSRESPONSE, |body| {
+            Ok(from_value::<PutDataObjectsResponse>(body)?.success)
+        })
+    }
+
+    fn put_chunked(
+        &mut self,
+        mut data_object: DataObject,
+        prune_contained_objects: bool,
+        chunk_size: usize,
+    ) -> Result<MapResponse<PutResponse>, Error> {
+        let blob_id = *uuid::Uuid::new_v4().as_bytes();
+        let data = std::mem::take(&mut data_object.data);
+        data_object.blob_id = Some(blob_id);
+
+        let msg_id = self.send_message(
+            PutDataObjects {
+                data_objects: HashMap::from([(data_object.resource.uri.clone(), data_object)]),
+                prune_contained_objects,
+            },
+            STORE_PUTDATAOBJECTS,
+            0,
+            MessageHeaderFlags::not_final(),
+            None,
+        )?;
+
+        let chunks: Vec<&[u8]> = data.chunks(chunk_size).collect();
+        for (pos, chunk) in chunks.iter().enumerate() {
+            let last = pos == chunks.len() - 1;
+            self.send_message(
+                Chunk {
+                    blob_id,
+                    data: chunk.to_vec(),
+                    r#final: last,
+                },
+                STORE_CHUNK,
+                msg_id,
+                if last {
+                    MessageHeaderFlags::default()
+                } else {
+                    MessageHeaderFlags::not_final()
+                },
+                None,
+            )?;
+        }
+
+        self.read_map_response(msg_id, STORE_PUTDATAOBJECTSRESPONSE, |body| {
+            Ok(from_value::<PutDataObjectsResponse>(body)?.success)
+        })
+    }
+}
+
+#[test]
+fn test_roundtrip_chunk() {
+    let chunk = Chunk {
+        blob_id: *uuid::Uuid::new_v4().as_bytes(),
+        data: b"<witsml:Well/>".to_vec(),
+        r#final: true,
+    };
+
+    let decoded = roundtrip(STORE_CHUNK, chunk.clone());
+
+    assert_eq!(from_value::<Chunk>(&decoded).unwrap(), chunk);
+}
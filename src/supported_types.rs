@@ -0,0 +1,210 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Supported Types (Protocol 25) - Customer side.
+// Plus a cache of what the store supports, so requests it would refuse are not sent.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{error::Error, headerflags::*, schema::*, schema_gen::*, session::Session};
+use apache_avro::from_value;
+use std::collections::HashMap;
+
+impl Session {
+    // Data object types found under a URI (eg. a dataspace or a data object), in the given scope.
+    pub fn get_supported_types(
+        &mut self,
+        uri: &str,
+        scope: ContextScopeKind,
+        return_empty_types: bool,
+        count_objects: bool,
+    ) -> Result<Vec<SupportedType>, Error> {
+        let msg_id = self.send_message(
+            GetSupportedTypes {
+                uri: uri.to_string(),
+                scope,
+                return_empty_types,
+                count_objects,
+            },
+            SUPPORTEDTYPES_GETSUPPORTEDTYPES,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        // The list may be split over several response messages.
+        let mut supported_types = vec![];
+        loop {
+            let (msg_hdr, msg_body) = self.read_response(msg_id)?;
+            if msg_hdr.msgtype() != SUPPORTEDTYPES_GETSUPPORTEDTYPESRESPONSE {
+                return Err(Error::UnexpectedMessage(
+                    msg_hdr.protocol,
+                    msg_hdr.message_type,
+                ));
+            }
+            supported_types
+                .extend(from_value::<GetSupportedTypesResponse>(&msg_body)?.supported_types);
+
+            if msg_hdr.get_flags().finalmsg {
+                return Ok(supported_types);
+            }
+        }
+    }
+}
+
+// What the store supports: the data objects it declared in OpenSession, and the types found under URIs.
+#[derive(Debug, Default)]
+pub struct CapabilityCache {
+    data_objects: Vec<SupportedDataObject>,
+    types: HashMap<(String, ContextScopeKind), Vec<SupportedType>>, // By URI and Scope queried
+}
+
+impl CapabilityCache {
+    pub fn new(open_session: &OpenSession) -> CapabilityCache {
+        CapabilityCache {
+            data_objects: open_session.supported_data_objects.clone(),
+            types: HashMap::new(),
+        }
+    }
+
+    // The store's declaration for a qualified type (eg. "witsml20.Well").
+    // An exact declaration is preferred over a wildcard one (eg. "witsml20.*").
+    pub fn data_object(&self, qualified_type: &str) -> Option<&SupportedDataObject> {
+        self.data_objects
+            .iter()
+            .find(|sdo| sdo.qualified_type == qualified_type)
+            .or_else(|| {
+                self.data_objects
+                    .iter()
+                    .find(|sdo| match sdo.qualified_type.strip_suffix('*') {
+                        Some(prefix) => qualified_type.starts_with(prefix),
+                        None => false,
+                    })
+            })
+    }
+
+    // Declared in OpenSession, or found by a supported_types query.
+    pub fn is_supported(&self, qualified_type: &str) -> bool {
+        self.data_object(qualified_type).is_some() || self.is_listed(qualified_type)
+    }
+
+    // If a cached SupportedTypes response lists the type.
+    fn is_listed(&self, qualified_type: &str) -> bool {
+        self.types
+            .values()
+            .flatten()
+            .any(|supported| supported.data_object_type == qualified_type)
+    }
+
+    pub fn capability(
+        &self,
+        qualified_type: &str,
+        capability: DataObjectCapabilityKind,
+    ) -> Option<&DataValue> {
+        self.data_object(qualified_type)?
+            .data_object_capabilities
+            .get(&format!("{:?}", capability))
+    }
+
+    // If the store allows an operation (SupportsGet, SupportsPut or SupportsDelete) on a type.
+    // A supported type without the capability allows it, as that is the ETP default; so does a type the
+    // store did not declare in OpenSession but listed in SupportedTypes.
+    pub fn supports(&self, qualified_type: &str, capability: DataObjectCapabilityKind) -> bool {
+        if !self.is_supported(qualified_type) {
+            return false;
+        }
+        match self.capability(qualified_type, capability) {
+            Some(DataValue {
+                item: DataValueEnum::Boolean(allowed),
+            }) => *allowed,
+            _ => true,
+        }
+    }
+
+    // Types under a URI.  Fetched from the store the first time, then answered from the cache.
+    pub fn supported_types(
+        &mut self,
+        session: &mut Session,
+        uri: &str,
+        scope: ContextScopeKind,
+    ) -> Result<&[SupportedType], Error> {
+        let key = (uri.to_string(), scope);
+        if !self.types.contains_key(&key) {
+            let types = session.get_supported_types(uri, key.1.clone(), true, false)?;
+            self.types.insert(key.clone(), types);
+        }
+        Ok(&self.types[&key])
+    }
+
+    // Forgets the cached types for a URI, eg. after objects have been put or deleted there.
+    pub fn invalidate(&mut self, uri: &str) {
+        self.types.retain(|(cached_uri, _), _| cached_uri != uri);
+    }
+}
+
+#[test]
+fn test_capability_cache() {
+    let open_session = OpenSession {
+        supported_data_objects: vec![
+            SupportedDataObject {
+                qualified_type: "witsml20.Well".to_string(),
+                data_object_capabilities: HashMap::from([(
+                    "SupportsDelete".to_string(),
+                    DataValue {
+                        item: DataValueEnum::Boolean(false),
+                    },
+                )]),
+            },
+            SupportedDataObject {
+                qualified_type: "resqml20.*".to_string(),
+                data_object_capabilities: HashMap::new(),
+            },
+        ],
+        ..OpenSession::default()
+    };
+    let cache = CapabilityCache::new(&open_session);
+
+    assert!(cache.supports("witsml20.Well", DataObjectCapabilityKind::SupportsPut));
+    assert!(!cache.supports("witsml20.Well", DataObjectCapabilityKind::SupportsDelete));
+    assert!(cache.supports(
+        "resqml20.obj_Grid2dRepresentation",
+        DataObjectCapabilityKind::SupportsGet
+    ));
+    assert!(!cache.supports("witsml20.Log", DataObjectCapabilityKind::SupportsGet));
+
+    // Once a SupportedTypes response lists it, the type is supported
+    let mut cache = cache;
+    cache.types.insert(
+        ("eml:///".to_string(), ContextScopeKind::Targets),
+        vec![SupportedType {
+            data_object_type: "witsml20.Log".to_string(),
+            object_count: None,
+            relationship_kind: RelationshipKind::Primary,
+        }],
+    );
+    assert!(cache.is_supported("witsml20.Log"));
+    assert!(cache.supports("witsml20.Log", DataObjectCapabilityKind::SupportsGet));
+    assert!(!cache.supports("witsml20.Well", DataObjectCapabilityKind::SupportsDelete));
+
+    cache.invalidate("eml:///");
+    assert!(!cache.supports("witsml20.Log", DataObjectCapabilityKind::SupportsGet));
+}
@@ -0,0 +1,232 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Transaction (Protocol 18) - Customer side.
+// A Transaction is rolled back when dropped, unless it was committed or rolled back explicitly.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{error::Error, headerflags::*, schema::*, schema_gen::*, session::Session};
+use apache_avro::from_value;
+#[allow(unused_imports)]
+use log::{info, trace, warn};
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+
+// An open transaction.  The session is used through the transaction (it derefs to Session) until it ends.
+pub struct Transaction<'a> {
+    session: &'a mut Session,
+    transaction_uuid: Uuid,
+    started: Instant,
+    timeout: Option<Duration>, // TransactionTimeoutPeriod advertised by the store
+    finished: bool,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn transaction_uuid(&self) -> Uuid {
+        self.transaction_uuid
+    }
+
+    // Time left before the store rolls the transaction back by itself.  None if the store has no timeout.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.timeout
+            .map(|timeout| timeout.saturating_sub(self.started.elapsed()))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    pub fn commit(mut self) -> Result<CommitTransactionResponse, Error> {
+        self.finished = true;
+        if self.is_expired() {
+            return Err(Error::Simple(format!(
+                "Transaction timed out after {:?}",
+                self.timeout.unwrap_or_default()
+            )));
+        }
+
+        let msg_id = self.session.send_message(
+            CommitTransaction {
+                transaction_uuid: self.transaction_uuid,
+            },
+            TRANSACTION_COMMITTRANSACTION,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let (msg_hdr, msg_body) = self.session.read_response(msg_id)?;
+        match msg_hdr.msgtype() {
+            TRANSACTION_COMMITTRANSACTIONRESPONSE => {
+                Ok(from_value::<CommitTransactionResponse>(&msg_body)?)
+            }
+            _ => Err(Error::UnexpectedMessage(
+                msg_hdr.protocol,
+                msg_hdr.message_type,
+            )),
+        }
+    }
+
+    pub fn rollback(mut self) -> Result<RollbackTransactionResponse, Error> {
+        self.finished = true;
+        self.send_rollback()
+    }
+
+    fn send_rollback(&mut self) -> Result<RollbackTransactionResponse, Error> {
+        let msg_id = self.session.send_message(
+            RollbackTransaction {
+                transaction_uuid: self.transaction_uuid,
+            },
+            TRANSACTION_ROLLBACKTRANSACTION,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let (msg_hdr, msg_body) = self.session.read_response(msg_id)?;
+        match msg_hdr.msgtype() {
+            TRANSACTION_ROLLBACKTRANSACTIONRESPONSE => {
+                Ok(from_value::<RollbackTransactionResponse>(&msg_body)?)
+            }
+            _ => Err(Error::UnexpectedMessage(
+                msg_hdr.protocol,
+                msg_hdr.message_type,
+            )),
+        }
+    }
+}
+
+impl<'a> Deref for Transaction<'a> {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        self.session
+    }
+}
+
+impl<'a> DerefMut for Transaction<'a> {
+    fn deref_mut(&mut self) -> &mut Session {
+        self.session
+    }
+}
+
+impl<'a> Drop for Transaction<'a> {
+    fn drop(&mut self) {
+        // Once timed out, the store has already rolled the transaction back.
+        if self.finished || self.is_expired() {
+            return;
+        }
+
+        match self.send_rollback() {
+            Ok(response) if !response.successful => {
+                warn!("Transaction rollback failed: {}", response.failure_reason)
+            }
+            Err(err) => warn!("Transaction rollback failed: {}", err),
+            _ => {}
+        }
+    }
+}
+
+impl Session {
+    // Starts a transaction over the dataspaces (all dataspaces if empty).
+    pub fn begin_transaction(
+        &mut self,
+        read_only: bool,
+        dataspace_uris: &[&str],
+    ) -> Result<Transaction<'_>, Error> {
+        let timeout = transaction_timeout(&self.open_session_msg);
+
+        let msg_id = self.send_message(
+            StartTransaction {
+                read_only,
+                message: String::new(),
+                dataspace_uris: dataspace_uris.iter().map(|uri| uri.to_string()).collect(),
+            },
+            TRANSACTION_STARTTRANSACTION,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+        let started = Instant::now();
+
+        let (msg_hdr, msg_body) = self.read_response(msg_id)?;
+        if msg_hdr.msgtype() != TRANSACTION_STARTTRANSACTIONRESPONSE {
+            return Err(Error::UnexpectedMessage(
+                msg_hdr.protocol,
+                msg_hdr.message_type,
+            ));
+        }
+
+        let response = from_value::<StartTransactionResponse>(&msg_body)?;
+        if !response.successful {
+            return Err(Error::Simple(format!(
+                "Transaction not started: {}",
+                response.failure_reason
+            )));
+        }
+
+        Ok(Transaction {
+            session: self,
+            transaction_uuid: response.transaction_uuid,
+            started,
+            timeout,
+            finished: false,
+        })
+    }
+}
+
+fn transaction_timeout(open_session: &OpenSession) -> Option<Duration> {
+    open_session
+        .protocol_capability(
+            Protocol::Transaction,
+            ProtocolCapabilityKind::TransactionTimeoutPeriod,
+        )
+        .map(|seconds| Duration::from_secs(seconds.max(0) as u64))
+}
+
+#[test]
+fn test_transaction_timeout() {
+    let mut open_session = OpenSession::default();
+    assert_eq!(transaction_timeout(&open_session), None);
+
+    open_session.supported_protocols.push(SupportedProtocol {
+        protocol: Protocol::Transaction as i32,
+        protocol_version: Version {
+            major: 1,
+            minor: 2,
+            revision: 0,
+            patch: 0,
+        },
+        role: "store".to_string(),
+        protocol_capabilities: std::collections::HashMap::from([(
+            "TransactionTimeoutPeriod".to_string(),
+            DataValue {
+                item: DataValueEnum::Long(30),
+            },
+        )]),
+    });
+    assert_eq!(
+        transaction_timeout(&open_session),
+        Some(Duration::from_secs(30))
+    );
+}
@@ -0,0 +1,355 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// Units of measure.  Feature: "uom"
+// The Energistics UOM symbols commonly found in channel and index metadata, and conversion of channel
+// values and index intervals between units of the same quantity.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{channel_index::*, error::Error, schema_gen::*};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Quantity {
+    Dimensionless,
+    Length,
+    Time,
+    Mass,
+    Volume,
+    Pressure,
+    Temperature,
+    PlaneAngle,
+    Velocity,
+    Density,
+    Force,
+    Torque,
+    Frequency,
+    VolumeFlowRate,
+}
+
+// A unit, as a linear conversion to the SI unit of its quantity: si = value * factor + offset.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Unit {
+    pub symbol: &'static str,
+    pub quantity: Quantity,
+    factor: f64,
+    offset: f64,
+}
+
+const fn unit(symbol: &'static str, quantity: Quantity, factor: f64) -> Unit {
+    Unit {
+        symbol,
+        quantity,
+        factor,
+        offset: 0.0,
+    }
+}
+
+const FT: f64 = 0.3048;
+const LBF: f64 = 4.448_221_615_260_5;
+const GAL_US: f64 = 0.003_785_411_784;
+const BBL: f64 = 0.158_987_294_928;
+
+const UNITS: &[Unit] = &[
+    unit("Euc", Quantity::Dimensionless, 1.0),
+    unit("%", Quantity::Dimensionless, 0.01),
+    unit("ppm", Quantity::Dimensionless, 1e-6),
+    unit("m", Quantity::Length, 1.0),
+    unit("km", Quantity::Length, 1000.0),
+    unit("cm", Quantity::Length, 0.01),
+    unit("mm", Quantity::Length, 0.001),
+    unit("ft", Quantity::Length, FT),
+    unit("ftUS", Quantity::Length, 1200.0 / 3937.0),
+    unit("in", Quantity::Length, 0.0254),
+    unit("yd", Quantity::Length, 0.9144),
+    unit("mi", Quantity::Length, 1609.344),
+    unit("s", Quantity::Time, 1.0),
+    unit("ms", Quantity::Time, 0.001),
+    unit("us", Quantity::Time, 1e-6),
+    unit("min", Quantity::Time, 60.0),
+    unit("h", Quantity::Time, 3600.0),
+    unit("d", Quantity::Time, 86400.0),
+    unit("kg", Quantity::Mass, 1.0),
+    unit("g", Quantity::Mass, 0.001),
+    unit("t", Quantity::Mass, 1000.0),
+    unit("lbm", Quantity::Mass, 0.453_592_37),
+    unit("m3", Quantity::Volume, 1.0),
+    unit("cm3", Quantity::Volume, 1e-6),
+    unit("L", Quantity::Volume, 0.001),
+    unit("ft3", Quantity::Volume, FT * FT * FT),
+    unit("galUS", Quantity::Volume, GAL_US),
+    unit("bbl", Quantity::Volume, BBL),
+    unit("Pa", Quantity::Pressure, 1.0),
+    unit("kPa", Quantity::Pressure, 1e3),
+    unit("MPa", Quantity::Pressure, 1e6),
+    unit("bar", Quantity::Pressure, 1e5),
+    unit("mbar", Quantity::Pressure, 100.0),
+    unit("atm", Quantity::Pressure, 101_325.0),
+    unit("psi", Quantity::Pressure, 6_894.757_293_168_361),
+    Unit {
+        symbol: "K",
+        quantity: Quantity::Temperature,
+        factor: 1.0,
+        offset: 0.0,
+    },
+    Unit {
+        symbol: "degC",
+        quantity: Quantity::Temperature,
+        factor: 1.0,
+        offset: 273.15,
+    },
+    Unit {
+        symbol: "degF",
+        quantity: Quantity::Temperature,
+        factor: 5.0 / 9.0,
+        offset: 459.67 * 5.0 / 9.0,
+    },
+    unit("degR", Quantity::Temperature, 5.0 / 9.0),
+    unit("rad", Quantity::PlaneAngle, 1.0),
+    unit("dega", Quantity::PlaneAngle, std::f64::consts::PI / 180.0),
+    unit("m/s", Quantity::Velocity, 1.0),
+    unit("m/min", Quantity::Velocity, 1.0 / 60.0),
+    unit("m/h", Quantity::Velocity, 1.0 / 3600.0),
+    unit("ft/s", Quantity::Velocity, FT),
+    unit("ft/min", Quantity::Velocity, FT / 60.0),
+    unit("ft/h", Quantity::Velocity, FT / 3600.0),
+    unit("kg/m3", Quantity::Density, 1.0),
+    unit("g/cm3", Quantity::Density, 1000.0),
+    unit("lbm/ft3", Quantity::Density, 0.453_592_37 / (FT * FT * FT)),
+    unit("lbm/galUS", Quantity::Density, 0.453_592_37 / GAL_US),
+    unit("N", Quantity::Force, 1.0),
+    unit("kN", Quantity::Force, 1e3),
+    unit("lbf", Quantity::Force, LBF),
+    unit("klbf", Quantity::Force, LBF * 1e3),
+    unit("N.m", Quantity::Torque, 1.0),
+    unit("kN.m", Quantity::Torque, 1e3),
+    unit("ft.lbf", Quantity::Torque, FT * LBF),
+    unit("kft.lbf", Quantity::Torque, FT * LBF * 1e3),
+    unit("Hz", Quantity::Frequency, 1.0),
+    unit("rpm", Quantity::Frequency, 1.0 / 60.0),
+    unit("m3/s", Quantity::VolumeFlowRate, 1.0),
+    unit("m3/h", Quantity::VolumeFlowRate, 1.0 / 3600.0),
+    unit("L/min", Quantity::VolumeFlowRate, 0.001 / 60.0),
+    unit("galUS/min", Quantity::VolumeFlowRate, GAL_US / 60.0),
+    unit("bbl/d", Quantity::VolumeFlowRate, BBL / 86400.0),
+];
+
+// Looks up an Energistics UOM symbol.  Symbols are case sensitive (eg. "mm" and "Mm" differ).
+pub fn parse(symbol: &str) -> Result<Unit, Error> {
+    UNITS
+        .iter()
+        .find(|unit| unit.symbol == symbol.trim())
+        .copied()
+        .ok_or_else(|| Error::UnknownUnit(symbol.to_string()))
+}
+
+// A conversion between two units of the same quantity: to = from * scale + offset.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Conversion {
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Conversion {
+    pub fn new(from: &str, to: &str) -> Result<Conversion, Error> {
+        let (from_unit, to_unit) = (parse(from)?, parse(to)?);
+        if from_unit.quantity != to_unit.quantity {
+            return Err(Error::IncompatibleUnits(from.to_string(), to.to_string()));
+        }
+        Ok(Conversion {
+            scale: from_unit.factor / to_unit.factor,
+            offset: (from_unit.offset - to_unit.offset) / to_unit.factor,
+        })
+    }
+
+    pub fn apply(&self, value: f64) -> f64 {
+        value * self.scale + self.offset
+    }
+
+    // Converts the numbers in a channel value.  Integers become doubles, as converted values are
+    // rarely whole; nullable integer arrays cannot hold them and are an error.
+    pub fn apply_value(&self, value: &DataValue) -> Result<DataValue, Error> {
+        let converted = |values: Vec<f64>| {
+            DataValueEnum::ArrayOfDouble(ArrayOfDouble {
+                values: values.into_iter().map(|v| self.apply(v)).collect(),
+            })
+        };
+        let item = match &value.item {
+            DataValueEnum::Null => DataValueEnum::Null,
+            DataValueEnum::Int(v) => DataValueEnum::Double(self.apply(*v as f64)),
+            DataValueEnum::Long(v) => DataValueEnum::Double(self.apply(*v as f64)),
+            DataValueEnum::Float(v) => DataValueEnum::Float(self.apply(*v as f64) as f32),
+            DataValueEnum::Double(v) => DataValueEnum::Double(self.apply(*v)),
+            DataValueEnum::ArrayOfInt(a) => converted(a.values.iter().map(|v| *v as f64).collect()),
+            DataValueEnum::ArrayOfLong(a) => {
+                converted(a.values.iter().map(|v| *v as f64).collect())
+            }
+            DataValueEnum::ArrayOfFloat(a) => DataValueEnum::ArrayOfFloat(ArrayOfFloat {
+                values: a
+                    .values
+                    .iter()
+                    .map(|v| self.apply(*v as f64) as f32)
+                    .collect(),
+            }),
+            DataValueEnum::ArrayOfDouble(a) => converted(a.values.clone()),
+            _ => {
+                return Err(Error::Simple(format!(
+                    "Cannot convert the units of a {} value",
+                    value.type_name()
+                )))
+            }
+        };
+        Ok(DataValue { item })
+    }
+
+    // Converts an index value.  Long values (eg. scaled depths) are rounded.
+    pub fn apply_index(&self, value: &IndexValue) -> IndexValue {
+        let item = value.item.as_ref().map(|item| match item {
+            UnionLongDoublePassIndexedDepth::Long(v) => {
+                UnionLongDoublePassIndexedDepth::Long(self.apply(*v as f64).round() as i64)
+            }
+            UnionLongDoublePassIndexedDepth::Double(v) => {
+                UnionLongDoublePassIndexedDepth::Double(self.apply(*v))
+            }
+            UnionLongDoublePassIndexedDepth::PassIndexedDepth(v) => {
+                UnionLongDoublePassIndexedDepth::PassIndexedDepth(PassIndexedDepth {
+                    depth: self.apply(v.depth),
+                    ..v.clone()
+                })
+            }
+        });
+        IndexValue { item }
+    }
+}
+
+pub fn convert(value: f64, from: &str, to: &str) -> Result<f64, Error> {
+    Ok(Conversion::new(from, to)?.apply(value))
+}
+
+impl IndexInterval {
+    // The interval in another unit.  An interval without a uom cannot be converted.
+    pub fn to_uom(&self, uom: &str) -> Result<IndexInterval, Error> {
+        let conversion = Conversion::new(&self.uom, uom)?;
+        Ok(IndexInterval {
+            start_index: conversion.apply_index(&self.start_index),
+            end_index: conversion.apply_index(&self.end_index),
+            uom: uom.to_string(),
+            depth_datum: self.depth_datum.clone(),
+        })
+    }
+}
+
+impl ChannelIndex {
+    // DateTime indexes have no uom, and cannot be converted.
+    pub fn to_uom(&self, uom: &str) -> Result<ChannelIndex, Error> {
+        let conversion = Conversion::new(&self.uom, uom)?;
+        let index = match &self.index {
+            Index::DateTime(_) => {
+                return Err(Error::IncompatibleUnits(
+                    "DateTime".to_string(),
+                    uom.to_string(),
+                ))
+            }
+            Index::ElapsedTime(v) => Index::ElapsedTime(conversion.apply(*v as f64).round() as i64),
+            Index::Depth(v) => Index::Depth(conversion.apply(*v)),
+            Index::Scalar(v) => Index::Scalar(conversion.apply(*v)),
+            Index::PassIndexedDepth(v) => Index::PassIndexedDepth(PassIndexedDepth {
+                depth: conversion.apply(v.depth),
+                ..v.clone()
+            }),
+        };
+        Ok(ChannelIndex {
+            index,
+            uom: uom.to_string(),
+            direction: self.direction.clone(),
+        })
+    }
+}
+
+impl ChannelIndexInterval {
+    pub fn to_uom(&self, uom: &str) -> Result<ChannelIndexInterval, Error> {
+        Ok(ChannelIndexInterval::new(
+            self.start.to_uom(uom)?,
+            self.end.to_uom(uom)?,
+        ))
+    }
+}
+
+impl ChannelMetadataRecord {
+    // Conversion of this channel's values to another unit.
+    pub fn conversion_to(&self, uom: &str) -> Result<Conversion, Error> {
+        Conversion::new(&self.uom, uom)
+    }
+}
+
+#[test]
+fn test_uom_conversions() {
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9 * b.abs().max(1.0);
+
+    assert!(close(convert(1000.0, "ft", "m").unwrap(), 304.8));
+    assert!(close(
+        convert(1.0, "psi", "kPa").unwrap(),
+        6.894757293168361
+    ));
+    assert!(close(convert(212.0, "degF", "degC").unwrap(), 100.0));
+    assert!(close(convert(0.0, "degC", "degF").unwrap(), 32.0));
+    assert!(close(convert(60.0, "m/h", "ft/min").unwrap(), 1.0 / 0.3048));
+
+    assert!(matches!(
+        convert(1.0, "m", "psi"),
+        Err(Error::IncompatibleUnits(_, _))
+    ));
+    assert!(matches!(parse("furlong"), Err(Error::UnknownUnit(_))));
+
+    let conversion = Conversion::new("ft", "m").unwrap();
+    let value = conversion
+        .apply_value(&DataValue {
+            item: DataValueEnum::ArrayOfInt(ArrayOfInt {
+                values: vec![0, 10],
+            }),
+        })
+        .unwrap();
+    assert_eq!(Vec::<f64>::try_from(&value).unwrap(), vec![0.0, 3.048]);
+    assert!(conversion
+        .apply_value(&DataValue {
+            item: DataValueEnum::String("x".to_string()),
+        })
+        .is_err());
+
+    let interval = IndexInterval {
+        start_index: IndexValue {
+            item: Some(UnionLongDoublePassIndexedDepth::Double(1000.0)),
+        },
+        end_index: IndexValue {
+            item: Some(UnionLongDoublePassIndexedDepth::Double(2000.0)),
+        },
+        uom: "ft".to_string(),
+        depth_datum: "KB".to_string(),
+    }
+    .to_uom("m")
+    .unwrap();
+    assert_eq!(interval.uom, "m");
+    assert_eq!(
+        interval.end_index.item,
+        Some(UnionLongDoublePassIndexedDepth::Double(609.6))
+    );
+}
@@ -0,0 +1,244 @@
+// Copyright 2023 - The Bardasz Group & etp-rs authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// ETP Schemas from Energistics Organisation are licenced under the Energistics Licence.
+// You may not use those schema's except in compliance with the license.
+// You can find a copy of the License at: schema/ENERGISTICS_LICENCE
+//
+// The following Energistics (c) products were used in the creation of this work: ETP 1.2 Specification.
+//
+// Author: Mark Farnan
+
+// ------------------------------------------------------------------------------------------------------------
+// WITSML SOAP (Private Protocol 2100) - Customer side.
+// The WITSML 1.4.1 Store API (WMLS_*) tunnelled through an ETP session.  Requests and results are
+// the same strings and result codes as the SOAP API.
+// ------------------------------------------------------------------------------------------------------------
+
+use crate::{error::Error, headerflags::*, schema::*, schema_gen::*, session::Session};
+use apache_avro::from_value;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+
+pub const WITSML_SOAP_PROTOCOL: i32 = 2100;
+
+// Result of a WMLS_* call.  Positive result codes are success, negative ones are errors
+// (WMLS_GetBaseMsg gives the text for a code).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WmlsResult {
+    pub result: i32,
+    pub xml_out: String, // XMLout for GetFromStore, CapabilitiesOut for GetCap, otherwise empty
+    pub supp_msg_out: String,
+}
+
+impl WmlsResult {
+    pub fn is_success(&self) -> bool {
+        self.result > 0
+    }
+}
+
+impl SupportedProtocol {
+    // Add to the requested protocols of the RequestSession, to use the WMLS_* calls.
+    pub fn witsml_soap(role: Role) -> SupportedProtocol {
+        SupportedProtocol {
+            protocol: WITSML_SOAP_PROTOCOL,
+            protocol_version: ETP12VERSION,
+            role: role.to_string(),
+            protocol_capabilities: HashMap::new(),
+        }
+    }
+}
+
+impl Session {
+    pub fn wmls_get_from_store(
+        &mut self,
+        wml_type_in: &str,
+        xml_in: &str,
+        options_in: &str,
+        capabilities_in: &str,
+    ) -> Result<WmlsResult, Error> {
+        let response: WmlsGetFromStoreResponse = self.wmls_request(
+            WmlsGetFromStore {
+                wm_ltype_in: wml_type_in.to_string(),
+                xm_lin: xml_in.to_string(),
+                options_in: options_in.to_string(),
+                capabilities_in: capabilities_in.to_string(),
+            },
+            WITSMLSOAP_WMLS_GETFROMSTORE,
+            WITSMLSOAP_WMLS_GETFROMSTORERESPONSE,
+        )?;
+        Ok(WmlsResult {
+            result: response.result,
+            xml_out: response.xm_lout,
+            supp_msg_out: response.supp_msg_out,
+        })
+    }
+
+    pub fn wmls_add_to_store(
+        &mut self,
+        wml_type_in: &str,
+        xml_in: &str,
+        options_in: &str,
+        capabilities_in: &str,
+    ) -> Result<WmlsResult, Error> {
+        let response: WmlsAddToStoreResponse = self.wmls_request(
+            WmlsAddToStore {
+                wm_ltype_in: wml_type_in.to_string(),
+                xm_lin: xml_in.to_string(),
+                options_in: options_in.to_string(),
+                capabilities_in: capabilities_in.to_string(),
+            },
+            WITSMLSOAP_WMLS_ADDTOSTORE,
+            WITSMLSOAP_WMLS_ADDTOSTORERESPONSE,
+        )?;
+        Ok(WmlsResult {
+            result: response.result,
+            xml_out: String::new(),
+            supp_msg_out: response.supp_msg_out,
+        })
+    }
+
+    pub fn wmls_update_in_store(
+        &mut self,
+        wml_type_in: &str,
+        xml_in: &str,
+        options_in: &str,
+        capabilities_in: &str,
+    ) -> Result<WmlsResult, Error> {
+        let response: WmlsUpdateInStoreResponse = self.wmls_request(
+            WmlsUpdateInStore {
+                wm_ltype_in: wml_type_in.to_string(),
+                xm_lin: xml_in.to_string(),
+                options_in: options_in.to_string(),
+                capabilities_in: capabilities_in.to_string(),
+            },
+            WITSMLSOAP_WMLS_UPDATEINSTORE,
+            WITSMLSOAP_WMLS_UPDATEINSTORERESPONSE,
+        )?;
+        Ok(WmlsResult {
+            result: response.result,
+            xml_out: String::new(),
+            supp_msg_out: response.supp_msg_out,
+        })
+    }
+
+    pub fn wmls_delete_from_store(
+        &mut self,
+        wml_type_in: &str,
+        xml_in: &str,
+        options_in: &str,
+        capabilities_in: &str,
+    ) -> Result<WmlsResult, Error> {
+        let response: WmlsDeleteFromStoreResponse = self.wmls_request(
+            WmlsDeleteFromStore {
+                wm_ltype_in: wml_type_in.to_string(),
+                xm_lin: xml_in.to_string(),
+                options_in: options_in.to_string(),
+                capabilities_in: capabilities_in.to_string(),
+            },
+            WITSMLSOAP_WMLS_DELETEFROMSTORE,
+            WITSMLSOAP_WMLS_DELETEFROMSTORERESPONSE,
+        )?;
+        Ok(WmlsResult {
+            result: response.result,
+            xml_out: String::new(),
+            supp_msg_out: response.supp_msg_out,
+        })
+    }
+
+    pub fn wmls_get_cap(&mut self, options_in: &str) -> Result<WmlsResult, Error> {
+        let response: WmlsGetCapResponse = self.wmls_request(
+            WmlsGetCap {
+                options_in: options_in.to_string(),
+            },
+            WITSMLSOAP_WMLS_GETCAP,
+            WITSMLSOAP_WMLS_GETCAPRESPONSE,
+        )?;
+        Ok(WmlsResult {
+            result: response.result,
+            xml_out: response.capabilities_out,
+            supp_msg_out: response.supp_msg_out,
+        })
+    }
+
+    // Comma separated list of the data schema versions the store supports.
+    pub fn wmls_get_version(&mut self) -> Result<String, Error> {
+        let response: WmlsGetVersionResponse = self.wmls_request(
+            WmlsGetVersion {},
+            WITSMLSOAP_WMLS_GETVERSION,
+            WITSMLSOAP_WMLS_GETVERSIONRESPONSE,
+        )?;
+        Ok(response.result)
+    }
+
+    // Text of a WITSML result code.
+    pub fn wmls_get_base_msg(&mut self, return_value_in: i32) -> Result<String, Error> {
+        let response: WmlsGetBaseMsgResponse = self.wmls_request(
+            WmlsGetBaseMsg { return_value_in },
+            WITSMLSOAP_WMLS_GETBASEMSG,
+            WITSMLSOAP_WMLS_GETBASEMSGRESPONSE,
+        )?;
+        Ok(response.result)
+    }
+
+    fn wmls_request<T, R>(
+        &mut self,
+        request: T,
+        request_type: (usize, usize),
+        response_type: (usize, usize),
+    ) -> Result<R, Error>
+    where
+        T: Serialize,
+        R: DeserializeOwned,
+    {
+        let msg_id = self.send_message(
+            request,
+            request_type,
+            0,
+            MessageHeaderFlags::default(),
+            None,
+        )?;
+
+        let (msg_hdr, msg_body) = self.read_response(msg_id)?;
+        if msg_hdr.msgtype() != response_type {
+            return Err(Error::UnexpectedMessage(
+                msg_hdr.protocol,
+                msg_hdr.message_type,
+            ));
+        }
+        Ok(from_value::<R>(&msg_body)?)
+    }
+}
+
+#[test]
+fn test_roundtrip_wmls_get_from_store() {
+    let es = MsgSchema::new();
+
+    let response = WmlsGetFromStoreResponse {
+        result: 1,
+        xm_lout: "<wells version=\"1.4.1.1\"/>".to_string(),
+        supp_msg_out: "".to_string(),
+    };
+
+    let decoded = roundtrip(WITSMLSOAP_WMLS_GETFROMSTORERESPONSE, response.clone());
+
+    assert_eq!(
+        from_value::<WmlsGetFromStoreResponse>(&decoded).unwrap(),
+        response
+    );
+    assert_eq!(
+        es.msg_name(WITSMLSOAP_WMLS_GETFROMSTORE).unwrap(),
+        "WitsmlSoap.WMLS_GetFromStore"
+    );
+}